@@ -0,0 +1,590 @@
+//! Debug Adapter Protocol server for Polar policies
+//!
+//! The adapter speaks the [Debug Adapter Protocol](https://microsoft.github.io/debug-adapter-protocol/)
+//! to an editor, loads policy files into a local [`oso::Oso`] instance, and runs a query
+//! under the Polar debugger. Protocol requests are translated into debugger commands
+//! (`break`, `step`, `stack`, `bindings`, ...), and the debugger's output is translated
+//! back into protocol responses and events.
+//!
+//! Supported requests are `initialize`, `launch`, `setBreakpoints` (including
+//! conditional breakpoints), `configurationDone`, `threads`, `stackTrace`, `scopes`,
+//! `variables`, `evaluate`, `continue`, `next`, `stepIn`, `stepOut` and `disconnect`.
+//!
+//! A launch configuration looks like:
+//!
+//! ```json
+//! {
+//!     "type": "polar",
+//!     "request": "launch",
+//!     "policies": ["${workspaceFolder}/policy.polar"],
+//!     "query": "allow(\"alice\", \"read\", \"document\")",
+//!     "stopOnEntry": false
+//! }
+//! ```
+
+pub mod output;
+pub mod protocol;
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use oso::Oso;
+use oso_protocol::{read_message, write_message};
+use polar_core::formatting::to_polar::ToPolarString;
+
+use protocol::{OutgoingMessage, Request};
+
+/// The only thread reported to the client; queries are single threaded.
+const THREAD_ID: i64 = 1;
+
+/// Variables reference for the bindings of the current query.
+const BINDINGS_REFERENCE: i64 = 1;
+
+/// Arguments of the `launch` request.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LaunchArguments {
+    /// Policy files to load.
+    #[serde(default)]
+    policies: Vec<String>,
+    /// A single policy file to load, for launch configurations that use `program`.
+    program: Option<String>,
+    /// The query to debug.
+    query: String,
+    /// Pause before the first query.
+    #[serde(default)]
+    stop_on_entry: bool,
+}
+
+#[derive(Debug, Clone)]
+struct SourceBreakpoint {
+    line: u64,
+    condition: Option<String>,
+}
+
+/// Client requests that are answered with the output of a debugger command.
+#[derive(Debug, Clone, Copy)]
+enum ResponseKind {
+    StackTrace,
+    Variables,
+    Evaluate,
+}
+
+/// What the next debugger message is in reply to.
+#[derive(Debug)]
+enum Awaiting {
+    /// A command the adapter sent on its own behalf, e.g. to update breakpoints.
+    Silent,
+    /// A command sent to answer a client request.
+    Response(Request, ResponseKind),
+}
+
+struct Connection {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    seq: i64,
+}
+
+impl Connection {
+    fn next_request(&mut self) -> io::Result<Option<Request>> {
+        read_message(self.input.as_mut())
+    }
+
+    fn next_seq(&mut self) -> i64 {
+        self.seq += 1;
+        self.seq
+    }
+
+    fn respond(&mut self, request: &Request, body: Value) -> io::Result<()> {
+        let message = OutgoingMessage::Response {
+            seq: self.next_seq(),
+            request_seq: request.seq,
+            success: true,
+            command: request.command.clone(),
+            message: None,
+            body,
+        };
+        write_message(self.output.as_mut(), &message)
+    }
+
+    fn respond_error(&mut self, request: &Request, error: &str) -> io::Result<()> {
+        let message = OutgoingMessage::Response {
+            seq: self.next_seq(),
+            request_seq: request.seq,
+            success: false,
+            command: request.command.clone(),
+            message: Some(error.to_string()),
+            body: Value::Null,
+        };
+        write_message(self.output.as_mut(), &message)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> io::Result<()> {
+        let message = OutgoingMessage::Event {
+            seq: self.next_seq(),
+            event: event.to_string(),
+            body,
+        };
+        write_message(self.output.as_mut(), &message)
+    }
+
+    fn output(&mut self, category: &str, output: &str) -> io::Result<()> {
+        self.event(
+            "output",
+            json!({ "category": category, "output": format!("{}\n", output) }),
+        )
+    }
+}
+
+/// State shared between the adapter and the debug handler of the running query.
+struct Session {
+    connection: Connection,
+    /// Breakpoints by source path.
+    breakpoints: BTreeMap<String, Vec<SourceBreakpoint>>,
+    /// Debugger commands to send before reading more requests.
+    pending: VecDeque<String>,
+    awaiting: Option<Awaiting>,
+    /// Number of debugger messages in reply to the commands that set up the query.
+    setup_messages: usize,
+    stop_on_entry: bool,
+    /// Set when the client disconnects; the query then runs to completion without stopping.
+    terminated: bool,
+    /// I/O error raised inside the debug handler, which can't return errors.
+    error: Option<io::Error>,
+}
+
+impl Session {
+    fn breakpoint_commands(&self) -> Vec<String> {
+        self.breakpoints
+            .iter()
+            .flat_map(|(path, breakpoints)| {
+                breakpoints.iter().map(move |breakpoint| {
+                    let mut command = format!("break {}:{}", path, breakpoint.line);
+                    if let Some(condition) = &breakpoint.condition {
+                        command.push_str(" if ");
+                        command.push_str(condition);
+                    }
+                    command
+                })
+            })
+            .collect()
+    }
+
+    fn set_breakpoints(&mut self, request: &Request) -> io::Result<()> {
+        let path = match request.arguments["source"]["path"].as_str() {
+            Some(path) => path.to_string(),
+            None => {
+                return self
+                    .connection
+                    .respond_error(request, "setBreakpoints requires a source path")
+            }
+        };
+        let breakpoints: Vec<SourceBreakpoint> = request.arguments["breakpoints"]
+            .as_array()
+            .map(|breakpoints| {
+                breakpoints
+                    .iter()
+                    .filter_map(|breakpoint| {
+                        Some(SourceBreakpoint {
+                            line: breakpoint["line"].as_u64()?,
+                            condition: breakpoint["condition"]
+                                .as_str()
+                                .filter(|condition| !condition.trim().is_empty())
+                                .map(str::to_string),
+                        })
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        let body = json!({
+            "breakpoints": breakpoints
+                .iter()
+                .map(|breakpoint| json!({ "verified": true, "line": breakpoint.line }))
+                .collect::<Vec<_>>()
+        });
+        if breakpoints.is_empty() {
+            self.breakpoints.remove(&path);
+        } else {
+            self.breakpoints.insert(path, breakpoints);
+        }
+        self.connection.respond(request, body)
+    }
+
+    fn terminate(&mut self) {
+        self.terminated = true;
+        self.pending = vec!["delete".to_string(), "continue".to_string()].into();
+    }
+
+    /// Debug handler for the running query: translate a debugger message into
+    /// protocol messages, and return the next debugger command.
+    fn on_debug(&mut self, message: &str) -> String {
+        if self.terminated {
+            return self
+                .pending
+                .pop_front()
+                .unwrap_or_else(|| "continue".to_string());
+        }
+        if self.setup_messages > 0 {
+            self.setup_messages -= 1;
+            let command = if self.setup_messages == 0 && self.stop_on_entry {
+                "step"
+            } else {
+                "continue"
+            };
+            return command.to_string();
+        }
+        let result = match self.awaiting.take() {
+            Some(Awaiting::Silent) => Ok(()),
+            Some(Awaiting::Response(request, kind)) => {
+                self.respond_with_output(&request, kind, message)
+            }
+            None => self.stopped(message),
+        }
+        .and_then(|_| self.next_command());
+        match result {
+            Ok(command) => command,
+            Err(e) => {
+                self.error = Some(e);
+                self.terminate();
+                self.pending.pop_front().unwrap()
+            }
+        }
+    }
+
+    fn stopped(&mut self, message: &str) -> io::Result<()> {
+        let reason = if message.starts_with("Breakpoint") {
+            "breakpoint"
+        } else if message.starts_with("Watch") {
+            "data breakpoint"
+        } else {
+            "step"
+        };
+        self.connection.output("console", message.trim_end())?;
+        self.connection.event(
+            "stopped",
+            json!({
+                "reason": reason,
+                "description": message.lines().next().unwrap_or_default(),
+                "threadId": THREAD_ID,
+                "allThreadsStopped": true,
+            }),
+        )
+    }
+
+    fn respond_with_output(
+        &mut self,
+        request: &Request,
+        kind: ResponseKind,
+        output: &str,
+    ) -> io::Result<()> {
+        let body = match kind {
+            ResponseKind::StackTrace => {
+                // Number frames from the innermost one, which has the bindings scope.
+                let frames = output::parse_stack(output)
+                    .into_iter()
+                    .enumerate()
+                    .map(|(id, frame)| {
+                        let source = frame.file.as_ref().map(|file| {
+                            let name = Path::new(file)
+                                .file_name()
+                                .map(|name| name.to_string_lossy().into_owned());
+                            json!({ "name": name, "path": file })
+                        });
+                        json!({
+                            "id": id,
+                            "name": frame.name,
+                            "source": source,
+                            "line": frame.line,
+                            "column": frame.column,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                json!({ "totalFrames": frames.len(), "stackFrames": frames })
+            }
+            ResponseKind::Variables => {
+                let variables = output::parse_bindings(output)
+                    .into_iter()
+                    .map(|(name, value)| {
+                        json!({ "name": name, "value": value, "variablesReference": 0 })
+                    })
+                    .collect::<Vec<_>>();
+                json!({ "variables": variables })
+            }
+            ResponseKind::Evaluate => json!({ "result": output, "variablesReference": 0 }),
+        };
+        self.connection.respond(request, body)
+    }
+
+    /// Send pending commands, or read requests until one of them needs a debugger command.
+    fn next_command(&mut self) -> io::Result<String> {
+        loop {
+            if let Some(command) = self.pending.pop_front() {
+                self.awaiting = Some(Awaiting::Silent);
+                return Ok(command);
+            }
+            match self.connection.next_request()? {
+                Some(request) => {
+                    if let Some(command) = self.handle_request(request)? {
+                        return Ok(command);
+                    }
+                }
+                None => self.terminate(),
+            }
+        }
+    }
+
+    /// Handle a request while the query is paused. Return the debugger
+    /// command to send, if any.
+    fn handle_request(&mut self, request: Request) -> io::Result<Option<String>> {
+        let command = match request.command.as_str() {
+            "threads" => {
+                self.connection.respond(
+                    &request,
+                    json!({ "threads": [{ "id": THREAD_ID, "name": "query" }] }),
+                )?;
+                return Ok(None);
+            }
+            "stackTrace" => {
+                self.awaiting = Some(Awaiting::Response(request, ResponseKind::StackTrace));
+                "stack".to_string()
+            }
+            "scopes" => {
+                // Only the bindings of the innermost query are available.
+                let scopes = if request.arguments["frameId"].as_i64() == Some(0) {
+                    json!([{
+                        "name": "Bindings",
+                        "variablesReference": BINDINGS_REFERENCE,
+                        "expensive": false,
+                    }])
+                } else {
+                    json!([])
+                };
+                self.connection
+                    .respond(&request, json!({ "scopes": scopes }))?;
+                return Ok(None);
+            }
+            "variables" => {
+                if request.arguments["variablesReference"].as_i64() != Some(BINDINGS_REFERENCE) {
+                    self.connection
+                        .respond(&request, json!({ "variables": [] }))?;
+                    return Ok(None);
+                }
+                self.awaiting = Some(Awaiting::Response(request, ResponseKind::Variables));
+                "bindings".to_string()
+            }
+            "evaluate" => {
+                let expression = request.arguments["expression"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string();
+                // The debug console sends raw debugger commands, everything else
+                // (hovers, watches) looks up variables.
+                let command = if request.arguments["context"].as_str() == Some("repl") {
+                    expression
+                } else {
+                    format!("var {}", expression)
+                };
+                self.awaiting = Some(Awaiting::Response(request, ResponseKind::Evaluate));
+                command
+            }
+            "setBreakpoints" => {
+                self.set_breakpoints(&request)?;
+                self.pending.push_back("delete".to_string());
+                self.pending.extend(self.breakpoint_commands());
+                return Ok(None);
+            }
+            "continue" => {
+                self.connection
+                    .respond(&request, json!({ "allThreadsContinued": true }))?;
+                "continue".to_string()
+            }
+            "next" | "stepIn" | "stepOut" => {
+                self.connection.respond(&request, Value::Null)?;
+                match request.command.as_str() {
+                    "next" => "over",
+                    "stepIn" => "step",
+                    _ => "out",
+                }
+                .to_string()
+            }
+            "disconnect" | "terminate" => {
+                self.connection.respond(&request, Value::Null)?;
+                self.terminate();
+                return Ok(None);
+            }
+            command => {
+                self.connection
+                    .respond_error(&request, &format!("unsupported request: {}", command))?;
+                return Ok(None);
+            }
+        };
+        Ok(Some(command))
+    }
+}
+
+/// A debug adapter session over a pair of streams, usually stdin and stdout.
+pub struct DebugAdapter {
+    session: Rc<RefCell<Session>>,
+}
+
+impl DebugAdapter {
+    pub fn new(input: impl BufRead + 'static, output: impl Write + 'static) -> Self {
+        Self {
+            session: Rc::new(RefCell::new(Session {
+                connection: Connection {
+                    input: Box::new(input),
+                    output: Box::new(output),
+                    seq: 0,
+                },
+                breakpoints: BTreeMap::new(),
+                pending: VecDeque::new(),
+                awaiting: None,
+                setup_messages: 0,
+                stop_on_entry: false,
+                terminated: false,
+                error: None,
+            })),
+        }
+    }
+
+    /// Serve requests until the client disconnects or closes the input.
+    pub fn run(&mut self) -> io::Result<()> {
+        let mut launch = None;
+        let mut configured = false;
+        let mut launched = false;
+        loop {
+            let request = match self.connection(|c| c.next_request())? {
+                Some(request) => request,
+                None => return Ok(()),
+            };
+            match request.command.as_str() {
+                "initialize" => {
+                    self.connection(|c| {
+                        c.respond(
+                            &request,
+                            json!({
+                                "supportsConfigurationDoneRequest": true,
+                                "supportsConditionalBreakpoints": true,
+                                "supportsEvaluateForHovers": true,
+                            }),
+                        )?;
+                        c.event("initialized", Value::Null)
+                    })?;
+                }
+                "launch" => match LaunchArguments::deserialize(&request.arguments) {
+                    Ok(arguments) => {
+                        launch = Some(arguments);
+                        self.connection(|c| c.respond(&request, Value::Null))?;
+                    }
+                    Err(e) => {
+                        let error = format!("invalid launch arguments: {}", e);
+                        self.connection(|c| c.respond_error(&request, &error))?;
+                    }
+                },
+                "setBreakpoints" => self.session.borrow_mut().set_breakpoints(&request)?,
+                "configurationDone" => {
+                    configured = true;
+                    self.connection(|c| c.respond(&request, Value::Null))?;
+                }
+                "threads" => self.connection(|c| {
+                    c.respond(
+                        &request,
+                        json!({ "threads": [{ "id": THREAD_ID, "name": "query" }] }),
+                    )
+                })?,
+                "disconnect" | "terminate" => {
+                    return self.connection(|c| c.respond(&request, Value::Null));
+                }
+                command => {
+                    let error = format!("unsupported request: {}", command);
+                    self.connection(|c| c.respond_error(&request, &error))?;
+                }
+            }
+
+            if let (Some(arguments), true, false) = (&launch, configured, launched) {
+                launched = true;
+                self.run_query(arguments)?;
+                self.connection(|c| {
+                    c.event("terminated", Value::Null)?;
+                    c.event("exited", json!({ "exitCode": 0 }))
+                })?;
+            }
+        }
+    }
+
+    fn connection<T, F>(&self, f: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut Connection) -> io::Result<T>,
+    {
+        f(&mut self.session.borrow_mut().connection)
+    }
+
+    /// Load the policies and run the query, reporting results as output events.
+    fn run_query(&mut self, arguments: &LaunchArguments) -> io::Result<()> {
+        let mut oso = Oso::new();
+        for policy in arguments.policies.iter().chain(arguments.program.iter()) {
+            if let Err(e) = oso.load_file(policy) {
+                let error = format!("Failed to load {}: {}", policy, e);
+                return self.connection(|c| c.output("stderr", &error));
+            }
+        }
+        let mut query = match oso.query(&arguments.query) {
+            Ok(query) => query,
+            Err(e) => return self.connection(|c| c.output("stderr", &e.to_string())),
+        };
+
+        let session = self.session.clone();
+        query.set_debugger(move |message: &str| session.borrow_mut().on_debug(message));
+
+        let commands = {
+            let mut session = self.session.borrow_mut();
+            let commands = session.breakpoint_commands();
+            session.setup_messages = commands.len();
+            session.stop_on_entry = arguments.stop_on_entry;
+            commands
+        };
+        let mut setup = commands
+            .iter()
+            .try_for_each(|command| query.debug_command(command));
+        if commands.is_empty() && arguments.stop_on_entry {
+            setup = setup.and_then(|_| query.debug_command("step"));
+        }
+        if let Err(e) = setup {
+            return self.connection(|c| c.output("stderr", &e.to_string()));
+        }
+
+        let mut has_result = false;
+        for result in &mut query {
+            if let Some(e) = self.session.borrow_mut().error.take() {
+                return Err(e);
+            }
+            let output = match result {
+                Ok(result) if result.is_empty() => "true".to_string(),
+                Ok(result) => result
+                    .iter_bindings()
+                    .map(|(var, value)| format!("{} = {}", var, value.to_polar()))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                Err(e) => return self.connection(|c| c.output("stderr", &e.to_string())),
+            };
+            has_result = true;
+            self.connection(|c| c.output("stdout", &output))?;
+            if self.session.borrow().terminated {
+                return Ok(());
+            }
+        }
+        if let Some(e) = self.session.borrow_mut().error.take() {
+            return Err(e);
+        }
+        if !has_result {
+            self.connection(|c| c.output("stdout", "false"))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,7 @@
+use std::io::{self, BufReader};
+
+use oso_dap::DebugAdapter;
+
+fn main() -> io::Result<()> {
+    DebugAdapter::new(BufReader::new(io::stdin()), io::stdout()).run()
+}
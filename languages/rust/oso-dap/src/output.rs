@@ -0,0 +1,138 @@
+//! Parse the text output of debugger commands into protocol values.
+
+/// One frame of the output of the `stack` debugger command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    pub id: usize,
+    pub name: String,
+    pub line: usize,
+    pub column: usize,
+    pub file: Option<String>,
+}
+
+/// Parse the output of the `stack` command, innermost frame first.
+///
+/// Each frame is printed as
+///
+/// ```text
+/// 1: b(x)
+///   in rule a at line 1, column 21 in file test.polar
+/// ```
+///
+/// where the second line is missing for queries without a source.
+pub fn parse_stack(output: &str) -> Vec<StackFrame> {
+    let mut frames: Vec<StackFrame> = vec![];
+    for line in output.lines() {
+        let line = line.trim();
+        if let Some(location) = line.strip_prefix("in ") {
+            if let Some(frame) = frames.last_mut() {
+                parse_location(location, frame);
+            }
+            continue;
+        }
+        let new_frame = line.find(": ").and_then(|index| {
+            line[..index].parse().ok().map(|id| StackFrame {
+                id,
+                name: line[index + 2..].to_string(),
+                line: 0,
+                column: 0,
+                file: None,
+            })
+        });
+        match (new_frame, frames.last_mut()) {
+            (Some(frame), _) => frames.push(frame),
+            // Continuation of a query that spans multiple lines.
+            (None, Some(frame)) if !line.is_empty() => {
+                frame.name.push(' ');
+                frame.name.push_str(line);
+            }
+            _ => (),
+        }
+    }
+    frames.sort_by_key(|frame| frame.id);
+    frames
+}
+
+/// Parse `rule a at line 1, column 21 in file test.polar` into `frame`.
+fn parse_location(location: &str, frame: &mut StackFrame) {
+    let (context, position) = match location.find(" at line ") {
+        Some(index) => (&location[..index], &location[index + " at line ".len()..]),
+        None => return,
+    };
+    if let Some(rule) = context.strip_prefix("rule ") {
+        frame.name = format!("{} (in {})", frame.name, rule);
+    }
+    let (position, file) = match position.find(" in file ") {
+        Some(index) => (
+            &position[..index],
+            Some(position[index + " in file ".len()..].to_string()),
+        ),
+        None => (position, None),
+    };
+    let mut numbers = position
+        .split(", column ")
+        .map(|n| n.trim().parse().unwrap_or(0));
+    frame.line = numbers.next().unwrap_or(0);
+    frame.column = numbers.next().unwrap_or(0);
+    frame.file = file;
+}
+
+/// Parse the output of the `bindings` command into `(name, value)` pairs.
+pub fn parse_bindings(output: &str) -> Vec<(String, String)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            line.find(" = ")
+                .map(|index| (line[..index].to_string(), line[index + 3..].to_string()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_stack() {
+        let output = "2: a(1)\n  in query at line 1, column 1\n1: debug() and b(x)\n  in rule a at line 1, column 9 in file test.polar\n0: b(x)\n  in rule a at line 1, column 21 in file test.polar\n";
+        let frames = parse_stack(output);
+        assert_eq!(
+            frames,
+            vec![
+                StackFrame {
+                    id: 0,
+                    name: "b(x) (in a)".to_string(),
+                    line: 1,
+                    column: 21,
+                    file: Some("test.polar".to_string()),
+                },
+                StackFrame {
+                    id: 1,
+                    name: "debug() and b(x) (in a)".to_string(),
+                    line: 1,
+                    column: 9,
+                    file: Some("test.polar".to_string()),
+                },
+                StackFrame {
+                    id: 2,
+                    name: "a(1)".to_string(),
+                    line: 1,
+                    column: 1,
+                    file: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_bindings() {
+        assert_eq!(
+            parse_bindings("_x_3 = 1\n_y_4 = [1, 2]"),
+            vec![
+                ("_x_3".to_string(), "1".to_string()),
+                ("_y_4".to_string(), "[1, 2]".to_string())
+            ]
+        );
+        assert!(parse_bindings("No bindings for the current query.").is_empty());
+    }
+}
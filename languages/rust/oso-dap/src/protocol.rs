@@ -0,0 +1,38 @@
+//! Message types for the Debug Adapter Protocol.
+//!
+//! See the [protocol specification](https://microsoft.github.io/debug-adapter-protocol/specification)
+//! for the full set of messages. Only the subset used by the adapter is modeled here.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// A request from the client (the editor).
+#[derive(Debug, Clone, Deserialize)]
+pub struct Request {
+    pub seq: i64,
+    pub command: String,
+    #[serde(default)]
+    pub arguments: Value,
+}
+
+/// A message sent from the adapter to the client.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum OutgoingMessage {
+    Response {
+        seq: i64,
+        request_seq: i64,
+        success: bool,
+        command: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        message: Option<String>,
+        #[serde(skip_serializing_if = "Value::is_null")]
+        body: Value,
+    },
+    Event {
+        seq: i64,
+        event: String,
+        #[serde(skip_serializing_if = "Value::is_null")]
+        body: Value,
+    },
+}
@@ -0,0 +1,111 @@
+use std::io::Cursor;
+
+use serde_json::{json, Value};
+
+use oso_dap::DebugAdapter;
+use oso_protocol::testing::{frame, unframe, SharedBuffer};
+
+fn run(requests: &[Value]) -> Vec<Value> {
+    let requests: Vec<Value> = requests
+        .iter()
+        .enumerate()
+        .map(|(seq, request)| {
+            let mut request = request.clone();
+            request["seq"] = json!(seq + 1);
+            request["type"] = json!("request");
+            request
+        })
+        .collect();
+    let output = SharedBuffer::default();
+    DebugAdapter::new(Cursor::new(frame(&requests)), output.clone())
+        .run()
+        .unwrap();
+    unframe(&output)
+}
+
+/// Summarize a message as `response <command>` or `event <event>`.
+fn kind(message: &Value) -> String {
+    match message["type"].as_str().unwrap() {
+        "response" => format!("response {}", message["command"].as_str().unwrap()),
+        kind => format!("{} {}", kind, message["event"].as_str().unwrap()),
+    }
+}
+
+#[test]
+fn test_breakpoint_session() {
+    let policy = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/test.polar");
+    let messages = run(&[
+        json!({ "command": "initialize", "arguments": { "adapterID": "polar" } }),
+        json!({ "command": "launch", "arguments": { "policies": [policy], "query": "a(x)" } }),
+        json!({
+            "command": "setBreakpoints",
+            "arguments": { "source": { "path": policy }, "breakpoints": [{ "line": 2 }] }
+        }),
+        json!({ "command": "configurationDone" }),
+        json!({ "command": "threads" }),
+        json!({ "command": "stackTrace", "arguments": { "threadId": 1 } }),
+        json!({ "command": "scopes", "arguments": { "frameId": 0 } }),
+        json!({ "command": "variables", "arguments": { "variablesReference": 1 } }),
+        json!({ "command": "evaluate", "arguments": { "expression": "x", "context": "hover" } }),
+        json!({ "command": "continue", "arguments": { "threadId": 1 } }),
+        json!({ "command": "disconnect" }),
+    ]);
+    assert_eq!(
+        messages.iter().map(kind).collect::<Vec<_>>(),
+        vec![
+            "response initialize",
+            "event initialized",
+            "response launch",
+            "response setBreakpoints",
+            "response configurationDone",
+            "event output",
+            "event stopped",
+            "response threads",
+            "response stackTrace",
+            "response scopes",
+            "response variables",
+            "response evaluate",
+            "response continue",
+            "event output",
+            "event output",
+            "event terminated",
+            "event exited",
+            "response disconnect",
+        ]
+    );
+
+    assert_eq!(messages[6]["body"]["reason"], "breakpoint");
+    let frames = messages[8]["body"]["stackFrames"].as_array().unwrap();
+    assert_eq!(frames.len(), 3);
+    assert_eq!(frames[0]["id"], 0);
+    assert_eq!(frames[0]["name"], "x = 1 (in b)");
+    assert_eq!(frames[0]["line"], 2);
+    assert_eq!(frames[0]["source"]["path"], policy);
+    assert_eq!(frames[1]["name"], "b(x) (in a)");
+    assert_eq!(frames[1]["line"], 1);
+    assert_eq!(messages[11]["body"]["result"], "x = _x_5");
+    assert_eq!(messages[13]["body"]["output"], "x = 1\n");
+    assert_eq!(messages[14]["body"]["output"], "x = 2\n");
+}
+
+#[test]
+fn test_load_error() {
+    let messages = run(&[
+        json!({ "command": "initialize", "arguments": { "adapterID": "polar" } }),
+        json!({ "command": "launch", "arguments": { "policies": ["missing.polar"], "query": "a(x)" } }),
+        json!({ "command": "configurationDone" }),
+        json!({ "command": "disconnect" }),
+    ]);
+    let output = messages
+        .iter()
+        .find(|message| message["event"] == "output")
+        .unwrap();
+    assert_eq!(output["body"]["category"], "stderr");
+    assert!(output["body"]["output"]
+        .as_str()
+        .unwrap()
+        .starts_with("Failed to load missing.polar"));
+    assert!(messages
+        .iter()
+        .any(|message| message["event"] == "terminated"));
+}
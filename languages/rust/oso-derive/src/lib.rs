@@ -3,13 +3,71 @@ extern crate quote;
 extern crate syn;
 
 use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
 use quote::quote;
-use syn::{Attribute, Fields, Lit, Meta, MetaNameValue, NestedMeta, Path};
+use syn::spanned::Spanned;
+use syn::{
+    Attribute, Data, DeriveInput, Error, Fields, FnArg, ImplItem, ItemImpl, Lit, Meta,
+    MetaNameValue, NestedMeta, Path,
+};
 
 #[derive(Debug, PartialEq)]
 enum OsoAttribute {
     ClassName { name: String },
     Attribute,
+    Rename { name: String },
+    Methods,
+    Method,
+    IteratorMethod,
+    ClassMethod,
+    Constructor,
+}
+
+impl OsoAttribute {
+    /// The name of the attribute, as written in `#[polar(...)]`.
+    fn name(&self) -> &'static str {
+        match self {
+            OsoAttribute::ClassName { .. } => "class_name",
+            OsoAttribute::Attribute => "attribute",
+            OsoAttribute::Rename { .. } => "rename",
+            OsoAttribute::Methods => "methods",
+            OsoAttribute::Method => "method",
+            OsoAttribute::IteratorMethod => "iterator_method",
+            OsoAttribute::ClassMethod => "class_method",
+            OsoAttribute::Constructor => "constructor",
+        }
+    }
+}
+
+/// The `#[polar(...)]` attributes of an item, and where they were written.
+struct OsoAttributes(Vec<(OsoAttribute, Span)>);
+
+impl OsoAttributes {
+    /// Fail on the attributes not in `allowed`, which apply to `target`.
+    fn check(&self, allowed: &[&str], target: &str) -> syn::Result<()> {
+        match self
+            .0
+            .iter()
+            .find(|(attr, _)| !allowed.contains(&attr.name()))
+        {
+            Some((attr, span)) => Err(Error::new(
+                *span,
+                format!("`{}` doesn't apply to {}", attr.name(), target),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    fn contains(&self, attr: &OsoAttribute) -> bool {
+        self.0.iter().any(|(a, _)| a == attr)
+    }
+
+    fn rename(&self) -> Option<(&str, Span)> {
+        self.0.iter().find_map(|(attr, span)| match attr {
+            OsoAttribute::Rename { name } => Some((name.as_str(), *span)),
+            _ => None,
+        })
+    }
 }
 
 fn get_single_segment(path: &Path) -> Option<String> {
@@ -20,103 +78,185 @@ fn get_single_segment(path: &Path) -> Option<String> {
     }
 }
 
-fn get_nested_attr(nested: NestedMeta, oso_attrs: &mut Vec<OsoAttribute>) {
-    match nested {
-        NestedMeta::Lit(_) => {
-            // @TODO: Probably error.
+fn string_value(lit: Lit, name: &str) -> syn::Result<String> {
+    match lit {
+        Lit::Str(value) => Ok(value.value()),
+        lit => Err(Error::new_spanned(
+            lit,
+            format!("`{}` must be a string, like `{} = \"Name\"`", name, name),
+        )),
+    }
+}
 
-            unimplemented!("Hit a literal instead of a name.");
+fn get_nested_attr(nested: NestedMeta) -> syn::Result<OsoAttribute> {
+    match nested {
+        NestedMeta::Lit(lit) => Err(Error::new_spanned(
+            lit,
+            "expected a polar attribute, like `attribute` or `class_name = \"Name\"`",
+        )),
+        NestedMeta::Meta(Meta::Path(path)) => {
+            let attr = match get_single_segment(&path).as_deref() {
+                Some("attribute") => OsoAttribute::Attribute,
+                Some("methods") => OsoAttribute::Methods,
+                Some("method") => OsoAttribute::Method,
+                Some("iterator_method") => OsoAttribute::IteratorMethod,
+                Some("class_method") => OsoAttribute::ClassMethod,
+                Some("constructor") => OsoAttribute::Constructor,
+                _ => return Err(Error::new_spanned(path, "unknown polar attribute")),
+            };
+            Ok(attr)
         }
-        NestedMeta::Meta(meta) => {
-            match meta {
-                Meta::Path(path) => {
-                    match get_single_segment(&path) {
-                        Some(ref seg) if seg == "attribute" => {
-                            oso_attrs.push(OsoAttribute::Attribute);
-                        }
-                        _ => (),
-                    };
-                }
-                Meta::List(_) => {
-                    // I don't know why this case would happen, seems like
-                    // polar(foo, bar) would each be two different attributes
-                    // nested under polar, not a single nested list.
-                    // leaving here till we add all the attributes we need in case it pops up.
-                    unimplemented!("Hit the list case");
-                }
-                Meta::NameValue(MetaNameValue { path, lit, .. }) => {
-                    if let Some(ref seg) = get_single_segment(&path) {
-                        if seg == "class_name" {
-                            // @TODO: Type error if it's not a string.
-                            if let Lit::Str(class_name) = lit {
-                                oso_attrs.push(OsoAttribute::ClassName {
-                                    name: class_name.value(),
-                                });
-                            }
-                        }
-                    }
-                }
+        NestedMeta::Meta(Meta::List(list)) => Err(Error::new_spanned(
+            list,
+            "expected a polar attribute, like `attribute` or `class_name = \"Name\"`",
+        )),
+        NestedMeta::Meta(Meta::NameValue(MetaNameValue { path, lit, .. })) => {
+            match get_single_segment(&path).as_deref() {
+                Some("class_name") => Ok(OsoAttribute::ClassName {
+                    name: string_value(lit, "class_name")?,
+                }),
+                Some("rename") => Ok(OsoAttribute::Rename {
+                    name: string_value(lit, "rename")?,
+                }),
+                _ => Err(Error::new_spanned(path, "unknown polar attribute")),
             }
         }
     }
 }
 
-fn get_oso_attrs(attr: Attribute, oso_attrs: &mut Vec<OsoAttribute>) {
-    let meta = attr.parse_meta().unwrap();
-    if let Meta::List(list) = meta {
-        match get_single_segment(&list.path) {
-            Some(ref seg) if seg == "polar" => {
+fn is_polar_attr(attr: &Attribute) -> bool {
+    get_single_segment(&attr.path).as_deref() == Some("polar")
+}
+
+fn get_oso_attrs(attrs: &[Attribute]) -> syn::Result<OsoAttributes> {
+    let mut oso_attrs = vec![];
+    for attr in attrs.iter().filter(|attr| is_polar_attr(attr)) {
+        match attr.parse_meta()? {
+            Meta::List(list) => {
                 for nested in list.nested {
-                    get_nested_attr(nested, oso_attrs);
+                    let span = nested.span();
+                    oso_attrs.push((get_nested_attr(nested)?, span));
                 }
             }
-            _ => (),
+            meta => {
+                return Err(Error::new_spanned(
+                    meta,
+                    "expected a list of polar attributes, like `#[polar(attribute)]`",
+                ))
+            }
         }
     }
+    Ok(OsoAttributes(oso_attrs))
 }
 
 #[proc_macro_derive(PolarClass, attributes(polar))]
 pub fn derive_polar_class_impl(ts: TokenStream) -> TokenStream {
-    let input = syn::parse_macro_input!(ts as syn::ItemStruct);
+    let input = syn::parse_macro_input!(ts as DeriveInput);
+    derive_polar_class(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn derive_polar_class(input: DeriveInput) -> syn::Result<TokenStream2> {
+    if !input.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            input.generics,
+            "PolarClass can't be derived for generic types",
+        ));
+    }
 
     let type_name = input.ident;
     let mut class_name = type_name.to_string();
 
-    let attrs = input.attrs;
-    let mut oso_attrs = vec![];
-    for attr in attrs {
-        get_oso_attrs(attr, &mut oso_attrs);
-    }
-    for oso_attr in oso_attrs {
+    let oso_attrs = get_oso_attrs(&input.attrs)?;
+    oso_attrs.check(&["class_name", "methods"], "types")?;
+    for (oso_attr, _) in &oso_attrs.0 {
         if let OsoAttribute::ClassName { name } = oso_attr {
-            class_name = name;
+            class_name = name.clone();
         }
     }
 
-    let mut getters = vec![];
-
-    if let Fields::Named(fields) = input.fields {
-        for field in fields.named {
-            let mut oso_attrs = vec![];
-            for attr in field.attrs {
-                get_oso_attrs(attr, &mut oso_attrs);
-            }
-            if oso_attrs.contains(&OsoAttribute::Attribute) {
-                let attr = field.ident.unwrap();
-                let name = attr.to_string();
-                getters.push(quote! {
+    let mut calls = vec![];
+    match input.data {
+        Data::Struct(data) => {
+            for field in data.fields {
+                let oso_attrs = get_oso_attrs(&field.attrs)?;
+                oso_attrs.check(&["attribute", "rename"], "fields")?;
+                if !oso_attrs.contains(&OsoAttribute::Attribute) {
+                    if let Some((_, span)) = oso_attrs.rename() {
+                        return Err(Error::new(
+                            span,
+                            "`rename` applies to attributes: add `attribute`",
+                        ));
+                    }
+                    continue;
+                }
+                let attr = match &field.ident {
+                    Some(ident) => ident,
+                    None => {
+                        return Err(Error::new_spanned(
+                            field,
+                            "`attribute` needs a named field; register tuple fields with `add_attribute_getter`",
+                        ))
+                    }
+                };
+                let name = match oso_attrs.rename() {
+                    Some((name, _)) => name.to_string(),
+                    None => attr.to_string(),
+                };
+                calls.push(quote! {
                     .add_attribute_getter(#name, |recv: &#type_name| recv.#attr.clone())
-                })
+                });
             }
         }
+        Data::Enum(data) => {
+            // Variants are constants on the class. Without fields, variants
+            // are equal if they're the same variant.
+            for variant in data.variants {
+                let oso_attrs = get_oso_attrs(&variant.attrs)?;
+                oso_attrs.check(&["rename"], "variants")?;
+                if !matches!(variant.fields, Fields::Unit) {
+                    return Err(Error::new_spanned(
+                        variant,
+                        "PolarClass can only be derived for enums whose variants have no fields",
+                    ));
+                }
+                let ident = &variant.ident;
+                let name = match oso_attrs.rename() {
+                    Some((name, _)) => name.to_string(),
+                    None => ident.to_string(),
+                };
+                calls.push(quote! {
+                    .add_constant(#name, #type_name::#ident)
+                });
+            }
+            calls.push(quote! {
+                .set_equality_check(|a: &#type_name, b: &#type_name| {
+                    ::std::mem::discriminant(a) == ::std::mem::discriminant(b)
+                })
+            });
+        }
+        Data::Union(data) => {
+            return Err(Error::new_spanned(
+                data.union_token,
+                "PolarClass can't be derived for unions",
+            ))
+        }
     }
 
-    let result = quote! {
+    let builder = if oso_attrs.contains(&OsoAttribute::Methods) {
+        quote! { <#type_name as oso::PolarMethods>::add_polar_methods(builder) }
+    } else {
+        quote! { builder }
+    };
+
+    Ok(quote! {
         impl oso::PolarClass for #type_name {
             fn get_polar_class_builder() -> oso::ClassBuilder<#type_name> {
-                oso::Class::builder()
+                let builder = oso::Class::builder()
                     .name(#class_name)
-                    #(#getters)*
+                    #(#calls)*;
+                #builder
             }
 
             fn get_polar_class() -> oso::Class {
@@ -124,6 +264,177 @@ pub fn derive_polar_class_impl(ts: TokenStream) -> TokenStream {
                 builder.build()
             }
         }
+    })
+}
+
+#[proc_macro_derive(FromResultSet, attributes(polar))]
+pub fn derive_from_result_set_impl(ts: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(ts as DeriveInput);
+    derive_from_result_set(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn derive_from_result_set(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let type_name = &input.ident;
+    get_oso_attrs(&input.attrs)?.check(&[], "types deriving FromResultSet")?;
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(Error::new_spanned(
+                    &data.fields,
+                    "FromResultSet needs named fields, one for each variable",
+                ))
+            }
+        },
+        _ => {
+            return Err(Error::new_spanned(
+                type_name,
+                "FromResultSet can only be derived for structs",
+            ))
+        }
     };
-    result.into()
+
+    let mut bindings = vec![];
+    for field in fields {
+        let oso_attrs = get_oso_attrs(&field.attrs)?;
+        oso_attrs.check(&["rename"], "fields of FromResultSet types")?;
+        let ident = field.ident.as_ref().unwrap();
+        let name = match oso_attrs.rename() {
+            Some((name, _)) => name.to_string(),
+            None => ident.to_string(),
+        };
+        bindings.push(quote! { #ident: result.get_var(#name)? });
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics oso::FromResultSet for #type_name #ty_generics #where_clause {
+            fn from_result_set(result: &oso::ResultSet) -> oso::Result<Self> {
+                Ok(Self {
+                    #(#bindings,)*
+                })
+            }
+        }
+    })
+}
+
+/// Register the methods of an `impl` block marked with `#[polar(method)]`,
+/// `#[polar(iterator_method)]`, `#[polar(class_method)]` or
+/// `#[polar(constructor)]`, for types deriving `PolarClass` with
+/// `#[polar(methods)]`. Methods can be renamed with `#[polar(rename = "name")]`.
+#[proc_macro_attribute]
+pub fn polar_methods(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = TokenStream2::from(args);
+    if !args.is_empty() {
+        return Error::new_spanned(args, "#[polar_methods] takes no arguments")
+            .to_compile_error()
+            .into();
+    }
+    let input = syn::parse_macro_input!(item as ItemImpl);
+    polar_methods_impl(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+fn polar_methods_impl(mut input: ItemImpl) -> syn::Result<TokenStream2> {
+    if let Some((_, path, _)) = &input.trait_ {
+        return Err(Error::new_spanned(
+            path,
+            "#[polar_methods] applies to inherent impl blocks, not trait impls",
+        ));
+    }
+
+    let self_ty = input.self_ty.clone();
+    let mut calls = vec![];
+    for item in &mut input.items {
+        let method = match item {
+            ImplItem::Method(method) => method,
+            _ => continue,
+        };
+        let oso_attrs = get_oso_attrs(&method.attrs)?;
+        method.attrs.retain(|attr| !is_polar_attr(attr));
+        if oso_attrs.0.is_empty() {
+            continue;
+        }
+        oso_attrs.check(
+            &[
+                "method",
+                "iterator_method",
+                "class_method",
+                "constructor",
+                "rename",
+            ],
+            "methods",
+        )?;
+
+        let kinds: Vec<&OsoAttribute> = oso_attrs
+            .0
+            .iter()
+            .map(|(attr, _)| attr)
+            .filter(|attr| !matches!(attr, OsoAttribute::Rename { .. }))
+            .collect();
+        let kind =
+            match kinds.as_slice() {
+                [kind] => *kind,
+                _ => return Err(Error::new_spanned(
+                    &method.sig,
+                    "expected one of `method`, `iterator_method`, `class_method` or `constructor`",
+                )),
+            };
+
+        let ident = &method.sig.ident;
+        let name = match oso_attrs.rename() {
+            Some((name, _)) => name.to_string(),
+            None => ident.to_string(),
+        };
+        let takes_ref_self = match method.sig.inputs.first() {
+            Some(FnArg::Receiver(receiver)) => {
+                receiver.reference.is_some() && receiver.mutability.is_none()
+            }
+            _ => false,
+        };
+        let has_receiver = matches!(method.sig.inputs.first(), Some(FnArg::Receiver(_)));
+        match kind {
+            OsoAttribute::Method | OsoAttribute::IteratorMethod if !takes_ref_self => {
+                return Err(Error::new_spanned(
+                    &method.sig,
+                    "methods registered with Polar must take `&self`",
+                ))
+            }
+            OsoAttribute::ClassMethod | OsoAttribute::Constructor if has_receiver => {
+                return Err(Error::new_spanned(
+                    &method.sig,
+                    "class methods and constructors can't take `self`",
+                ))
+            }
+            OsoAttribute::Constructor => {
+                if let Some((_, span)) = oso_attrs.rename() {
+                    return Err(Error::new(span, "constructors can't be renamed"));
+                }
+            }
+            _ => (),
+        }
+
+        calls.push(match kind {
+            OsoAttribute::Method => quote! { .add_method(#name, <#self_ty>::#ident) },
+            OsoAttribute::IteratorMethod => {
+                quote! { .add_iterator_method(#name, <#self_ty>::#ident) }
+            }
+            OsoAttribute::ClassMethod => quote! { .add_class_method(#name, <#self_ty>::#ident) },
+            _ => quote! { .set_constructor(<#self_ty>::#ident) },
+        });
+    }
+
+    let (impl_generics, _, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        #input
+
+        impl #impl_generics oso::PolarMethods for #self_ty #where_clause {
+            fn add_polar_methods(builder: oso::ClassBuilder<Self>) -> oso::ClassBuilder<Self> {
+                builder #(#calls)*
+            }
+        }
+    })
 }
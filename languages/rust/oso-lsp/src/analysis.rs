@@ -0,0 +1,237 @@
+//! Analysis of the open policy documents.
+//!
+//! All open documents are loaded into one [`Polar`] instance, named by their
+//! URI, and the language features are answered from its knowledge base.
+
+use std::collections::{BTreeMap, HashMap};
+use std::iter;
+
+use polar_core::diagnostic::{Diagnostic, Range};
+use polar_core::kb::KnowledgeBase;
+use polar_core::polar::Polar;
+use polar_core::rules::Rule;
+use polar_core::terms::{ExternalInstance, Symbol, Term, Value};
+
+/// A definition of a rule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Definition {
+    pub name: String,
+    /// The rule head, e.g. `allow(actor: User, action, resource)`.
+    pub signature: String,
+    /// URI of the document the rule is defined in.
+    pub uri: String,
+    /// Range of the rule name.
+    pub name_range: Range,
+    /// Range of the whole rule.
+    pub range: Range,
+}
+
+pub struct Analysis {
+    polar: Polar,
+    /// Errors and warnings by document URI.
+    pub diagnostics: BTreeMap<String, Vec<Diagnostic>>,
+    /// URIs of the documents with the same text as an earlier document, and
+    /// the URI of that document.
+    copies: BTreeMap<String, String>,
+}
+
+impl Analysis {
+    /// Load `documents`, keyed by URI, with `classes` registered.
+    pub fn new(documents: &BTreeMap<String, String>, classes: &[String]) -> Self {
+        let polar = Polar::new();
+        for class in classes {
+            let instance = ExternalInstance {
+                instance_id: polar.get_external_id(),
+                constructor: None,
+                repr: Some(class.clone()),
+            };
+            polar.register_constant(
+                Symbol(class.clone()),
+                Term::new_temporary(Value::ExternalInstance(instance)),
+            );
+        }
+
+        let mut diagnostics: BTreeMap<String, Vec<Diagnostic>> =
+            documents.keys().map(|uri| (uri.clone(), vec![])).collect();
+        // Polar refuses to load the same text twice, and a copy of a document
+        // would only define its rules again, so copies share its diagnostics.
+        let mut loaded: HashMap<&str, &String> = HashMap::new();
+        let mut copies = BTreeMap::new();
+        for (uri, text) in documents {
+            if let Some(original) = loaded.get(text.as_str()) {
+                copies.insert(uri.clone(), (*original).clone());
+                continue;
+            }
+            loaded.insert(text, uri);
+            if let Err(error) = polar.load(text, Some(uri.clone())) {
+                diagnostics
+                    .get_mut(uri)
+                    .unwrap()
+                    .extend(error.errors().map(|e| e.diagnostic()));
+            }
+        }
+
+        // Calls are checked once all the documents are loaded.
+        polar.check();
+        let warnings =
+            iter::from_fn(|| polar.next_message()).filter_map(|message| message.diagnostic);
+        for warning in warnings {
+            let uri = warning.filename.clone().unwrap_or_default();
+            if let Some(diagnostics) = diagnostics.get_mut(&uri) {
+                diagnostics.push(warning);
+            }
+        }
+        for diagnostics in diagnostics.values_mut() {
+            diagnostics.sort_by_key(|diagnostic| {
+                diagnostic
+                    .range
+                    .map(|range| (range.start.line, range.start.column))
+            });
+        }
+        for (uri, original) in &copies {
+            let copy = diagnostics[original]
+                .iter()
+                .cloned()
+                .map(|mut diagnostic| {
+                    diagnostic.filename = Some(uri.clone());
+                    diagnostic
+                })
+                .collect();
+            diagnostics.insert(uri.clone(), copy);
+        }
+
+        Self {
+            polar,
+            diagnostics,
+            copies,
+        }
+    }
+
+    /// All definitions of the rule `name`, in the order they were loaded.
+    pub fn definitions(&self, name: &str) -> Vec<Definition> {
+        let kb = self.polar.kb.read().unwrap();
+        kb.rules
+            .get(&Symbol(name.to_string()))
+            .map(|generic_rule| {
+                generic_rule
+                    .rules()
+                    .filter_map(|rule| definition(rule, &kb))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Definitions of the rules in the document `uri`, in source order.
+    pub fn document_rules(&self, uri: &str) -> Vec<Definition> {
+        let loaded_uri = self.copies.get(uri).map_or(uri, String::as_str);
+        let kb = self.polar.kb.read().unwrap();
+        let mut definitions: Vec<Definition> = kb
+            .rules
+            .values()
+            .flat_map(|generic_rule| generic_rule.rules())
+            .filter_map(|rule| definition(rule, &kb))
+            .filter(|definition| definition.uri == loaded_uri)
+            .map(|definition| Definition {
+                uri: uri.to_string(),
+                ..definition
+            })
+            .collect();
+        definitions
+            .sort_by_key(|definition| (definition.range.start.line, definition.range.start.column));
+        definitions
+    }
+
+    pub fn rule_names(&self) -> Vec<String> {
+        let kb = self.polar.kb.read().unwrap();
+        let mut names: Vec<String> = kb.rules.keys().map(|name| name.0.clone()).collect();
+        names.sort();
+        names
+    }
+
+    pub fn class_names(&self) -> Vec<String> {
+        let kb = self.polar.kb.read().unwrap();
+        let mut names: Vec<String> = kb
+            .constants
+            .iter()
+            .filter(|(_, value)| matches!(value.value(), Value::ExternalInstance(_)))
+            .map(|(name, _)| name.0.clone())
+            .collect();
+        names.sort();
+        names
+    }
+}
+
+fn definition(rule: &Rule, kb: &KnowledgeBase) -> Option<Definition> {
+    let (body_left, body_right) = rule.body.span()?;
+    let source = kb.sources.get_source(rule.body.get_source_id()?)?;
+    let uri = source.filename?;
+    let name = &rule.name.0;
+
+    // The name is the last occurrence of it before the first parameter, or
+    // before the body for rules without parameters.
+    let head = rule
+        .params
+        .iter()
+        .filter_map(|param| param.parameter.span())
+        .map(|(left, _)| left)
+        .chain(iter::once(body_left))
+        .min()
+        .unwrap();
+    let start = source.src[..head].rfind(name.as_str()).unwrap_or(head);
+    let name_end = start + name.len();
+
+    // The rule ends with the `;` after its body.
+    let end = source.src[body_right..]
+        .find(';')
+        .map_or(body_right, |i| body_right + i + 1);
+
+    // The head as written, since the parameters are rewritten when loaded.
+    let head = source.src[start..body_left].trim_end();
+    let head = head.strip_suffix("if").unwrap_or(head);
+    let signature = head.split_whitespace().collect::<Vec<_>>().join(" ");
+    Some(Definition {
+        name: name.clone(),
+        signature,
+        uri,
+        name_range: Range::from_offsets(&source.src, start, name_end),
+        range: Range::from_offsets(&source.src, start, end),
+    })
+}
+
+/// The offset of a (0-based) line and character in `text`.
+pub fn offset(text: &str, line: usize, character: usize) -> usize {
+    let mut offset = 0;
+    for (i, text_line) in text.split_inclusive('\n').enumerate() {
+        if i == line {
+            return offset
+                + text_line
+                    .char_indices()
+                    .nth(character)
+                    .map_or(text_line.trim_end_matches('\n').len(), |(i, _)| i);
+        }
+        offset += text_line.len();
+    }
+    offset
+}
+
+/// The name at `offset` in `text`, if any.
+pub fn name_at(text: &str, offset: usize) -> Option<&str> {
+    let is_name = |c: char| c == '_' || c.is_alphanumeric();
+    let offset = offset.min(text.len());
+    let start = text[..offset]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_name(*c))
+        .last()
+        .map_or(offset, |(i, _)| i);
+    let end = text[offset..]
+        .char_indices()
+        .find(|(_, c)| !is_name(*c))
+        .map_or(text.len(), |(i, _)| offset + i);
+    let name = &text[start..end];
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        None
+    } else {
+        Some(name)
+    }
+}
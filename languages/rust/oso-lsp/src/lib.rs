@@ -0,0 +1,279 @@
+//! Language Server Protocol server for Polar policies
+//!
+//! The server speaks the [Language Server Protocol](https://microsoft.github.io/language-server-protocol/)
+//! to an editor over stdio. Whenever a document is opened, changed or closed, all
+//! open documents are loaded together into a [`polar_core::polar::Polar`] instance,
+//! so that calls between files resolve, and the server publishes:
+//!
+//! - diagnostics for parse errors and load-time warnings, such as singleton
+//!   variables and calls to undefined rules,
+//!
+//! and answers `textDocument/definition` (from a rule call to all definitions of
+//! the rule), `textDocument/hover` (the signatures of a rule),
+//! `textDocument/documentSymbol` (the rules of a document) and
+//! `textDocument/completion` (rule names and class names).
+//!
+//! The server has no application to register classes, so class names are
+//! passed as initialization options:
+//!
+//! ```json
+//! {
+//!     "classes": ["User", "Document"]
+//! }
+//! ```
+//!
+//! Positions are counted in characters rather than UTF-16 code units.
+
+pub mod analysis;
+pub mod protocol;
+
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+
+use serde_json::{json, Value};
+
+use oso_protocol::{read_message, write_message};
+use polar_core::diagnostic::{Diagnostic, Range, Severity};
+
+use analysis::{name_at, offset, Analysis, Definition};
+use protocol::{error_response, notification, response, Message, METHOD_NOT_FOUND};
+
+/// `SymbolKind.Function` and `CompletionItemKind.Function`.
+const FUNCTION_SYMBOL: i64 = 12;
+const FUNCTION_COMPLETION: i64 = 3;
+/// `CompletionItemKind.Class`.
+const CLASS_COMPLETION: i64 = 7;
+
+/// An LSP range: lines and characters start at 0.
+fn lsp_range(range: &Range) -> Value {
+    json!({
+        "start": { "line": range.start.line - 1, "character": range.start.column - 1 },
+        "end": { "line": range.end.line - 1, "character": range.end.column - 1 },
+    })
+}
+
+fn lsp_diagnostic(diagnostic: &Diagnostic) -> Value {
+    let range = match &diagnostic.range {
+        Some(range) => lsp_range(range),
+        None => json!({
+            "start": { "line": 0, "character": 0 },
+            "end": { "line": 0, "character": 0 },
+        }),
+    };
+    let severity = match diagnostic.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+    };
+    json!({
+        "range": range,
+        "severity": severity,
+        "code": diagnostic.code,
+        "source": "polar",
+        "message": diagnostic.message,
+    })
+}
+
+fn location(definition: &Definition) -> Value {
+    json!({ "uri": definition.uri, "range": lsp_range(&definition.name_range) })
+}
+
+pub struct LanguageServer {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    /// Text of the open documents by URI.
+    documents: BTreeMap<String, String>,
+    /// Class names from the initialization options.
+    classes: Vec<String>,
+    analysis: Analysis,
+}
+
+impl LanguageServer {
+    pub fn new(input: impl BufRead + 'static, output: impl Write + 'static) -> Self {
+        let documents = BTreeMap::new();
+        Self {
+            input: Box::new(input),
+            output: Box::new(output),
+            analysis: Analysis::new(&documents, &[]),
+            documents,
+            classes: vec![],
+        }
+    }
+
+    /// Serve messages until the client sends `exit` or closes the input.
+    pub fn run(&mut self) -> io::Result<()> {
+        while let Some(message) = read_message::<Message>(self.input.as_mut())? {
+            let method = match &message.method {
+                Some(method) => method.as_str(),
+                // Responses from the client are not expected.
+                None => continue,
+            };
+            if method == "exit" {
+                break;
+            }
+            let result = self.handle(method, &message.params)?;
+            if let Some(id) = &message.id {
+                let reply = match result {
+                    Some(result) => response(id, result),
+                    None => error_response(
+                        id,
+                        METHOD_NOT_FOUND,
+                        &format!("unsupported method {}", method),
+                    ),
+                };
+                write_message(self.output.as_mut(), &reply)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a request or notification, and return the result of a request.
+    fn handle(&mut self, method: &str, params: &Value) -> io::Result<Option<Value>> {
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        let result = match method {
+            "initialize" => {
+                self.classes = params["initializationOptions"]["classes"]
+                    .as_array()
+                    .map(|classes| {
+                        classes
+                            .iter()
+                            .filter_map(|class| class.as_str().map(str::to_string))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                json!({
+                    "capabilities": {
+                        "textDocumentSync": 1,
+                        "definitionProvider": true,
+                        "hoverProvider": true,
+                        "documentSymbolProvider": true,
+                        "completionProvider": {},
+                    },
+                    "serverInfo": { "name": "oso-lsp", "version": env!("CARGO_PKG_VERSION") },
+                })
+            }
+            "shutdown" => Value::Null,
+            "textDocument/didOpen" => {
+                let text = params["textDocument"]["text"].as_str().unwrap_or_default();
+                self.documents.insert(uri.to_string(), text.to_string());
+                self.update(None)?;
+                return Ok(None);
+            }
+            "textDocument/didChange" => {
+                // Documents are synced in full, so the last change has the whole text.
+                let text = params["contentChanges"]
+                    .as_array()
+                    .and_then(|changes| changes.last())
+                    .and_then(|change| change["text"].as_str());
+                if let Some(text) = text {
+                    self.documents.insert(uri.to_string(), text.to_string());
+                    self.update(None)?;
+                }
+                return Ok(None);
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                self.update(Some(uri))?;
+                return Ok(None);
+            }
+            "textDocument/definition" => match self.name_at(params) {
+                Some(name) => Value::Array(
+                    self.analysis
+                        .definitions(&name)
+                        .iter()
+                        .map(location)
+                        .collect(),
+                ),
+                None => Value::Null,
+            },
+            "textDocument/hover" => {
+                let definitions = self
+                    .name_at(params)
+                    .map(|name| self.analysis.definitions(&name))
+                    .unwrap_or_default();
+                if definitions.is_empty() {
+                    Value::Null
+                } else {
+                    let signatures: Vec<&str> = definitions
+                        .iter()
+                        .map(|definition| definition.signature.as_str())
+                        .collect();
+                    json!({
+                        "contents": {
+                            "kind": "markdown",
+                            "value": format!("```polar\n{}\n```", signatures.join("\n")),
+                        }
+                    })
+                }
+            }
+            "textDocument/documentSymbol" => Value::Array(
+                self.analysis
+                    .document_rules(uri)
+                    .iter()
+                    .map(|definition| {
+                        json!({
+                            "name": definition.name,
+                            "detail": definition.signature,
+                            "kind": FUNCTION_SYMBOL,
+                            "range": lsp_range(&definition.range),
+                            "selectionRange": lsp_range(&definition.name_range),
+                        })
+                    })
+                    .collect(),
+            ),
+            "textDocument/completion" => {
+                let rules = self.analysis.rule_names().into_iter().map(|name| {
+                    let detail = self
+                        .analysis
+                        .definitions(&name)
+                        .first()
+                        .map(|definition| definition.signature.clone());
+                    json!({ "label": name, "kind": FUNCTION_COMPLETION, "detail": detail })
+                });
+                let classes = self
+                    .analysis
+                    .class_names()
+                    .into_iter()
+                    .map(|name| json!({ "label": name, "kind": CLASS_COMPLETION }));
+                Value::Array(rules.chain(classes).collect())
+            }
+            _ => return Ok(None),
+        };
+        Ok(Some(result))
+    }
+
+    /// The name at the position of a text document position request.
+    fn name_at(&self, params: &Value) -> Option<String> {
+        let text = self
+            .documents
+            .get(params["textDocument"]["uri"].as_str()?)?;
+        let line = params["position"]["line"].as_u64()? as usize;
+        let character = params["position"]["character"].as_u64()? as usize;
+        name_at(text, offset(text, line, character)).map(str::to_string)
+    }
+
+    /// Analyze the open documents again and publish their diagnostics, and
+    /// clear the diagnostics of the `closed` document.
+    fn update(&mut self, closed: Option<&str>) -> io::Result<()> {
+        self.analysis = Analysis::new(&self.documents, &self.classes);
+        let mut published: Vec<(&str, Value)> = self
+            .analysis
+            .diagnostics
+            .iter()
+            .map(|(uri, diagnostics)| {
+                let diagnostics: Vec<Value> = diagnostics.iter().map(lsp_diagnostic).collect();
+                (uri.as_str(), Value::Array(diagnostics))
+            })
+            .collect();
+        if let Some(closed) = closed {
+            published.push((closed, json!([])));
+        }
+        for (uri, diagnostics) in published {
+            let params = json!({ "uri": uri, "diagnostics": diagnostics });
+            write_message(
+                self.output.as_mut(),
+                &notification("textDocument/publishDiagnostics", params),
+            )?;
+        }
+        Ok(())
+    }
+}
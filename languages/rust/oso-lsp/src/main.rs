@@ -0,0 +1,7 @@
+use std::io::{self, BufReader};
+
+use oso_lsp::LanguageServer;
+
+fn main() -> io::Result<()> {
+    LanguageServer::new(BufReader::new(io::stdin()), io::stdout()).run()
+}
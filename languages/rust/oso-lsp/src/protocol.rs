@@ -0,0 +1,37 @@
+//! Message types for the Language Server Protocol.
+//!
+//! Messages are [JSON-RPC 2.0](https://www.jsonrpc.org/specification) requests,
+//! responses and notifications, framed with a `Content-Length` header. See the
+//! [protocol specification](https://microsoft.github.io/language-server-protocol/specification)
+//! for the full set of messages.
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+/// The requested method does not exist or is not supported.
+pub const METHOD_NOT_FOUND: i64 = -32601;
+
+/// A request or notification from the client (the editor).
+///
+/// Requests have an `id` to answer with a response, notifications don't.
+/// Responses from the client have no `method`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Message {
+    #[serde(default)]
+    pub id: Option<Value>,
+    pub method: Option<String>,
+    #[serde(default)]
+    pub params: Value,
+}
+
+pub fn response(id: &Value, result: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "result": result })
+}
+
+pub fn error_response(id: &Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+pub fn notification(method: &str, params: Value) -> Value {
+    json!({ "jsonrpc": "2.0", "method": method, "params": params })
+}
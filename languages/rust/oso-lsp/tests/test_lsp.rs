@@ -0,0 +1,238 @@
+use std::io::Cursor;
+
+use serde_json::{json, Value};
+
+use oso_lsp::LanguageServer;
+use oso_protocol::testing::{frame, unframe, SharedBuffer};
+
+/// Run the server on `messages`, numbering the ones with an `"id": true`
+/// placeholder.
+fn run(messages: &[Value]) -> Vec<Value> {
+    let messages: Vec<Value> = messages
+        .iter()
+        .enumerate()
+        .map(|(id, message)| {
+            let mut message = message.clone();
+            message["jsonrpc"] = json!("2.0");
+            if message["id"] == json!(true) {
+                message["id"] = json!(id);
+            }
+            message
+        })
+        .collect();
+    let output = SharedBuffer::default();
+    LanguageServer::new(Cursor::new(frame(&messages)), output.clone())
+        .run()
+        .unwrap();
+    unframe(&output)
+}
+
+fn open(uri: &str, text: &str) -> Value {
+    json!({
+        "method": "textDocument/didOpen",
+        "params": { "textDocument": { "uri": uri, "languageId": "polar", "version": 1, "text": text } }
+    })
+}
+
+fn request(method: &str, uri: &str, position: Option<(u64, u64)>) -> Value {
+    let mut params = json!({ "textDocument": { "uri": uri } });
+    if let Some((line, character)) = position {
+        params["position"] = json!({ "line": line, "character": character });
+    }
+    json!({ "id": true, "method": method, "params": params })
+}
+
+/// The diagnostic codes published for `uri`, by publication.
+fn published(messages: &[Value], uri: &str) -> Vec<Vec<String>> {
+    messages
+        .iter()
+        .filter(|message| {
+            message["method"] == "textDocument/publishDiagnostics"
+                && message["params"]["uri"] == uri
+        })
+        .map(|message| {
+            message["params"]["diagnostics"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|diagnostic| diagnostic["code"].as_str().unwrap().to_string())
+                .collect()
+        })
+        .collect()
+}
+
+fn result(messages: &[Value], id: u64) -> &Value {
+    &messages.iter().find(|message| message["id"] == id).unwrap()["result"]
+}
+
+#[test]
+fn test_language_features() {
+    let allow = "file:///allow.polar";
+    let roles = "file:///roles.polar";
+    let broken = "file:///broken.polar";
+    let messages = run(&[
+        json!({ "id": true, "method": "initialize", "params": { "initializationOptions": { "classes": ["User"] } } }),
+        json!({ "method": "initialized", "params": {} }),
+        open(
+            allow,
+            "allow(actor: User, action, resource) if\n    has_role(actor, action, resource);\n",
+        ),
+        open(roles, "has_role(actor: User, role, resource) if actor.role = role;\nhas_role(_: User, _, _);\n"),
+        open(broken, "f(x) if x = ;\ng(x) if x = 1 +;\n"),
+        request("textDocument/definition", allow, Some((1, 6))),
+        request("textDocument/hover", allow, Some((1, 12))),
+        request("textDocument/hover", allow, Some((1, 14))),
+        request("textDocument/documentSymbol", roles, None),
+        request("textDocument/completion", allow, Some((1, 4))),
+        json!({ "method": "textDocument/didClose", "params": { "textDocument": { "uri": broken } } }),
+        json!({ "id": true, "method": "textDocument/formatting", "params": {} }),
+        json!({ "id": true, "method": "shutdown" }),
+        json!({ "method": "exit" }),
+    ]);
+
+    let capabilities = &result(&messages, 0)["capabilities"];
+    assert_eq!(capabilities["definitionProvider"], true);
+    assert_eq!(capabilities["textDocumentSync"], 1);
+
+    // The call to has_role is unknown until roles.polar is opened.
+    assert_eq!(
+        published(&messages, allow),
+        vec![vec!["W0003"], vec![], vec![], vec![]]
+    );
+    assert_eq!(
+        published(&messages, roles),
+        vec![vec!["W0001"], vec!["W0001"], vec!["W0001"]]
+    );
+    assert_eq!(
+        published(&messages, broken),
+        vec![vec!["E0005", "E0005"], vec![]]
+    );
+    let diagnostic = messages
+        .iter()
+        .find(|message| message["params"]["uri"] == broken)
+        .unwrap()["params"]["diagnostics"][1]
+        .clone();
+    assert_eq!(diagnostic["severity"], 1);
+    assert_eq!(
+        diagnostic["range"],
+        json!({ "start": { "line": 1, "character": 15 }, "end": { "line": 1, "character": 16 } })
+    );
+
+    assert_eq!(
+        result(&messages, 5),
+        &json!([
+            {
+                "uri": roles,
+                "range": { "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 8 } }
+            },
+            {
+                "uri": roles,
+                "range": { "start": { "line": 1, "character": 0 }, "end": { "line": 1, "character": 8 } }
+            },
+        ])
+    );
+    assert_eq!(
+        result(&messages, 6)["contents"]["value"],
+        "```polar\nhas_role(actor: User, role, resource)\nhas_role(_: User, _, _)\n```"
+    );
+    assert_eq!(result(&messages, 7), &Value::Null);
+
+    let symbols = result(&messages, 8).as_array().unwrap();
+    assert_eq!(symbols.len(), 2);
+    assert_eq!(symbols[0]["name"], "has_role");
+    assert_eq!(symbols[0]["kind"], 12);
+    assert_eq!(
+        symbols[0]["range"],
+        json!({ "start": { "line": 0, "character": 0 }, "end": { "line": 0, "character": 59 } })
+    );
+    assert_eq!(symbols[1]["selectionRange"]["start"]["line"], 1);
+
+    let completions: Vec<(&str, i64)> = result(&messages, 9)
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|item| {
+            (
+                item["label"].as_str().unwrap(),
+                item["kind"].as_i64().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        completions,
+        vec![("allow", 3), ("has_role", 3), ("User", 7)]
+    );
+
+    let unsupported = messages.iter().find(|message| message["id"] == 11).unwrap();
+    assert_eq!(unsupported["error"]["code"], -32601);
+    assert_eq!(result(&messages, 12), &Value::Null);
+}
+
+#[test]
+fn test_identical_documents() {
+    let first = "file:///first.polar";
+    let second = "file:///second.polar";
+    let text = "allow(actor, _action, _resource) if is_admin(actor);\n";
+    let messages = run(&[
+        json!({ "id": true, "method": "initialize", "params": {} }),
+        open(first, text),
+        open(second, text),
+        request("textDocument/documentSymbol", second, None),
+        json!({ "id": true, "method": "shutdown" }),
+        json!({ "method": "exit" }),
+    ]);
+
+    // The copy shares the diagnostics of the first document, without an
+    // error for loading the same text twice.
+    assert_eq!(
+        published(&messages, first),
+        vec![vec!["W0003"], vec!["W0003"]]
+    );
+    assert_eq!(published(&messages, second), vec![vec!["W0003"]]);
+    let symbols = result(&messages, 3).as_array().unwrap();
+    assert_eq!(symbols.len(), 1);
+    assert_eq!(symbols[0]["name"], "allow");
+}
+
+#[test]
+fn test_non_ascii_parse_error() {
+    let uri = "file:///café.polar";
+    let messages = run(&[
+        json!({ "id": true, "method": "initialize", "params": {} }),
+        open(uri, "# café\nf(x) if x = 1\n"),
+        json!({ "id": true, "method": "shutdown" }),
+        json!({ "method": "exit" }),
+    ]);
+
+    assert_eq!(published(&messages, uri), vec![vec!["E0004"]]);
+    let diagnostic = &messages
+        .iter()
+        .find(|message| message["method"] == "textDocument/publishDiagnostics")
+        .unwrap()["params"]["diagnostics"][0];
+    assert_eq!(
+        diagnostic["range"],
+        json!({ "start": { "line": 1, "character": 13 }, "end": { "line": 1, "character": 13 } })
+    );
+}
+
+#[test]
+fn test_non_ascii_symbols() {
+    let uri = "file:///café.polar";
+    let messages = run(&[
+        json!({ "id": true, "method": "initialize", "params": {} }),
+        open(uri, "# café\nallow(_, \"é\", _);\n"),
+        request("textDocument/documentSymbol", uri, None),
+        json!({ "id": true, "method": "shutdown" }),
+        json!({ "method": "exit" }),
+    ]);
+
+    let symbols = result(&messages, 2).as_array().unwrap();
+    assert_eq!(
+        symbols[0]["range"],
+        json!({ "start": { "line": 1, "character": 0 }, "end": { "line": 1, "character": 17 } })
+    );
+    assert_eq!(
+        symbols[0]["selectionRange"],
+        json!({ "start": { "line": 1, "character": 0 }, "end": { "line": 1, "character": 5 } })
+    );
+}
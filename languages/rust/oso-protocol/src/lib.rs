@@ -0,0 +1,97 @@
+//! Message framing shared by the Polar language server and debug adapter
+//!
+//! Both the [Language Server Protocol](https://microsoft.github.io/language-server-protocol/)
+//! and the [Debug Adapter Protocol](https://microsoft.github.io/debug-adapter-protocol/)
+//! send JSON messages preceded by a `Content-Length` header. The messages
+//! themselves are modeled by the `oso-lsp` and `oso-dap` crates.
+
+use std::io::{self, BufRead, Write};
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+/// Read one `Content-Length` framed message.
+///
+/// Returns `Ok(None)` when the input is closed.
+pub fn read_message<T: DeserializeOwned>(input: &mut dyn BufRead) -> io::Result<Option<T>> {
+    let mut content_length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            if content_length.is_some() {
+                break;
+            }
+            // Tolerate blank lines between messages.
+            continue;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            content_length = Some(value.trim().parse::<usize>().map_err(|_| {
+                io::Error::new(io::ErrorKind::InvalidData, "invalid Content-Length header")
+            })?);
+        }
+    }
+
+    let mut content = vec![0; content_length.unwrap()];
+    input.read_exact(&mut content)?;
+    serde_json::from_slice(&content)
+        .map(Some)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Write one `Content-Length` framed message.
+pub fn write_message<T: Serialize>(output: &mut dyn Write, message: &T) -> io::Result<()> {
+    let content = serde_json::to_string(message)?;
+    write!(
+        output,
+        "Content-Length: {}\r\n\r\n{}",
+        content.len(),
+        content
+    )?;
+    output.flush()
+}
+
+/// Helpers for tests that run a server on in-memory input and output.
+pub mod testing {
+    use std::cell::RefCell;
+    use std::io::{self, Cursor, Write};
+    use std::rc::Rc;
+
+    use serde::de::DeserializeOwned;
+    use serde::Serialize;
+
+    use super::{read_message, write_message};
+
+    /// Output sink that can be inspected after the server is done with it.
+    #[derive(Clone, Default)]
+    pub struct SharedBuffer(pub Rc<RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// Frame `messages` as server input.
+    pub fn frame<T: Serialize>(messages: &[T]) -> Vec<u8> {
+        let mut input = vec![];
+        for message in messages {
+            write_message(&mut input, message).unwrap();
+        }
+        input
+    }
+
+    /// Read back all the messages written to `output`.
+    pub fn unframe<T: DeserializeOwned>(output: &SharedBuffer) -> Vec<T> {
+        let output = output.0.borrow();
+        let mut input = Cursor::new(output.as_slice());
+        std::iter::from_fn(|| read_message(&mut input).unwrap()).collect()
+    }
+}
@@ -0,0 +1,63 @@
+//! Hooks for driving the Polar debugger from the application.
+
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex};
+
+/// Receives messages from the Polar debugger and decides what to do next.
+///
+/// A query pauses in the debugger when it reaches `debug()` in a policy, a
+/// breakpoint, or the end of a step. The debugger output is passed to
+/// [`Debugger::debug`], which returns the next debugger command, such as
+/// `"step"`, `"stack"` or `"continue"`. Informational commands produce another
+/// message, and the query stays paused until a command like `"continue"` resumes it.
+///
+/// Install a debugger with [`Oso::set_debugger`](crate::Oso::set_debugger) for all
+/// queries, or [`Query::set_debugger`](crate::Query::set_debugger) for a single query.
+/// Closures of type `FnMut(&str) -> String` implement this trait.
+pub trait Debugger {
+    /// Handle a message from the debugger and return the next command.
+    fn debug(&mut self, message: &str) -> String;
+}
+
+impl<F> Debugger for F
+where
+    F: FnMut(&str) -> String,
+{
+    fn debug(&mut self, message: &str) -> String {
+        self(message)
+    }
+}
+
+/// Debugger that prints messages to stdout and reads commands from stdin.
+///
+/// Used by the `oso` REPL. Closing stdin continues the query.
+#[derive(Clone, Debug, Default)]
+pub struct StdioDebugger;
+
+impl Debugger for StdioDebugger {
+    fn debug(&mut self, message: &str) -> String {
+        if !message.is_empty() {
+            println!("{}", message);
+        }
+        print!("debug> ");
+        let _ = io::stdout().flush();
+        let mut command = String::new();
+        match io::stdin().lock().read_line(&mut command) {
+            Ok(0) | Err(_) => "continue".to_string(),
+            Ok(_) => command.trim().trim_end_matches(';').to_string(),
+        }
+    }
+}
+
+/// A debugger installed on an `Oso` instance, shared by its queries.
+#[derive(Clone)]
+pub(crate) struct SharedDebugger(pub Arc<Mutex<dyn Debugger + Send>>);
+
+impl Debugger for SharedDebugger {
+    fn debug(&mut self, message: &str) -> String {
+        self.0
+            .lock()
+            .expect("debugger lock poisoned")
+            .debug(message)
+    }
+}
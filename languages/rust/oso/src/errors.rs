@@ -45,6 +45,9 @@ pub enum OsoError {
     #[error("failed to convert type to Polar")]
     ToPolar,
 
+    #[error("Invalid test case at {location}: {message}")]
+    InvalidTestCase { location: String, message: String },
+
     #[error("Class {name} already registered")]
     DuplicateClassError { name: String },
 
@@ -86,6 +89,15 @@ impl OsoError {
             attr.replace(name);
         }
     }
+
+    /// The structured diagnostic of an error from Polar, with its code and
+    /// the location of the policy source it refers to.
+    pub fn diagnostic(&self) -> Option<polar_core::diagnostic::Diagnostic> {
+        match self {
+            Self::Polar(e) => Some(e.diagnostic()),
+            _ => None,
+        }
+    }
 }
 
 /// These are conditions that should never occur, and indicate a bug in oso.
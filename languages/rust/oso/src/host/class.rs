@@ -1,15 +1,17 @@
 //! Support for dynamic class objects in Rust
 
-use polar_core::terms::Term;
+use polar_core::terms::{Symbol, Term};
 
 use std::any::TypeId;
-use std::collections::HashMap;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
 use std::sync::Arc;
 
-use crate::errors::{InvalidCallError, OsoError};
+use crate::errors::{InvalidCallError, OsoError, TypeError};
 
 use super::class_method::{AttributeGetter, ClassMethod, Constructor, InstanceMethod};
+use super::dynamic::DynamicInstance;
 use super::from_polar::FromPolarList;
 use super::method::{Function, Method};
 use super::to_polar::ToPolarResults;
@@ -18,6 +20,9 @@ use super::Host;
 type Attributes = HashMap<&'static str, AttributeGetter>;
 type ClassMethods = HashMap<&'static str, ClassMethod>;
 type InstanceMethods = HashMap<&'static str, InstanceMethod>;
+type Constants = HashMap<&'static str, Arc<dyn Fn(&mut Host) -> Term + Send + Sync>>;
+type ComparisonCheck =
+    Arc<dyn Fn(&Host, &Instance, &Instance) -> crate::Result<Option<Ordering>> + Send + Sync>;
 
 fn equality_not_supported(
 ) -> Box<dyn Fn(&Host, &Instance, &Instance) -> crate::Result<bool> + Send + Sync> {
@@ -31,28 +36,57 @@ fn equality_not_supported(
     Box::new(eq)
 }
 
+fn comparison_not_supported() -> ComparisonCheck {
+    Arc::new(|host: &Host, lhs: &Instance, _: &Instance| {
+        Err(OsoError::UnsupportedOperation {
+            operation: String::from("comparison"),
+            type_name: lhs.name(host).to_owned(),
+        })
+    })
+}
+
 #[derive(Clone)]
 pub struct Class {
     /// The class name. Defaults to the `std::any::type_name`
     pub name: String,
     pub type_id: TypeId,
     /// A wrapped method that constructs an instance of `T` from Polar terms
-    constructor: Option<Constructor>,
+    pub(super) constructor: Option<Constructor>,
     /// Methods that return simple attribute lookups on an instance of `T`
     attributes: Attributes,
     /// Instance methods on `T` that expect Polar terms, and an instance of `&T`
     instance_methods: InstanceMethods,
     /// Class methods on `T`
     class_methods: ClassMethods,
+    /// Constants on the class, like `Foo.BAR`
+    constants: Constants,
 
     /// A method to check whether the supplied `TypeId` matches this class
     /// (This isn't using `type_id` because we might want to register other types here
     /// in order to check inheritance)
     class_check: Arc<dyn Fn(TypeId) -> bool + Send + Sync>,
 
+    /// Names of the classes this class is a subclass of, most specific first.
+    superclasses: Vec<String>,
+
+    /// Parameter names of the constructor, for keyword arguments.
+    constructor_params: Option<Vec<&'static str>>,
+    /// Parameter names of instance methods, for keyword arguments.
+    method_params: HashMap<&'static str, Vec<&'static str>>,
+    /// Parameter names of class methods, for keyword arguments.
+    class_method_params: HashMap<&'static str, Vec<&'static str>>,
+
+    /// The fields of a dynamic class, which are the attributes of its instances.
+    pub(super) fields: Vec<String>,
+
     /// A function that accepts arguments of this class and compares them for equality.
     /// Limitation: Only works on comparisons of the same type.
-    equality_check: Arc<dyn Fn(&Host, &Instance, &Instance) -> crate::Result<bool> + Send + Sync>,
+    pub(super) equality_check:
+        Arc<dyn Fn(&Host, &Instance, &Instance) -> crate::Result<bool> + Send + Sync>,
+
+    /// A function that orders two arguments of this class, for the `<`, `<=`,
+    /// `>` and `>=` operators. Returns `None` if they can't be ordered.
+    comparison_check: ComparisonCheck,
 }
 
 impl Class {
@@ -70,6 +104,43 @@ impl Class {
         }
     }
 
+    /// The arguments to call the constructor with, given positional `args`
+    /// and keyword arguments `kwargs`.
+    pub fn constructor_args(
+        &self,
+        args: Vec<Term>,
+        kwargs: BTreeMap<Symbol, Term>,
+    ) -> crate::Result<Vec<Term>> {
+        let callee = format!("{} constructor", self.name);
+        bind_kwargs(&callee, self.constructor_params.as_deref(), args, kwargs)
+    }
+
+    /// The arguments to call the instance method `name` with, given
+    /// positional `args` and keyword arguments `kwargs`.
+    pub fn method_args(
+        &self,
+        name: &str,
+        args: Vec<Term>,
+        kwargs: BTreeMap<Symbol, Term>,
+    ) -> crate::Result<Vec<Term>> {
+        let callee = format!("{}.{}", self.name, name);
+        let params = self.method_params.get(name).map(Vec::as_slice);
+        bind_kwargs(&callee, params, args, kwargs)
+    }
+
+    /// The arguments to call the class method `name` with, given positional
+    /// `args` and keyword arguments `kwargs`.
+    pub fn class_method_args(
+        &self,
+        name: &str,
+        args: Vec<Term>,
+        kwargs: BTreeMap<Symbol, Term>,
+    ) -> crate::Result<Vec<Term>> {
+        let callee = format!("{}.{}", self.name, name);
+        let params = self.class_method_params.get(name).map(Vec::as_slice);
+        bind_kwargs(&callee, params, args, kwargs)
+    }
+
     /// Call class method `attr` on `self` with arguments from `args`.
     ///
     /// Returns: An iterable of results from the method.
@@ -90,6 +161,11 @@ impl Class {
         attr.clone().invoke(args, host)
     }
 
+    /// Names of the classes this class is a subclass of.
+    pub fn superclasses(&self) -> &[String] {
+        &self.superclasses
+    }
+
     fn get_method(&self, name: &str) -> Option<InstanceMethod> {
         tracing::trace!({class=%self.name, name}, "get_method");
         if self.type_id == TypeId::of::<Class>() {
@@ -103,6 +179,15 @@ impl Class {
     fn equals(&self, host: &Host, lhs: &Instance, rhs: &Instance) -> crate::Result<bool> {
         (self.equality_check)(host, lhs, rhs)
     }
+
+    fn compare(
+        &self,
+        host: &Host,
+        lhs: &Instance,
+        rhs: &Instance,
+    ) -> crate::Result<Option<Ordering>> {
+        (self.comparison_check)(host, lhs, rhs)
+    }
 }
 
 #[derive(Clone)]
@@ -127,8 +212,15 @@ where
                 attributes: HashMap::new(),
                 instance_methods: InstanceMethods::new(),
                 class_methods: ClassMethods::new(),
+                constants: Constants::new(),
                 class_check: Arc::new(|type_id| TypeId::of::<T>() == type_id),
+                superclasses: vec![],
+                constructor_params: None,
+                method_params: HashMap::new(),
+                class_method_params: HashMap::new(),
+                fields: vec![],
                 equality_check: Arc::from(equality_not_supported()),
+                comparison_check: comparison_not_supported(),
                 type_id: TypeId::of::<T>(),
             },
             ty: std::marker::PhantomData,
@@ -167,6 +259,27 @@ where
         self
     }
 
+    /// Name the parameters of the constructor, in order, so that it can be
+    /// called with keyword arguments, like `new Foo(x: 1)`.
+    pub fn set_constructor_params(mut self, params: &[&'static str]) -> Self {
+        self.class.constructor_params = Some(params.to_vec());
+        self
+    }
+
+    /// Name the parameters of the method `name`, in order, so that it can be
+    /// called with keyword arguments, like `foo.bar(x: 1)`.
+    pub fn set_method_params(mut self, name: &'static str, params: &[&'static str]) -> Self {
+        self.class.method_params.insert(name, params.to_vec());
+        self
+    }
+
+    /// Name the parameters of the class method `name`, in order, so that it
+    /// can be called with keyword arguments, like `Foo.bar(x: 1)`.
+    pub fn set_class_method_params(mut self, name: &'static str, params: &[&'static str]) -> Self {
+        self.class.class_method_params.insert(name, params.to_vec());
+        self
+    }
+
     /// Set an equality function to be used for polar `==` statements.
     pub fn set_equality_check<F>(mut self, f: F) -> Self
     where
@@ -192,6 +305,32 @@ where
         self.set_equality_check(|a, b| PartialEq::eq(a, b))
     }
 
+    /// Set an ordering function to be used for polar `<`, `<=`, `>` and `>=`
+    /// statements. Arguments it returns `None` for satisfy none of them.
+    pub fn set_comparison_check<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&T, &T) -> Option<Ordering> + Send + Sync + 'static,
+    {
+        self.class.comparison_check = Arc::new(move |host, a, b| {
+            tracing::trace!("comparison check");
+
+            let a = a.downcast(Some(host)).map_err(|e| e.user())?;
+            let b = b.downcast(Some(host)).map_err(|e| e.user())?;
+
+            Ok((f)(a, b))
+        });
+
+        self
+    }
+
+    /// Use PartialOrd::partial_cmp as the ordering for polar comparisons.
+    pub fn with_comparison_check(self) -> Self
+    where
+        T: PartialOrd<T>,
+    {
+        self.set_comparison_check(|a, b| PartialOrd::partial_cmp(a, b))
+    }
+
     /// Add an attribute getter for statments like `foo.bar`
     /// `class.add_attribute_getter("bar", |instance| instance.bar)
     pub fn add_attribute_getter<F, R>(mut self, name: &'static str, f: F) -> Self
@@ -204,6 +343,17 @@ where
         self
     }
 
+    /// Declare the class as a subclass of the class registered as `name`.
+    ///
+    /// Instances of the class are then also instances of that class and
+    /// its superclasses, and rules specialized on the class are more
+    /// specific than those specialized on its superclasses. `name` need not
+    /// be registered yet. Superclasses added first are more specific.
+    pub fn add_superclass(mut self, name: &str) -> Self {
+        self.class.superclasses.push(name.to_string());
+        self
+    }
+
     /// Set the name of the polar class.
     pub fn name(mut self, name: &str) -> Self {
         self.class.name = name.to_string();
@@ -253,12 +403,72 @@ where
         self
     }
 
+    /// A constant on the class, for statements like `Foo.BAR`.
+    pub fn add_constant<V>(mut self, name: &'static str, value: V) -> Self
+    where
+        V: crate::ToPolar + Clone,
+    {
+        self.class
+            .constants
+            .insert(name, Arc::new(move |host| value.clone().to_polar(host)));
+        self
+    }
+
     /// Finish building a build the class
     pub fn build(self) -> Class {
         self.class
     }
 }
 
+/// Place keyword arguments after the positional arguments, in the order of
+/// the named parameters `params`.
+fn bind_kwargs(
+    callee: &str,
+    params: Option<&[&'static str]>,
+    mut args: Vec<Term>,
+    mut kwargs: BTreeMap<Symbol, Term>,
+) -> crate::Result<Vec<Term>> {
+    if kwargs.is_empty() {
+        return Ok(args);
+    }
+    let params = match params {
+        Some(params) => params,
+        None => {
+            return lazy_error!(
+                "Invalid call error: {} has no named parameters for keyword arguments.",
+                callee
+            )
+        }
+    };
+    for (i, param) in params.iter().enumerate() {
+        if i < args.len() {
+            if kwargs.contains_key(&Symbol(param.to_string())) {
+                return lazy_error!(
+                    "Invalid call error: argument {} of {} is given twice.",
+                    param,
+                    callee
+                );
+            }
+        } else if let Some(arg) = kwargs.remove(&Symbol(param.to_string())) {
+            args.push(arg);
+        } else {
+            return lazy_error!(
+                "Invalid call error: missing argument {} of {}.",
+                param,
+                callee
+            );
+        }
+    }
+    match kwargs.keys().next() {
+        Some(name) => lazy_error!(
+            "Invalid call error: {} has no parameter named {}.",
+            callee,
+            name
+        ),
+        None => Ok(args),
+    }
+}
+
 /// Container for an instance of a `Class`
 ///
 /// Not guaranteed to be an instance of a registered class,
@@ -294,14 +504,21 @@ impl Instance {
 
     /// Check whether this is an instance of `class`
     pub fn instance_of(&self, class: &Class) -> bool {
-        self.inner.as_ref().type_id() == class.type_id
+        match self.inner.downcast_ref::<DynamicInstance>() {
+            // All dynamic classes have the same type, so match by name.
+            Some(instance) => class.is_dynamic() && class.name == instance.class_name(),
+            None => self.inner.as_ref().type_id() == class.type_id,
+        }
     }
 
     /// Looks up the `Class` for this instance on the provided `host`
     pub fn class<'a>(&self, host: &'a Host) -> crate::Result<&'a Class> {
+        if let Some(instance) = self.inner.downcast_ref::<DynamicInstance>() {
+            return host.get_class(&Symbol(instance.class_name().to_owned()));
+        }
         host.get_class_by_type_id(self.inner.as_ref().type_id())
             .map_err(|_| OsoError::MissingClassError {
-                name: self.name(&host).to_owned(),
+                name: self.debug_type_name.to_owned(),
             })
     }
 
@@ -318,6 +535,20 @@ impl Instance {
     /// Lookup an attribute on the instance via the registered `Class`
     pub fn get_attr(&self, name: &str, host: &mut Host) -> crate::Result<Term> {
         tracing::trace!({ method = %name }, "get_attr");
+        if let Some(constant) = self
+            .inner
+            .downcast_ref::<Class>()
+            .and_then(|class| class.constants.get(name))
+        {
+            return Ok(constant(host));
+        }
+        if let Some(instance) = self.inner.downcast_ref::<DynamicInstance>() {
+            // Only the declared fields of a dynamic class are attributes.
+            let declared = self.class(host)?.fields.iter().any(|field| field == name);
+            if let Some(value) = instance.get(name).filter(|_| declared).cloned() {
+                return Ok(value.to_term(host));
+            }
+        }
         let attr = self
             .class(host)
             .and_then(|c| {
@@ -361,10 +592,28 @@ impl Instance {
     /// Return `true` if the `instance` of self equals the instance of `other`.
     pub fn equals(&self, other: &Self, host: &Host) -> crate::Result<bool> {
         tracing::trace!("equals");
-        self.class(host)
+        self.comparable_class(host)
             .and_then(|class| class.equals(host, &self, other))
     }
 
+    /// Order the instance of self and the instance of `other`, or return `None`
+    /// if they are unordered.
+    pub fn compare(&self, other: &Self, host: &Host) -> crate::Result<Option<Ordering>> {
+        tracing::trace!("compare");
+        self.comparable_class(host)
+            .and_then(|class| class.compare(host, self, other))
+    }
+
+    /// The class that compares this instance, or a type error if its type
+    /// isn't registered, like a JSON `null` or an integer too large for Polar.
+    fn comparable_class<'a>(&self, host: &'a Host) -> crate::Result<&'a Class> {
+        self.class(host).map_err(|_| {
+            TypeError::expected("an instance of a registered class")
+                .got(self.debug_type_name)
+                .user()
+        })
+    }
+
     /// Attempt to downcast the inner type of the instance to a reference to the type `T`
     /// This should be the _only_ place using downcast to avoid mistakes.
     ///
@@ -34,6 +34,14 @@ impl Constructor {
         }))
     }
 
+    /// A constructor that builds the instance from the arguments itself.
+    pub fn from_fn<F>(f: F) -> Self
+    where
+        F: Fn(Vec<Term>, &mut Host) -> crate::Result<Instance> + Send + Sync + 'static,
+    {
+        Constructor(Arc::new(f))
+    }
+
     pub fn invoke(&self, args: Vec<Term>, host: &mut Host) -> crate::Result<Instance> {
         self.0(args, host)
     }
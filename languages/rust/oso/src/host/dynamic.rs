@@ -0,0 +1,175 @@
+//! Classes described by data rather than by Rust types.
+//!
+//! A dynamic class is a name and a list of fields, and its instances are
+//! records of values for those fields, e.g. built from JSON documents or
+//! database rows.
+
+use polar_core::terms::{ExternalInstance, Term, Value};
+
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::class::{Class, Instance};
+use super::class_method::Constructor;
+use super::{Host, PolarValue, ToPolar};
+use crate::errors::TypeError;
+
+/// An instance of a dynamic class: the name of its class, and the values
+/// of its fields.
+///
+/// Dynamic instances belong to the dynamic class registered with the same
+/// name, and the fields declared by that class are their attributes. Two
+/// instances are equal if they have the same class and equal fields.
+#[derive(Clone, Debug)]
+pub struct DynamicInstance {
+    class: String,
+    fields: HashMap<String, PolarValue>,
+}
+
+impl DynamicInstance {
+    pub fn new(class: &str, fields: HashMap<String, PolarValue>) -> Self {
+        Self {
+            class: class.to_string(),
+            fields,
+        }
+    }
+
+    /// Make an instance of `class` from a JSON object.
+    ///
+    /// Fields that are `null` are left out, since Polar has no null value.
+    /// Nested objects are dictionaries, and arrays are lists.
+    ///
+    /// Requires the `json` feature.
+    #[cfg(feature = "json")]
+    pub fn from_json(class: &str, value: &serde_json::Value) -> crate::Result<Self> {
+        let object = value.as_object().ok_or_else(|| {
+            TypeError::expected("JSON object")
+                .got(value.to_string())
+                .user()
+        })?;
+        let mut fields = HashMap::new();
+        for (field, value) in object {
+            if !value.is_null() {
+                fields.insert(field.clone(), PolarValue::from_json(value));
+            }
+        }
+        Ok(Self::new(class, fields))
+    }
+
+    /// The name of the class of this instance.
+    pub fn class_name(&self) -> &str {
+        &self.class
+    }
+
+    pub fn get(&self, field: &str) -> Option<&PolarValue> {
+        self.fields.get(field)
+    }
+
+    pub fn fields(&self) -> &HashMap<String, PolarValue> {
+        &self.fields
+    }
+}
+
+/// Compare field values, comparing instances with their class's equality.
+fn values_equal(host: &Host, left: &PolarValue, right: &PolarValue) -> crate::Result<bool> {
+    use PolarValue::*;
+    Ok(match (left, right) {
+        (Integer(l), Integer(r)) => l == r,
+        (Float(l), Float(r)) => l == r,
+        (Integer(i), Float(f)) | (Float(f), Integer(i)) => *i as f64 == *f,
+        (String(l), String(r)) => l == r,
+        (Boolean(l), Boolean(r)) => l == r,
+        (List(l), List(r)) => {
+            if l.len() != r.len() {
+                return Ok(false);
+            }
+            for (l, r) in l.iter().zip(r) {
+                if !values_equal(host, l, r)? {
+                    return Ok(false);
+                }
+            }
+            true
+        }
+        (Map(l), Map(r)) => maps_equal(host, l, r)?,
+        (Instance(l), Instance(r)) => l.equals(r, host)?,
+        _ => false,
+    })
+}
+
+fn maps_equal(
+    host: &Host,
+    left: &HashMap<String, PolarValue>,
+    right: &HashMap<String, PolarValue>,
+) -> crate::Result<bool> {
+    if left.len() != right.len() {
+        return Ok(false);
+    }
+    for (key, l) in left {
+        match right.get(key) {
+            Some(r) if values_equal(host, l, r)? => continue,
+            _ => return Ok(false),
+        }
+    }
+    Ok(true)
+}
+
+fn instances_equal(host: &Host, left: &Instance, right: &Instance) -> crate::Result<bool> {
+    let left: &DynamicInstance = left.downcast(Some(host)).map_err(|e| e.user())?;
+    match right.downcast::<DynamicInstance>(Some(host)) {
+        Ok(right) if left.class == right.class => maps_equal(host, &left.fields, &right.fields),
+        _ => Ok(false),
+    }
+}
+
+impl ToPolar for DynamicInstance {
+    fn to_polar_value(self, host: &mut Host) -> Value {
+        let repr = Some(self.class.clone());
+        let instance_id = host.cache_instance(Instance::new(self), None);
+        Value::ExternalInstance(ExternalInstance {
+            constructor: None,
+            repr,
+            instance_id,
+        })
+    }
+}
+
+impl Class {
+    /// Create a class of [`DynamicInstance`]s with the given fields.
+    ///
+    /// Instances of the class are found by name: a [`DynamicInstance`] with
+    /// the class name `name` is an instance of this class once it is
+    /// registered, and its values for `fields` are its attributes.
+    ///
+    /// `new Name(a, b)` in a policy makes an instance with the fields set to
+    /// the arguments, in order.
+    pub fn dynamic(name: &str, fields: &[&str]) -> Self {
+        let class_name = name.to_string();
+        let fields: Vec<String> = fields.iter().map(|field| field.to_string()).collect();
+        let constructor_fields = fields.clone();
+        let constructor = Constructor::from_fn(move |args: Vec<Term>, host: &mut Host| {
+            let fields = &constructor_fields;
+            if args.len() != fields.len() {
+                return Err(TypeError::expected(format!("{} arguments", fields.len()))
+                    .got(format!("{} arguments", args.len()))
+                    .user());
+            }
+            let values = args
+                .iter()
+                .map(|arg| PolarValue::from_term(arg, host))
+                .collect::<crate::Result<Vec<_>>>()?;
+            let fields = fields.iter().cloned().zip(values).collect();
+            Ok(Instance::new(DynamicInstance::new(&class_name, fields)))
+        });
+        let mut class = Class::builder::<DynamicInstance>().name(name).build();
+        class.constructor = Some(constructor);
+        class.fields = fields;
+        class.equality_check = Arc::new(instances_equal);
+        class
+    }
+
+    /// Whether this is a class of [`DynamicInstance`]s.
+    pub fn is_dynamic(&self) -> bool {
+        self.type_id == TypeId::of::<DynamicInstance>()
+    }
+}
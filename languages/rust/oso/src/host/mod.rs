@@ -1,3 +1,4 @@
+use std::cmp::Ordering;
 use std::collections::HashMap;
 use std::sync::Arc;
 
@@ -8,15 +9,21 @@ use crate::Polar;
 
 mod class;
 mod class_method;
+mod dynamic;
 mod from_polar;
 mod method;
+#[cfg(feature = "serde")]
+mod serialize;
 mod to_polar;
 mod value;
 
 pub use value::*;
 
 pub use class::{Class, ClassBuilder, Instance};
+pub use dynamic::DynamicInstance;
 pub use from_polar::{FromPolar, FromPolarList};
+#[cfg(feature = "serde")]
+pub use serialize::Deserialized;
 pub use to_polar::{PolarResultIter, ToPolar, ToPolarList, ToPolarResults};
 
 impl ToPolar for crate::Class {}
@@ -94,7 +101,10 @@ impl Host {
             return Err(OsoError::DuplicateClassError { name: name.0 });
         }
 
-        self.class_names.insert(class.type_id, name.clone());
+        // Dynamic classes share a type, and are found by name instead.
+        if !class.is_dynamic() {
+            self.class_names.insert(class.type_id, name.clone());
+        }
         self.classes.insert(name.clone(), class);
         Ok(name.0)
     }
@@ -147,6 +157,10 @@ impl Host {
                 let class = self.get_class(class_tag)?;
                 let instance = self.get_instance(*instance_id)?;
                 instance.instance_of(class)
+                    || instance
+                        .class(self)
+                        .map(|class| self.mro(class).contains(name))
+                        .unwrap_or(false)
             }
             terms::Value::Boolean(_) => name == "Boolean",
             terms::Value::Dictionary(_) => name == "Dictionary",
@@ -164,16 +178,64 @@ impl Host {
         Ok(res)
     }
 
-    pub fn is_subspecializer(&self, _id: u64, _left_tag: &Symbol, _right_tag: &Symbol) -> bool {
-        // Rust has no notion of inheritance, so there are no subspecializers.
-        false
+    /// The names of `class` and its superclasses, from most to least
+    /// specific: each class comes before its superclasses, which are
+    /// visited depth first in the order they were added.
+    pub fn mro(&self, class: &Class) -> Vec<String> {
+        fn visit(host: &Host, name: &str, superclasses: &[String], mro: &mut Vec<String>) {
+            if mro.iter().any(|visited| visited == name) {
+                return;
+            }
+            mro.push(name.to_string());
+            for superclass in superclasses {
+                let parents = host
+                    .get_class(&Symbol(superclass.clone()))
+                    .map(|class| class.superclasses())
+                    .unwrap_or_default();
+                visit(host, superclass, parents, mro);
+            }
+        }
+
+        let mut mro = vec![];
+        visit(self, &class.name, class.superclasses(), &mut mro);
+        mro
+    }
+
+    /// Whether `left_tag` is more specific than `right_tag` for the instance
+    /// `id`, i.e. comes before it in the MRO of the instance's class.
+    pub fn is_subspecializer(&self, id: u64, left_tag: &Symbol, right_tag: &Symbol) -> bool {
+        let class = match self
+            .get_instance(id)
+            .and_then(|instance| instance.class(self))
+        {
+            Ok(class) => class,
+            Err(_) => return false,
+        };
+        let mro = self.mro(class);
+        let position = |tag: &Symbol| mro.iter().position(|name| name == &tag.0);
+        match (position(left_tag), position(right_tag)) {
+            (Some(left), Some(right)) => left < right,
+            _ => false,
+        }
     }
 
-    pub fn operator(&self, _op: Operator, _args: [class::Instance; 2]) -> crate::Result<bool> {
-        // Operators are not supported
-        // TODO (dhatch): Implement.
-        Err(OsoError::UnimplementedOperation {
-            operation: String::from("comparison operators"),
-        })
+    pub fn operator(&self, op: Operator, args: [class::Instance; 2]) -> crate::Result<bool> {
+        let [left, right] = &args;
+        match op {
+            Operator::Eq => left.equals(right, self),
+            Operator::Neq => left.equals(right, self).map(|equal| !equal),
+            Operator::Lt | Operator::Leq | Operator::Gt | Operator::Geq => {
+                Ok(match (op, left.compare(right, self)?) {
+                    (_, None) => false,
+                    (Operator::Lt, Some(ordering)) => ordering == Ordering::Less,
+                    (Operator::Leq, Some(ordering)) => ordering != Ordering::Greater,
+                    (Operator::Gt, Some(ordering)) => ordering == Ordering::Greater,
+                    (_, Some(ordering)) => ordering != Ordering::Less,
+                })
+            }
+            _ => Err(OsoError::UnimplementedOperation {
+                operation: format!("{:?} operators", op),
+            }),
+        }
     }
 }
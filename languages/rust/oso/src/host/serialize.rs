@@ -0,0 +1,88 @@
+//! Conversions between Polar values and types that implement serde's
+//! `Serialize` and `Deserialize`, by way of JSON values.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use std::convert::TryFrom;
+
+use super::{FromPolarValue, PolarValue};
+use crate::errors::TypeError;
+
+impl PolarValue {
+    /// Convert a `Serialize` value, like a request payload, to pass it to a
+    /// query. Structs and maps become dictionaries, and sequences become
+    /// lists. Fields that serialize to `null`, like `None`, are left out.
+    ///
+    /// ```
+    /// # fn main() -> anyhow::Result<()> {
+    /// use oso::{Oso, PolarValue};
+    /// use serde_json::json;
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.load_str(r#"allow(_, "update", request) if request.owner = "alice";"#)?;
+    ///
+    /// let request = json!({"owner": "alice", "title": "Budget"});
+    /// let args = ("alice", "update", PolarValue::serialize(&request)?);
+    /// assert!(oso.query_rule("allow", args)?.next().is_some());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn serialize<T: Serialize + ?Sized>(value: &T) -> crate::Result<Self> {
+        let json = serde_json::to_value(value).map_err(|e| {
+            TypeError::expected("serializable value")
+                .got(e.to_string())
+                .user()
+        })?;
+        Ok(Self::from_json(&json))
+    }
+
+    /// Convert to a `Deserialize` type, like a configuration struct.
+    pub fn deserialize<T: DeserializeOwned>(self) -> crate::Result<T> {
+        let json = self.to_json()?;
+        serde_json::from_value(json).map_err(|e| {
+            TypeError::expected(std::any::type_name::<T>())
+                .got(e.to_string())
+                .user()
+        })
+    }
+}
+
+/// A `Deserialize` value read from a query result with
+/// `ResultSet::get_typed`:
+///
+/// ```
+/// # fn main() -> anyhow::Result<()> {
+/// use oso::{Deserialized, Oso};
+/// use serde::Deserialize;
+///
+/// #[derive(Clone, Deserialize)]
+/// struct Limits {
+///     max_uploads: i64,
+/// }
+///
+/// let mut oso = Oso::new();
+/// oso.load_str(r#"limits("free", {max_uploads: 3});"#)?;
+///
+/// let result = oso.query(r#"limits("free", limits)"#)?.next().unwrap()?;
+/// let Deserialized(limits) = result.get_typed::<Deserialized<Limits>>("limits")?;
+/// assert_eq!(limits.max_uploads, 3);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Deserialized<T>(pub T);
+
+impl<T: DeserializeOwned + Clone + 'static> FromPolarValue for Deserialized<T> {
+    fn from_polar_value(val: PolarValue) -> crate::Result<Self> {
+        val.deserialize().map(Deserialized)
+    }
+}
+
+impl<T: DeserializeOwned + Clone + 'static> TryFrom<PolarValue> for Deserialized<T> {
+    type Error = crate::OsoError;
+
+    fn try_from(v: PolarValue) -> Result<Self, Self::Error> {
+        Self::from_polar_value(v)
+    }
+}
@@ -4,8 +4,10 @@
 use impl_trait_for_tuples::*;
 use polar_core::terms::*;
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
 use std::iter;
+use std::sync::Arc;
 
 use super::Host;
 use crate::host::Instance;
@@ -32,13 +34,7 @@ use crate::PolarValue;
 /// any borrows.
 pub trait ToPolar: Send + Sync + Sized + 'static {
     fn to_polar_value(self, host: &mut Host) -> Value {
-        let instance = Instance::new(self);
-        let instance = host.cache_instance(instance, None);
-        Value::ExternalInstance(ExternalInstance {
-            constructor: None,
-            repr: Some(std::any::type_name::<Self>().to_owned()),
-            instance_id: instance,
-        })
+        instance_value(self, host)
     }
 
     fn to_polar(self, host: &mut Host) -> Term {
@@ -46,6 +42,17 @@ pub trait ToPolar: Send + Sync + Sized + 'static {
     }
 }
 
+/// Store `value` on the host as an instance.
+fn instance_value<T: Send + Sync + 'static>(value: T, host: &mut Host) -> Value {
+    let instance = Instance::new(value);
+    let instance = host.cache_instance(instance, None);
+    Value::ExternalInstance(ExternalInstance {
+        constructor: None,
+        repr: Some(std::any::type_name::<T>().to_owned()),
+        instance_id: instance,
+    })
+}
+
 impl<C: crate::PolarClass + Send + Sync> ToPolar for C {
     fn to_polar_value(self, host: &mut Host) -> Value {
         let instance = Instance::new(self);
@@ -125,6 +132,24 @@ int_to_polar!(u32);
 int_to_polar!(i32);
 int_to_polar!(i64);
 
+// Values too large for Polar integers are passed as instances, so that they
+// convert back unchanged.
+macro_rules! unsigned_to_polar {
+    ($i:ty) => {
+        impl ToPolar for $i {
+            fn to_polar_value(self, host: &mut Host) -> Value {
+                match i64::try_from(self) {
+                    Ok(i) => Value::Number(Numeric::Integer(i)),
+                    Err(_) => instance_value(self, host),
+                }
+            }
+        }
+    };
+}
+
+unsigned_to_polar!(u64);
+unsigned_to_polar!(usize);
+
 macro_rules! float_to_polar {
     ($i:ty) => {
         impl ToPolar for $i {
@@ -167,6 +192,50 @@ impl<T: ToPolar> ToPolar for HashMap<String, T> {
     }
 }
 
+impl<T: ToPolar> ToPolar for BTreeMap<String, T> {
+    fn to_polar_value(self, host: &mut Host) -> Value {
+        Value::Dictionary(Dictionary {
+            fields: self
+                .into_iter()
+                .map(|(k, v)| (Symbol(k), v.to_polar(host)))
+                .collect(),
+        })
+    }
+}
+
+impl<T: ToPolar> ToPolar for HashSet<T> {
+    fn to_polar_value(self, host: &mut Host) -> Value {
+        Value::List(self.into_iter().map(|v| v.to_polar(host)).collect())
+    }
+}
+
+#[impl_for_tuples(1, 16)]
+#[tuple_types_custom_trait_bound(ToPolar)]
+impl ToPolar for Tuple {
+    fn to_polar_value(self, host: &mut Host) -> Value {
+        Value::List(self.to_polar_list(host))
+    }
+}
+
+// `Box<T>` can't be `ToPolar`, since other crates may make it a `PolarClass`.
+// `Option<T>` can't be either: every `ToPolar` type is `ToPolarResults`, and
+// `Option` already is, so that methods returning `None` have no results.
+impl<T: ToPolar + Clone> ToPolar for Arc<T> {
+    fn to_polar_value(self, host: &mut Host) -> Value {
+        Arc::try_unwrap(self)
+            .unwrap_or_else(|arc| (*arc).clone())
+            .to_polar_value(host)
+    }
+}
+
+/// Converted with `PolarValue::from_json`.
+#[cfg(feature = "json")]
+impl ToPolar for serde_json::Value {
+    fn to_polar_value(self, host: &mut Host) -> Value {
+        PolarValue::from_json(&self).to_polar_value(host)
+    }
+}
+
 impl ToPolar for PolarValue {
     fn to_polar_value(self, host: &mut Host) -> Value {
         self.to_term(host).value().clone()
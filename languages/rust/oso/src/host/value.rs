@@ -1,6 +1,10 @@
 use std::collections::hash_map::HashMap;
+use std::collections::{BTreeMap, HashSet};
 use std::convert::TryFrom;
+use std::hash::Hash;
+use std::sync::Arc;
 
+use impl_trait_for_tuples::*;
 use polar_core::terms::*;
 
 use crate::host::Host;
@@ -83,7 +87,7 @@ impl PolarValue {
                 let id = host.cache_instance(instance.clone(), None);
                 Value::ExternalInstance(ExternalInstance {
                     constructor: None,
-                    repr: Some(std::any::type_name::<Self>().to_owned()),
+                    repr: Some(instance.name(host).to_owned()),
                     instance_id: id,
                 })
             }
@@ -98,6 +102,95 @@ impl PolarValue {
         };
         Term::new_from_ffi(value)
     }
+
+    /// The name of the type of the value, for type errors.
+    pub(crate) fn type_name(&self) -> &'static str {
+        match self {
+            PolarValue::Integer(_) => "Integer",
+            PolarValue::Float(_) => "Float",
+            PolarValue::String(_) => "String",
+            PolarValue::Boolean(_) => "Boolean",
+            PolarValue::Map(_) => "Map",
+            PolarValue::List(_) => "List",
+            PolarValue::Variable(_) => "Variable",
+            PolarValue::Instance(_) => "Instance",
+        }
+    }
+
+    /// Convert a JSON value. Polar has no null value, so `null` fields of
+    /// objects are left out, and other `null`s are passed as instances that
+    /// convert back to `null`, like integers larger than `i64::MAX`.
+    #[cfg(feature = "json")]
+    pub(crate) fn from_json(value: &serde_json::Value) -> Self {
+        use serde_json::Value;
+        match value {
+            Value::Null => PolarValue::new_from_instance(Value::Null),
+            Value::Bool(b) => PolarValue::Boolean(*b),
+            Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+                (Some(i), _) => PolarValue::Integer(i),
+                (None, Some(u)) => PolarValue::new_from_instance(u),
+                (None, None) => PolarValue::Float(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            Value::String(s) => PolarValue::String(s.clone()),
+            Value::Array(values) => {
+                PolarValue::List(values.iter().map(PolarValue::from_json).collect())
+            }
+            Value::Object(values) => PolarValue::Map(
+                values
+                    .iter()
+                    .filter(|(_, v)| !v.is_null())
+                    .map(|(k, v)| (k.clone(), PolarValue::from_json(v)))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// Convert to a JSON value. Instances of `serde_json::Value`, `u64` and
+    /// `usize` convert to their JSON values. Variables, other instances and
+    /// floats that aren't finite have no JSON value.
+    #[cfg(feature = "json")]
+    pub(crate) fn to_json(&self) -> crate::Result<serde_json::Value> {
+        use serde_json::Value;
+        Ok(match self {
+            PolarValue::Integer(i) => Value::from(*i),
+            PolarValue::Float(f) => match serde_json::Number::from_f64(*f) {
+                Some(n) => Value::Number(n),
+                None => {
+                    return Err(TypeError::expected("finite Float")
+                        .got(f.to_string())
+                        .user())
+                }
+            },
+            PolarValue::String(s) => Value::String(s.clone()),
+            PolarValue::Boolean(b) => Value::Bool(*b),
+            PolarValue::Map(map) => Value::Object(
+                map.iter()
+                    .map(|(k, v)| v.to_json().map(|v| (k.clone(), v)))
+                    .collect::<crate::Result<_>>()?,
+            ),
+            PolarValue::List(l) => Value::Array(
+                l.iter()
+                    .map(PolarValue::to_json)
+                    .collect::<crate::Result<_>>()?,
+            ),
+            PolarValue::Variable(v) => {
+                return Err(TypeError::expected("JSON value")
+                    .got(format!("unbound variable {}", v))
+                    .user())
+            }
+            PolarValue::Instance(instance) => {
+                if let Ok(value) = instance.downcast::<serde_json::Value>(None) {
+                    value.clone()
+                } else if let Ok(u) = instance.downcast::<u64>(None) {
+                    Value::from(*u)
+                } else if let Ok(u) = instance.downcast::<usize>(None) {
+                    Value::from(*u)
+                } else {
+                    return Err(TypeError::expected("JSON value").got("Instance").user());
+                }
+            }
+        })
+    }
 }
 
 pub trait FromPolarValue: Clone + Sized + 'static {
@@ -110,14 +203,39 @@ impl FromPolarValue for PolarValue {
     }
 }
 
+fn out_of_range<T>(i: impl std::fmt::Display) -> crate::OsoError {
+    TypeError::expected(std::any::type_name::<T>())
+        .got(format!("{}, which is out of range", i))
+        .user()
+}
+
 macro_rules! polar_to_int {
     ($i:ty) => {
         impl FromPolarValue for $i {
             fn from_polar_value(val: PolarValue) -> crate::Result<Self> {
                 if let PolarValue::Integer(i) = val {
-                    <$i>::try_from(i).map_err(|_| crate::OsoError::FromPolar)
+                    <$i>::try_from(i).map_err(|_| out_of_range::<$i>(i))
                 } else {
-                    Err(TypeError::expected("Integer").user())
+                    Err(TypeError::expected("Integer").got(val.type_name()).user())
+                }
+            }
+        }
+    };
+}
+
+// Values too large for Polar integers are passed to Polar as instances (see
+// `ToPolar`), and convert back unchanged.
+macro_rules! polar_to_unsigned {
+    ($i:ty) => {
+        impl FromPolarValue for $i {
+            fn from_polar_value(val: PolarValue) -> crate::Result<Self> {
+                match val {
+                    PolarValue::Integer(i) => <$i>::try_from(i).map_err(|_| out_of_range::<$i>(i)),
+                    PolarValue::Instance(instance) => instance
+                        .downcast::<$i>(None)
+                        .map(|i| *i)
+                        .map_err(|e| e.user()),
+                    val => Err(TypeError::expected("Integer").got(val.type_name()).user()),
                 }
             }
         }
@@ -131,6 +249,8 @@ polar_to_int!(i16);
 polar_to_int!(u32);
 polar_to_int!(i32);
 polar_to_int!(i64);
+polar_to_unsigned!(u64);
+polar_to_unsigned!(usize);
 
 impl<T> FromPolarValue for T
 where
@@ -140,7 +260,7 @@ where
         if let PolarValue::Instance(instance) = val {
             Ok(instance.downcast::<T>(None).map_err(|e| e.user())?.clone())
         } else {
-            Err(TypeError::expected("Instance").user())
+            Err(TypeError::expected("Instance").got(val.type_name()).user())
         }
     }
 }
@@ -150,7 +270,7 @@ impl FromPolarValue for f64 {
         if let PolarValue::Float(f) = val {
             Ok(f)
         } else {
-            Err(TypeError::expected("Float").user())
+            Err(TypeError::expected("Float").got(val.type_name()).user())
         }
     }
 }
@@ -160,7 +280,7 @@ impl FromPolarValue for String {
         if let PolarValue::String(s) = val {
             Ok(s)
         } else {
-            Err(TypeError::expected("String").user())
+            Err(TypeError::expected("String").got(val.type_name()).user())
         }
     }
 }
@@ -170,7 +290,7 @@ impl FromPolarValue for bool {
         if let PolarValue::Boolean(b) = val {
             Ok(b)
         } else {
-            Err(TypeError::expected("Boolean").user())
+            Err(TypeError::expected("Boolean").got(val.type_name()).user())
         }
     }
 }
@@ -185,7 +305,7 @@ impl<T: FromPolarValue> FromPolarValue for HashMap<String, T> {
             }
             Ok(result)
         } else {
-            Err(TypeError::expected("Map").user())
+            Err(TypeError::expected("Map").got(val.type_name()).user())
         }
     }
 }
@@ -199,7 +319,73 @@ impl<T: FromPolarValue> FromPolarValue for Vec<T> {
             }
             Ok(result)
         } else {
-            Err(TypeError::expected("List").user())
+            Err(TypeError::expected("List").got(val.type_name()).user())
+        }
+    }
+}
+
+impl<T: FromPolarValue> FromPolarValue for BTreeMap<String, T> {
+    fn from_polar_value(val: PolarValue) -> crate::Result<Self> {
+        if let PolarValue::Map(map) = val {
+            map.into_iter()
+                .map(|(k, v)| T::from_polar_value(v).map(|v| (k, v)))
+                .collect()
+        } else {
+            Err(TypeError::expected("Map").got(val.type_name()).user())
+        }
+    }
+}
+
+impl<T: FromPolarValue + Eq + Hash> FromPolarValue for HashSet<T> {
+    fn from_polar_value(val: PolarValue) -> crate::Result<Self> {
+        if let PolarValue::List(l) = val {
+            l.into_iter().map(T::from_polar_value).collect()
+        } else {
+            Err(TypeError::expected("List").got(val.type_name()).user())
+        }
+    }
+}
+
+/// Unbound variables, like a result variable the query didn't bind, are
+/// `None`.
+impl<T: FromPolarValue> FromPolarValue for Option<T> {
+    fn from_polar_value(val: PolarValue) -> crate::Result<Self> {
+        match val {
+            PolarValue::Variable(_) => Ok(None),
+            val => T::from_polar_value(val).map(Some),
+        }
+    }
+}
+
+// `Box<T>` can't be `FromPolarValue`, since other crates may make it a
+// `PolarClass`.
+impl<T: FromPolarValue> FromPolarValue for Arc<T> {
+    fn from_polar_value(val: PolarValue) -> crate::Result<Self> {
+        T::from_polar_value(val).map(Arc::new)
+    }
+}
+
+#[cfg(feature = "json")]
+impl FromPolarValue for serde_json::Value {
+    fn from_polar_value(val: PolarValue) -> crate::Result<Self> {
+        val.to_json()
+    }
+}
+
+#[impl_for_tuples(1, 16)]
+#[tuple_types_custom_trait_bound(FromPolarValue)]
+impl FromPolarValue for Tuple {
+    fn from_polar_value(val: PolarValue) -> crate::Result<Self> {
+        let len = [for_tuples!( #( std::any::type_name::<Tuple>() ),* )].len();
+        match val {
+            PolarValue::List(l) if l.len() == len => {
+                let mut iter = l.into_iter();
+                Ok((for_tuples!( #( Tuple::from_polar_value(iter.next().unwrap())? ),* )))
+            }
+            PolarValue::List(l) => Err(TypeError::expected(format!("List of {} elements", len))
+                .got(format!("List of {} elements", l.len()))
+                .user()),
+            val => Err(TypeError::expected("List").got(val.type_name()).user()),
         }
     }
 }
@@ -233,6 +419,10 @@ try_from_polar!(i16);
 try_from_polar!(u32);
 try_from_polar!(i32);
 try_from_polar!(i64);
+try_from_polar!(u64);
+try_from_polar!(usize);
+#[cfg(feature = "json")]
+try_from_polar!(serde_json::Value);
 try_from_polar!(f64);
 try_from_polar!(String);
 try_from_polar!(bool);
@@ -252,3 +442,45 @@ impl<T: FromPolarValue> TryFrom<PolarValue> for Vec<T> {
         Self::from_polar_value(v)
     }
 }
+
+impl<T: FromPolarValue> TryFrom<PolarValue> for BTreeMap<String, T> {
+    type Error = crate::OsoError;
+
+    fn try_from(v: PolarValue) -> Result<Self, Self::Error> {
+        Self::from_polar_value(v)
+    }
+}
+
+impl<T: FromPolarValue + Eq + Hash> TryFrom<PolarValue> for HashSet<T> {
+    type Error = crate::OsoError;
+
+    fn try_from(v: PolarValue) -> Result<Self, Self::Error> {
+        Self::from_polar_value(v)
+    }
+}
+
+// `Option<T>` and `Arc<T>` can't have `TryFrom<PolarValue>`: it would
+// overlap with `TryFrom<PolarValue>` for `Option<PolarValue>` and
+// `Arc<PolarValue>`, which comes from their `From<T>` impls.
+#[impl_for_tuples(1, 16)]
+#[tuple_types_custom_trait_bound(FromPolarValue)]
+impl TryFrom<PolarValue> for Tuple {
+    type Error = crate::OsoError;
+
+    fn try_from(v: PolarValue) -> Result<Self, Self::Error> {
+        Self::from_polar_value(v)
+    }
+}
+
+/// Borrow the string of a `PolarValue::String`.
+impl<'a> TryFrom<&'a PolarValue> for &'a str {
+    type Error = crate::OsoError;
+
+    fn try_from(v: &'a PolarValue) -> Result<Self, Self::Error> {
+        if let PolarValue::String(s) = v {
+            Ok(s)
+        } else {
+            Err(TypeError::expected("String").got(v.type_name()).user())
+        }
+    }
+}
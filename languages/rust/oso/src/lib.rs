@@ -75,17 +75,30 @@
 pub mod macros;
 
 pub(crate) mod builtins;
+mod debugger;
 pub mod errors;
 mod host;
 mod oso;
 mod query;
+mod testing;
 
 pub use crate::oso::Oso;
+pub use debugger::{Debugger, StdioDebugger};
 pub use errors::{OsoError, Result};
+#[cfg(feature = "serde")]
+pub use host::Deserialized;
 pub use host::{
-    Class, ClassBuilder, FromPolar, FromPolarList, FromPolarValue, PolarValue, ToPolar, ToPolarList,
+    Class, ClassBuilder, DynamicInstance, FromPolar, FromPolarList, FromPolarValue, PolarValue,
+    ToPolar, ToPolarList,
 };
-pub use query::{Query, ResultSet};
+pub use polar_core::coverage::{
+    ConditionCoverage, CoverageReport, FileCoverage, Hits, LineCoverage, RuleCoverage,
+};
+pub use polar_core::profiler::{ExternalStats, Profile, RuleStats};
+pub use polar_core::recording::Recording;
+pub use polar_core::traces::{ExportedNode, ExportedTrace, NodeKind, SourceLocation};
+pub use query::{FromResultSet, Query, ResultSet};
+pub use testing::{Expectation, TestCase, TestReport, TestResult};
 
 use polar_core::polar::Polar;
 
@@ -117,6 +130,47 @@ pub trait PolarClass: Sized + 'static {
     }
 }
 
+/// Methods of a `PolarClass` to register with its class.
+///
+/// Implemented by the `#[polar_methods]` attribute on an `impl` block, and
+/// used by `#[derive(PolarClass)]` on types with the `#[polar(methods)]`
+/// attribute:
+///
+/// ```
+/// # fn main() -> anyhow::Result<()> {
+/// use oso::{polar_methods, Oso, PolarClass};
+///
+/// #[derive(Clone, PolarClass)]
+/// #[polar(methods)]
+/// struct User {
+///     #[polar(attribute)]
+///     name: String,
+/// }
+///
+/// #[polar_methods]
+/// impl User {
+///     #[polar(constructor)]
+///     fn new(name: String) -> Self {
+///         Self { name }
+///     }
+///
+///     #[polar(method, rename = "is_admin")]
+///     fn admin(&self) -> bool {
+///         self.name == "alice"
+///     }
+/// }
+///
+/// let mut oso = Oso::new();
+/// oso.register_class(User::get_polar_class())?;
+/// oso.load_str(r#"allow(user: User, "read", _) if user.is_admin();"#)?;
+/// assert!(oso.is_allowed(User::new("alice".to_string()), "read", "doc")?);
+/// # Ok(())
+/// # }
+/// ```
+pub trait PolarMethods: Sized + 'static {
+    fn add_polar_methods(builder: ClassBuilder<Self>) -> ClassBuilder<Self>;
+}
+
 #[cfg(feature = "derive")]
 #[allow(unused_imports)]
 #[macro_use]
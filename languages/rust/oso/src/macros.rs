@@ -12,9 +12,10 @@ macro_rules! check_messages {
         while let Some(message) = $core_obj.next_message() {
             match message.kind {
                 ::polar_core::messages::MessageKind::Print => ::tracing::debug!("{}", &message.msg),
-                ::polar_core::messages::MessageKind::Warning => {
-                    ::tracing::warn!("{}", &message.msg)
-                }
+                ::polar_core::messages::MessageKind::Warning => match &message.diagnostic {
+                    Some(diagnostic) => ::tracing::warn!(code = %diagnostic.code, "{}", &message.msg),
+                    None => ::tracing::warn!("{}", &message.msg),
+                },
             }
         }
         true
@@ -1,13 +1,19 @@
 //! Communicate with the Polar virtual machine: load rules, make queries, etc/
 
+use polar_core::coverage::CoverageReport;
+use polar_core::error::{ErrorKind, RuntimeError};
+use polar_core::profiler::Profile;
+use polar_core::recording::Recording;
 use polar_core::terms::{Call, Symbol, Term, Value};
 
 use std::fs::File;
 use std::io::Read;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
+use crate::debugger::{Debugger, SharedDebugger};
 use crate::host::Host;
 use crate::query::Query;
+use crate::testing::{TestFile, TestReport, TestResult};
 use crate::{ToPolar, ToPolarList};
 
 /// Oso is the main struct you interact with. It is an instance of the Oso authorization library
@@ -16,6 +22,9 @@ use crate::{ToPolar, ToPolarList};
 pub struct Oso {
     inner: Arc<polar_core::polar::Polar>,
     host: Host,
+    debugger: Option<SharedDebugger>,
+    /// Whether queries trace their results.
+    tracing: bool,
 }
 
 impl Default for Oso {
@@ -30,7 +39,12 @@ impl Oso {
         let inner = Arc::new(polar_core::polar::Polar::new());
         let host = Host::new(inner.clone());
 
-        let mut oso = Self { host, inner };
+        let mut oso = Self {
+            host,
+            inner,
+            debugger: None,
+            tracing: false,
+        };
 
         for class in crate::builtins::classes() {
             oso.register_class(class)
@@ -67,10 +81,22 @@ impl Oso {
 
     fn check_inline_queries(&mut self) -> crate::Result<()> {
         while let Some(q) = self.inner.next_inline_query(false) {
-            let query = Query::new(q, self.host.clone());
+            let source = q.source_info();
+            let query = self.new_query(q, self.host.clone());
             match query.collect::<crate::Result<Vec<_>>>() {
                 Ok(v) if !v.is_empty() => continue,
-                Ok(_) => return lazy_error!("inline query result was false"),
+                Ok(_) => {
+                    return lazy_error!("Inline query failed: expected a result for {}", source)
+                }
+                // Failed expectations are located already.
+                Err(crate::OsoError::Polar(e))
+                    if matches!(
+                        e.kind,
+                        ErrorKind::Runtime(RuntimeError::InlineQueryFailed { .. })
+                    ) =>
+                {
+                    return Err(e.into())
+                }
                 Err(e) => return lazy_error!("error in inline query: {}", e),
             }
         }
@@ -79,8 +105,27 @@ impl Oso {
     }
 
     /// Load a file containing polar rules. All polar files must end in `.polar`
+    ///
+    /// Calls to undefined rules are checked by the next query, so rules can
+    /// call rules defined in files loaded later.
     pub fn load_file<P: AsRef<std::path::Path>>(&mut self, file: P) -> crate::Result<()> {
-        let file = file.as_ref();
+        self.read_policy(file.as_ref())?;
+        self.check_inline_queries()
+    }
+
+    /// Load files containing polar rules. All polar files must end in `.polar`
+    ///
+    /// Calls to undefined rules are checked once all the files are loaded, so
+    /// rules in one file can call rules defined in another.
+    pub fn load_files<P: AsRef<std::path::Path>>(&mut self, files: &[P]) -> crate::Result<()> {
+        for file in files {
+            self.read_policy(file.as_ref())?;
+        }
+        self.inner.check();
+        self.check_inline_queries()
+    }
+
+    fn read_policy(&mut self, file: &std::path::Path) -> crate::Result<()> {
         if !file.extension().map(|ext| ext == "polar").unwrap_or(false) {
             return Err(crate::OsoError::IncorrectFileType {
                 filename: file.to_string_lossy().into_owned(),
@@ -91,7 +136,45 @@ impl Oso {
         f.read_to_string(&mut policy)?;
         self.inner
             .load(&policy, Some(file.to_string_lossy().into_owned()))?;
-        self.check_inline_queries()
+        Ok(())
+    }
+
+    /// Run the test cases of a test file against the loaded policies. The
+    /// other rules of the file are loaded first, as fixtures, into a copy of
+    /// the knowledge base, so they don't outlive the test run.
+    ///
+    /// See [`TestCase`](crate::TestCase) for the format of test files.
+    pub fn run_tests<P: AsRef<std::path::Path>>(&self, file: P) -> crate::Result<TestReport> {
+        let file = file.as_ref();
+        let filename = file.to_string_lossy().into_owned();
+        if !file.extension().map(|ext| ext == "polar").unwrap_or(false) {
+            return Err(crate::OsoError::IncorrectFileType { filename });
+        }
+        let mut src = String::new();
+        File::open(file)?.read_to_string(&mut src)?;
+        let test_file = TestFile::parse(&src, &filename)?;
+        let mut oso = Self {
+            inner: Arc::new(self.inner.fork()),
+            host: self.host.clone(),
+            debugger: self.debugger.clone(),
+            tracing: self.tracing,
+        };
+        oso.inner.load(&test_file.fixtures, Some(filename))?;
+        oso.inner.check();
+        oso.check_inline_queries()?;
+
+        let mut report = TestReport::default();
+        for case in test_file.cases {
+            let results = oso
+                .query(&case.query)
+                .and_then(|query| query.collect::<crate::Result<Vec<_>>>());
+            let failure = match results {
+                Ok(results) => case.check(&results).err(),
+                Err(e) => Some(format!("error: {}", e)),
+            };
+            report.results.push(TestResult { case, failure });
+        }
+        Ok(report)
     }
 
     /// Load a string of polar source directly.
@@ -110,9 +193,9 @@ impl Oso {
     /// oso.query("x = 1 or x = 2");
     /// ```
     pub fn query(&mut self, s: &str) -> crate::Result<Query> {
-        let query = self.inner.new_query(s, false)?;
+        let query = self.inner.new_query(s, self.tracing)?;
         check_messages!(self.inner);
-        let query = Query::new(query, self.host.clone());
+        let query = self.new_query(query, self.host.clone());
         Ok(query)
     }
 
@@ -132,12 +215,91 @@ impl Oso {
             kwargs: None,
         });
         let query_term = Term::new_from_ffi(query_value);
-        let query = self.inner.new_query_from_term(query_term, false);
+        let query = self.inner.new_query_from_term(query_term, self.tracing);
         check_messages!(self.inner);
-        let query = Query::new(query, query_host);
+        let query = self.new_query(query, query_host);
         Ok(query)
     }
 
+    /// Set the debugger used by queries made from this instance, including
+    /// inline queries in loaded policies. See [`Debugger`].
+    pub fn set_debugger<D: Debugger + Send + 'static>(&mut self, debugger: D) {
+        self.debugger = Some(SharedDebugger(Arc::new(Mutex::new(debugger))));
+    }
+
+    fn new_query(&self, query: polar_core::polar::Query, host: Host) -> Query {
+        let mut query = Query::new(query, host);
+        if let Some(debugger) = &self.debugger {
+            query.set_debugger(debugger.clone());
+        }
+        query
+    }
+
+    /// Trace the results of queries made from now on. The trace of a result,
+    /// which shows the rules and conditions that led to it, is returned by
+    /// [`ResultSet::trace`](crate::ResultSet::trace).
+    pub fn enable_tracing(&mut self, enabled: bool) {
+        self.tracing = enabled;
+    }
+
+    /// Set the time queries made from now on may run for before they fail
+    /// with a timeout error. Defaults to 30 seconds.
+    pub fn set_query_timeout(&self, timeout: std::time::Duration) {
+        self.inner.set_query_timeout(timeout);
+    }
+
+    /// The names of the loaded rules, sorted.
+    pub fn rule_names(&self) -> Vec<String> {
+        self.inner.rule_names()
+    }
+
+    /// The source of each definition of the rule `name`, in the order they
+    /// were loaded.
+    pub fn rule_sources(&self, name: &str) -> Vec<String> {
+        self.inner.rule_sources(name)
+    }
+
+    /// Profile all queries made from now on. The profile of a query is added
+    /// to the total returned by [`Oso::profile`] when the query is dropped,
+    /// so it covers the results that were fetched from it. Disabling
+    /// profiling discards the profile.
+    pub fn enable_profiling(&self, enabled: bool) {
+        self.inner.enable_profiling(enabled);
+    }
+
+    /// The profile of the queries dropped since profiling was enabled.
+    pub fn profile(&self) -> Option<Profile> {
+        self.inner.profile()
+    }
+
+    /// Collect coverage for all queries made from now on. Disabling coverage
+    /// discards what was collected.
+    pub fn enable_coverage(&self, enabled: bool) {
+        self.inner.enable_coverage(enabled);
+    }
+
+    /// Report which of the loaded rules and rule body conditions were evaluated,
+    /// and succeeded, in the queries finished since coverage was enabled.
+    ///
+    /// The report can be written out as JSON with [`CoverageReport::to_json`] or
+    /// as an LCOV tracefile with [`CoverageReport::to_lcov`].
+    pub fn coverage_report(&self) -> Option<CoverageReport> {
+        self.inner.coverage_report()
+    }
+
+    /// Replay a query recorded with [`Query::start_recording`] against the
+    /// rules loaded into this instance, and return its results.
+    ///
+    /// Answers to the application are taken from the recording, so the
+    /// application's classes don't need to be registered. Fails if the
+    /// execution diverges from the recording, e.g. because the rules changed.
+    pub fn replay(&self, recording: Recording) -> crate::Result<Vec<crate::ResultSet>> {
+        let replay = self.inner.replay(recording);
+        let results = crate::query::replay_results(replay, self.host.clone());
+        check_messages!(self.inner);
+        results
+    }
+
     /// Register a rust type as a Polar class.
     /// See [`oso::Class`] docs.
     pub fn register_class(&mut self, class: crate::host::Class) -> crate::Result<()> {
@@ -158,6 +320,11 @@ impl Oso {
             .register_constant(Symbol(name.to_string()), value.to_polar(&mut self.host));
         Ok(())
     }
+
+    /// Whether a class or constant is registered under `name`.
+    pub fn is_registered(&self, name: &str) -> bool {
+        self.inner.is_constant(&Symbol(name.to_string()))
+    }
 }
 
 // Make sure the `Oso` object is threadsafe
@@ -1,12 +1,16 @@
 use std::collections::BTreeMap;
 use std::collections::HashMap;
 
-use crate::errors::OsoError;
-use crate::host::{Host, Instance, PolarResultIter};
+use crate::debugger::Debugger;
+use crate::errors::{OsoError, TypeError};
+use crate::host::{Class, Host, Instance, PolarResultIter};
 use crate::{FromPolar, PolarValue};
 
 use polar_core::events::*;
+use polar_core::profiler::Profile;
+use polar_core::recording::{Recording, Replay};
 use polar_core::terms::*;
+use polar_core::traces::ExportedTrace;
 
 impl Iterator for Query {
     type Item = crate::Result<ResultSet>;
@@ -19,6 +23,7 @@ pub struct Query {
     inner: polar_core::polar::Query,
     calls: HashMap<u64, PolarResultIter>,
     host: Host,
+    debugger: Option<Box<dyn Debugger>>,
 }
 
 impl Query {
@@ -27,9 +32,85 @@ impl Query {
             calls: HashMap::new(),
             inner,
             host,
+            debugger: None,
         }
     }
 
+    /// Set the debugger for this query, replacing the one installed on `Oso`.
+    /// See [`Debugger`].
+    pub fn set_debugger<D: Debugger + 'static>(&mut self, debugger: D) {
+        self.debugger = Some(Box::new(debugger));
+    }
+
+    /// Collect a [`Profile`] of rule and external calls while running the query.
+    pub fn enable_profiling(&mut self) {
+        self.inner.enable_profiling();
+    }
+
+    /// The profile of the query so far, if profiling is enabled.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.inner.profile()
+    }
+
+    /// Export a trace of each result, and of each failing branch if
+    /// `failing_branches` is true, for viewing outside the library.
+    /// See [`ExportedTrace`] for the format.
+    pub fn export_traces(&mut self, failing_branches: bool) {
+        self.inner.export_traces(failing_branches);
+    }
+
+    /// Return the traces exported since the last call.
+    pub fn take_traces(&mut self) -> Vec<ExportedTrace> {
+        self.inner.take_traces()
+    }
+
+    /// Start recording the events of this query and the answers sent back to
+    /// it, so the execution can be replayed with [`Oso::replay`](crate::Oso::replay).
+    pub fn start_recording(&mut self) {
+        self.inner.start_recording();
+    }
+
+    /// Stop recording and return the recording, e.g. to save it with
+    /// [`Recording::to_json`].
+    pub fn take_recording(&mut self) -> Option<Recording> {
+        self.inner.take_recording()
+    }
+
+    /// Send a command to the debugger, e.g. to set a breakpoint before
+    /// the query starts.
+    pub fn debug_command(&mut self, command: &str) -> crate::Result<()> {
+        Ok(self.inner.debug_command(command)?)
+    }
+
+    /// Decode each result into a `T`, like a struct with
+    /// `#[derive(FromResultSet)]` with a field for each variable:
+    ///
+    /// ```
+    /// # fn main() -> anyhow::Result<()> {
+    /// use oso::{FromResultSet, Oso};
+    ///
+    /// #[derive(FromResultSet)]
+    /// struct Role {
+    ///     user: String,
+    ///     role: String,
+    /// }
+    ///
+    /// let mut oso = Oso::new();
+    /// oso.load_str(r#"role("alice", "admin"); role("bob", "member");"#)?;
+    ///
+    /// let roles = oso
+    ///     .query("role(user, role)")?
+    ///     .typed::<Role>()
+    ///     .collect::<oso::Result<Vec<Role>>>()?;
+    /// assert_eq!(roles[1].user, "bob");
+    /// assert_eq!(roles[1].role, "member");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn typed<T: FromResultSet>(self) -> impl Iterator<Item = crate::Result<T>> {
+        self.map(|result| result.and_then(|result| T::from_result_set(&result)))
+    }
+
     pub fn next_result(&mut self) -> Option<crate::Result<ResultSet>> {
         loop {
             let event = self.inner.next()?;
@@ -42,10 +123,11 @@ impl Query {
             let result = match event {
                 QueryEvent::None => Ok(()),
                 QueryEvent::Done { .. } => return None,
-                QueryEvent::Result { bindings, .. } => {
+                QueryEvent::Result { bindings, trace } => {
                     return Some(Ok(ResultSet {
                         bindings,
                         host: self.host.clone(),
+                        trace: trace.map(|trace| trace.formatted),
                     }));
                 }
                 QueryEvent::MakeExternal {
@@ -130,11 +212,14 @@ impl Query {
     fn handle_make_external(&mut self, instance_id: u64, constructor: Term) -> crate::Result<()> {
         match constructor.value() {
             Value::Call(Call { name, args, kwargs }) => {
-                if !kwargs.is_none() {
-                    lazy_error!("keyword args for constructor not supported.")
-                } else {
-                    self.host.make_instance(name, args.clone(), instance_id)
-                }
+                let args = match kwargs {
+                    Some(kwargs) => self
+                        .host
+                        .get_class(name)?
+                        .constructor_args(args.clone(), kwargs.clone())?,
+                    None => args.clone(),
+                };
+                self.host.make_instance(name, args, instance_id)
             }
             _ => lazy_error!("invalid type for constructing an instance -- internal error"),
         }
@@ -171,10 +256,27 @@ impl Query {
         args: Option<Vec<Term>>,
         kwargs: Option<BTreeMap<Symbol, Term>>,
     ) -> crate::Result<()> {
-        if kwargs.is_some() {
-            return lazy_error!("Invalid call error: kwargs not supported in Rust.");
-        }
         let instance = Instance::from_polar(&instance, &self.host).unwrap();
+        let args = match kwargs {
+            Some(kwargs) => {
+                let args = args.unwrap_or_default();
+                // Methods called on a class are its class methods.
+                let args = match instance.downcast::<Class>(None) {
+                    Ok(class) => class.class_method_args(&name.0, args, kwargs),
+                    Err(_) => instance
+                        .class(&self.host)
+                        .and_then(|class| class.method_args(&name.0, args, kwargs)),
+                };
+                match args {
+                    Ok(args) => Some(args),
+                    Err(e) => {
+                        self.call_result_none(call_id)?;
+                        return Err(e);
+                    }
+                }
+            }
+            None => args,
+        };
         if let Err(e) = self.register_call(call_id, instance, name, args) {
             self.call_result_none(call_id)?;
             return Err(e);
@@ -249,16 +351,41 @@ impl Query {
     }
 
     fn handle_debug(&mut self, message: String) -> crate::Result<()> {
-        eprintln!("TODO: {}", message);
         check_messages!(self.inner);
-        Ok(())
+        if let Some(debugger) = self.debugger.as_mut() {
+            let command = debugger.debug(&message);
+            self.debug_command(&command)
+        } else {
+            // Without a debugger there is nobody to answer, so the query continues.
+            tracing::warn!(
+                "query paused in the debugger, but no debugger is set: {}",
+                message
+            );
+            Ok(())
+        }
+    }
+}
+
+/// Run a replay to completion and collect its results.
+pub(crate) fn replay_results(replay: Replay, host: Host) -> crate::Result<Vec<ResultSet>> {
+    let mut results = vec![];
+    for event in replay {
+        if let QueryEvent::Result { bindings, .. } = event? {
+            results.push(ResultSet {
+                bindings,
+                host: host.clone(),
+                trace: None,
+            });
+        }
     }
+    Ok(results)
 }
 
 #[derive(Clone)]
 pub struct ResultSet {
     bindings: polar_core::kb::Bindings,
     host: crate::host::Host,
+    trace: Option<String>,
 }
 
 impl ResultSet {
@@ -275,6 +402,12 @@ impl ResultSet {
         self.bindings.is_empty()
     }
 
+    /// The trace of the rules and conditions that led to this result, if
+    /// tracing is enabled with [`Oso::enable_tracing`](crate::Oso::enable_tracing).
+    pub fn trace(&self) -> Option<&str> {
+        self.trace.as_deref()
+    }
+
     pub fn get(&self, name: &str) -> Option<crate::PolarValue> {
         self.bindings
             .get(&Symbol(name.to_string()))
@@ -286,6 +419,42 @@ impl ResultSet {
             .ok_or_else(|| crate::OsoError::FromPolar)
             .and_then(T::from_polar_value)
     }
+
+    /// Get the binding of the variable `name` as a `T`, failing with a
+    /// `TypeError` that names the variable if it has no binding or the
+    /// binding has another type. A variable with no binding is unbound, so
+    /// it is `None` as an `Option`.
+    pub fn get_var<T: crate::host::FromPolarValue>(&self, name: &str) -> crate::Result<T> {
+        let value = match self.get(name) {
+            Some(value) => value,
+            None => {
+                return T::from_polar_value(PolarValue::Variable(name.to_owned())).map_err(|_| {
+                    TypeError::expected(format!("a binding for variable {}", name))
+                        .got("no binding")
+                        .user()
+                })
+            }
+        };
+        T::from_polar_value(value).map_err(|e| match e {
+            OsoError::TypeError(e) => TypeError {
+                expected: format!("{} for variable {}", e.expected, name),
+                got: e.got,
+            }
+            .user(),
+            e => e,
+        })
+    }
+}
+
+/// Types decoded from the bindings of a query result, with
+/// `Query::typed`.
+///
+/// Derive it with `#[derive(FromResultSet)]` on a struct with a field for
+/// each variable, like `ResultSet::get_var` for each field. Fields can be
+/// renamed with `#[polar(rename = "variable")]`, and `Option` fields are
+/// `None` for variables with no binding.
+pub trait FromResultSet: Sized {
+    fn from_result_set(result: &ResultSet) -> crate::Result<Self>;
 }
 
 impl std::fmt::Debug for ResultSet {
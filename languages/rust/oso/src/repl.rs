@@ -5,15 +5,109 @@ use rustyline::validate::{ValidationContext, ValidationResult, Validator};
 use rustyline::Editor;
 use rustyline_derive::{Completer, Helper, Highlighter, Hinter};
 
-use oso::Oso;
+use oso::{Class, DynamicInstance, Oso, StdioDebugger};
+use polar_core::formatter::format_policy;
 use polar_core::formatting::to_polar::ToPolarString;
 
+use std::collections::HashMap;
 use std::env;
-use std::fs::OpenOptions;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::time::Duration;
 
 pub fn load_files(oso: &mut Oso, files: &mut dyn Iterator<Item = String>) -> anyhow::Result<()> {
-    for file in files {
-        oso.load_file(&file)?;
+    oso.load_files(&files.collect::<Vec<_>>())?;
+    Ok(())
+}
+
+/// Whether `file` declares fixtures rather than rules.
+fn is_fixture_file(file: &str) -> bool {
+    file.ends_with(".json") || file.ends_with(".yaml") || file.ends_with(".yml")
+}
+
+/// Register the dynamic classes and instances declared in a JSON or YAML
+/// fixture file:
+///
+/// ```yaml
+/// classes:
+///   User: [name, role]
+/// instances:
+///   alice:
+///     class: User
+///     fields: { name: alice, role: admin }
+/// ```
+///
+/// Instances without `fields` have none set. Classes are registered by
+/// name, and instances as constants. Nothing is registered unless the whole
+/// file is valid and none of its names are registered already, so it can be
+/// fixed and loaded again.
+pub fn load_fixtures(oso: &mut Oso, file: &str) -> anyhow::Result<()> {
+    let src = fs::read_to_string(file)?;
+    let fixtures: serde_json::Value = if file.ends_with(".json") {
+        serde_json::from_str(&src)?
+    } else {
+        serde_yaml::from_str(&src)?
+    };
+    let invalid = |message: &str| anyhow::anyhow!("{}: {}", file, message);
+
+    let mut classes: HashMap<&str, Vec<&str>> = HashMap::new();
+    if let Some(declared) = fixtures.get("classes") {
+        let declared = declared
+            .as_object()
+            .ok_or_else(|| invalid("`classes` must map class names to lists of fields"))?;
+        for (name, fields) in declared {
+            let fields: Vec<&str> = fields
+                .as_array()
+                .and_then(|fields| fields.iter().map(|field| field.as_str()).collect())
+                .ok_or_else(|| {
+                    invalid(&format!("the fields of {} must be a list of names", name))
+                })?;
+            classes.insert(name, fields);
+        }
+    }
+    let mut instances = vec![];
+    if let Some(declared) = fixtures.get("instances") {
+        let declared = declared
+            .as_object()
+            .ok_or_else(|| invalid("`instances` must map constant names to instances"))?;
+        for (name, instance) in declared {
+            let class = instance["class"]
+                .as_str()
+                .ok_or_else(|| invalid(&format!("{} needs a class", name)))?;
+            let declared_fields = classes
+                .get(class)
+                .ok_or_else(|| invalid(&format!("{} is not a declared class", class)))?;
+            let empty = serde_json::json!({});
+            let fields = instance.get("fields").unwrap_or(&empty);
+            if let Some(values) = fields.as_object() {
+                if let Some(field) = values
+                    .keys()
+                    .find(|field| !declared_fields.contains(&field.as_str()))
+                {
+                    return Err(invalid(&format!("{} has no field {}", class, field)));
+                }
+            }
+            let instance = DynamicInstance::from_json(class, fields)
+                .map_err(|e| invalid(&format!("the fields of {}: {}", name, e)))?;
+            instances.push((name, instance));
+        }
+    }
+
+    let names = classes
+        .keys()
+        .copied()
+        .chain(instances.iter().map(|(name, _)| name.as_str()));
+    for name in names {
+        if oso.is_registered(name) {
+            return Err(invalid(&format!("{} is already registered", name)));
+        }
+    }
+
+    for (name, fields) in &classes {
+        oso.register_class(Class::dynamic(name, fields))?;
+    }
+    for (name, instance) in instances {
+        oso.register_constant(instance, name)?;
     }
     Ok(())
 }
@@ -35,21 +129,26 @@ pub fn try_create_history_file() -> Option<std::path::PathBuf> {
 
 /// Provides input validation.
 ///
-/// Currently, is only used to determine whether a line is
-/// incomplete (missing a ';').
+/// Queries and definitions may span several lines, and are complete once
+/// they end with a `;`. Commands are a single line.
 #[derive(Completer, Helper, Highlighter, Hinter)]
 struct InputValidator {}
 
 impl Validator for InputValidator {
     fn validate(&self, ctx: &mut ValidationContext) -> Result<ValidationResult, ReadlineError> {
-        let _input = ctx.input();
-        // if !input.ends_with(';') {
-        //     return Ok(ValidationResult::Incomplete);
-        // }
-        Ok(ValidationResult::Valid(None))
+        if is_complete(ctx.input()) {
+            Ok(ValidationResult::Valid(None))
+        } else {
+            Ok(ValidationResult::Incomplete)
+        }
     }
 }
 
+fn is_complete(input: &str) -> bool {
+    let input = input.trim();
+    input.is_empty() || input.starts_with(':') || input.ends_with(';')
+}
+
 pub struct Repl {
     editor: Editor<InputValidator>,
     plain_editor: Editor<()>,
@@ -102,35 +201,145 @@ impl Drop for Repl {
     }
 }
 
-pub fn main() -> anyhow::Result<()> {
-    tracing_subscriber::fmt::init();
-    let mut repl = Repl::new();
+/// Format policy files in place, or with `--check`, list the files that
+/// aren't formatted and fail if there are any.
+pub fn fmt(args: &mut dyn Iterator<Item = String>, out: &mut dyn Write) -> anyhow::Result<()> {
+    let mut check = false;
+    let mut unformatted = 0;
+    for arg in args {
+        if arg == "--check" {
+            check = true;
+            continue;
+        }
+        let src = fs::read_to_string(&arg)?;
+        let formatted = format_policy(&src, Some(arg.clone()))?;
+        if formatted == src {
+            continue;
+        }
+        if check {
+            writeln!(out, "{}", arg)?;
+            unformatted += 1;
+        } else {
+            fs::write(&arg, formatted)?;
+        }
+    }
+    if unformatted > 0 {
+        anyhow::bail!("{} file(s) are not formatted", unformatted);
+    }
+    Ok(())
+}
+
+/// Run the cases of test files (ending in `.test.polar`) against the other
+/// policy files, and fail if any case fails.
+pub fn test(args: &mut dyn Iterator<Item = String>, out: &mut dyn Write) -> anyhow::Result<()> {
+    let (tests, policies): (Vec<String>, Vec<String>) =
+        args.partition(|arg| arg.ends_with(".test.polar"));
     let mut oso = Oso::new();
+    load_files(&mut oso, &mut policies.into_iter())?;
+    let mut failed = 0;
+    for test in tests {
+        let report = oso.run_tests(&test)?;
+        writeln!(out, "{}", report)?;
+        failed += report.failures().count();
+    }
+    if failed > 0 {
+        anyhow::bail!("{} test(s) failed", failed);
+    }
+    Ok(())
+}
 
-    let mut args = env::args();
-    let _ = args.next(); // skip the binary filename
-    load_files(&mut oso, &mut args)?;
-    loop {
-        // get input
-        let mut input: String = match repl.oso_input("query> ") {
-            Ok(input) => input,
-            Err(e) => {
-                eprintln!("Readline error: {}", e);
-                break;
-            }
-        };
+const HELP: &str = "\
+Enter a query ending with `;` to run it, or `%def` followed by rules to load them.
 
-        if input.starts_with("%def") {
-            if let Err(e) = oso.load_str(&input[4..]) {
-                println!("{}", e);
+Commands:
+  :load FILE...       load policy files, or fixture files (.json, .yaml)
+  :reload             clear the rules and load the loaded files again
+  :rules [NAME]       list the names of the rules, or the definitions of NAME
+  :trace on|off       print the trace of each result
+  :timeout SECONDS    set the query timeout
+  :clear              clear the rules and forget the loaded files
+  :help               show this message";
+
+/// The state of a REPL session.
+pub struct Session {
+    oso: Oso,
+    /// Files loaded on the command line or with `:load`, for `:reload`.
+    files: Vec<String>,
+}
+
+impl Session {
+    pub fn new(oso: Oso) -> Self {
+        Self { oso, files: vec![] }
+    }
+
+    /// Load policy files, after registering the fixtures of fixture files.
+    /// Registered classes and constants outlive `:reload` and `:clear`, so
+    /// fixture files aren't loaded again.
+    pub fn load_files<S: AsRef<str>>(&mut self, files: &[S]) -> anyhow::Result<()> {
+        let (fixtures, policies): (Vec<&str>, Vec<&str>) = files
+            .iter()
+            .map(|file| file.as_ref())
+            .partition(|file| is_fixture_file(file));
+        for file in fixtures {
+            load_fixtures(&mut self.oso, file)?;
+        }
+        self.oso.load_files(&policies)?;
+        self.files
+            .extend(policies.iter().map(|file| file.to_string()));
+        Ok(())
+    }
+
+    /// Run a `:` command.
+    pub fn command(&mut self, input: &str) -> anyhow::Result<()> {
+        let mut words = input.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+        match (command, args.as_slice()) {
+            (":help", []) => println!("{}", HELP),
+            (":load", files) if !files.is_empty() => self.load_files(files)?,
+            (":reload", []) => {
+                self.oso.clear_rules();
+                self.oso.load_files(&self.files)?;
             }
-            continue;
+            (":rules", []) => {
+                for name in self.oso.rule_names() {
+                    println!("{}", name);
+                }
+            }
+            (":rules", [name]) => {
+                let sources = self.oso.rule_sources(name);
+                if sources.is_empty() {
+                    anyhow::bail!("no rule named {}", name);
+                }
+                for source in sources {
+                    println!("{}", source);
+                }
+            }
+            (":trace", ["on"]) => self.oso.enable_tracing(true),
+            (":trace", ["off"]) => self.oso.enable_tracing(false),
+            (":timeout", [seconds]) => match seconds.parse() {
+                Ok(seconds) if seconds > 0 => {
+                    self.oso.set_query_timeout(Duration::from_secs(seconds))
+                }
+                _ => anyhow::bail!("the timeout must be a positive number of seconds"),
+            },
+            (":clear", []) => {
+                self.oso.clear_rules();
+                self.files.clear();
+            }
+            _ => anyhow::bail!("invalid command {}, see :help", input.trim()),
         }
-        input.pop(); // remove trailing `;`
-        let mut query = match oso.query(&input) {
+        Ok(())
+    }
+
+    /// Run a query and print its results.
+    pub fn query(&mut self, input: &str) {
+        let input = input.trim_end();
+        let input = input.strip_suffix(';').unwrap_or(input);
+        let mut query = match self.oso.query(input) {
             Err(e) => {
                 println!("{}", e);
-                continue;
+                return;
             }
             Ok(q) => q,
         };
@@ -138,6 +347,9 @@ pub fn main() -> anyhow::Result<()> {
         while let Some(res) = query.next() {
             has_result = true;
             if let Ok(res) = res {
+                if let Some(trace) = res.trace() {
+                    println!("{}", trace.trim_end());
+                }
                 if res.is_empty() {
                     println!("true");
                 } else {
@@ -153,5 +365,254 @@ pub fn main() -> anyhow::Result<()> {
             println!("false")
         }
     }
+}
+
+pub fn main() -> anyhow::Result<()> {
+    tracing_subscriber::fmt::init();
+
+    let mut args = env::args().peekable();
+    let _ = args.next(); // skip the binary filename
+    if args.peek().map(String::as_str) == Some("fmt") {
+        let _ = args.next();
+        return fmt(&mut args, &mut io::stdout());
+    }
+    if args.peek().map(String::as_str) == Some("test") {
+        let _ = args.next();
+        return test(&mut args, &mut io::stdout());
+    }
+
+    let mut repl = Repl::new();
+    let mut oso = Oso::new();
+    oso.set_debugger(StdioDebugger);
+    let mut session = Session::new(oso);
+    session.load_files(&args.collect::<Vec<_>>())?;
+    loop {
+        // get input, reading more lines until it is complete
+        let mut input: String = match repl.oso_input("query> ") {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("Readline error: {}", e);
+                break;
+            }
+        };
+        while !is_complete(&input) {
+            match repl.oso_input("   ...> ") {
+                Ok(more) => {
+                    input.push('\n');
+                    input.push_str(&more);
+                }
+                Err(e) => {
+                    eprintln!("Readline error: {}", e);
+                    return Ok(());
+                }
+            }
+        }
+
+        if input.trim_start().starts_with(':') {
+            if let Err(e) = session.command(&input) {
+                println!("{}", e);
+            }
+        } else if let Some(src) = input.strip_prefix("%def") {
+            if let Err(e) = session.oso.load_str(src) {
+                println!("{}", e);
+            }
+        } else if !input.trim().is_empty() {
+            session.query(&input);
+        }
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Write;
+
+    fn fixture_file(suffix: &str, contents: &str) -> tempfile::NamedTempFile {
+        let mut file = tempfile::Builder::new().suffix(suffix).tempfile().unwrap();
+        write!(file, "{}", contents).unwrap();
+        file
+    }
+
+    fn load_error(contents: &str) -> String {
+        let file = fixture_file(".yaml", contents);
+        let path = file.path().to_str().unwrap();
+        let err = load_fixtures(&mut Oso::new(), path)
+            .unwrap_err()
+            .to_string();
+        err.strip_prefix(&format!("{}: ", path))
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn test_load_fixtures() {
+        let mut oso = Oso::new();
+        let file = fixture_file(
+            ".yaml",
+            "classes:\n  User: [name, role]\ninstances:\n  alice:\n    class: User\n    fields: { name: alice, role: admin }\n",
+        );
+        load_fixtures(&mut oso, file.path().to_str().unwrap()).unwrap();
+        let file = fixture_file(
+            ".json",
+            r#"{"classes": {"Doc": ["owner"]}, "instances": {"doc": {"class": "Doc", "fields": {"owner": "alice"}}}}"#,
+        );
+        load_fixtures(&mut oso, file.path().to_str().unwrap()).unwrap();
+        let mut query = oso
+            .query("alice matches User and doc.owner = alice.name and alice.role = \"admin\"")
+            .unwrap();
+        assert!(query.next().is_some());
+
+        // Instances without fields have none set.
+        let file = fixture_file(
+            ".yaml",
+            "classes:\n  Guest: [name]\ninstances:\n  guest: { class: Guest }",
+        );
+        load_fixtures(&mut oso, file.path().to_str().unwrap()).unwrap();
+        let mut query = oso.query("guest matches Guest").unwrap();
+        assert!(query.next().is_some());
+    }
+
+    #[test]
+    fn test_load_fixtures_errors() {
+        assert_eq!(
+            load_error("classes: [User]"),
+            "`classes` must map class names to lists of fields"
+        );
+        assert_eq!(
+            load_error("classes:\n  User: name"),
+            "the fields of User must be a list of names"
+        );
+        assert_eq!(
+            load_error("instances: [alice]"),
+            "`instances` must map constant names to instances"
+        );
+        assert_eq!(load_error("instances:\n  alice: {}"), "alice needs a class");
+        assert_eq!(
+            load_error("instances:\n  alice: { class: User }"),
+            "User is not a declared class"
+        );
+        assert_eq!(
+            load_error(
+                "classes:\n  User: [name]\ninstances:\n  alice: { class: User, fields: { age: 3 } }"
+            ),
+            "User has no field age"
+        );
+        assert!(load_error(
+            "classes:\n  User: [name]\ninstances:\n  alice: { class: User, fields: [alice] }"
+        )
+        .starts_with("the fields of alice: "));
+    }
+
+    #[test]
+    fn test_load_fixtures_after_error() {
+        // Nothing is registered from an invalid file, so it can be loaded
+        // again once it is fixed.
+        let mut oso = Oso::new();
+        let file = fixture_file(
+            ".yaml",
+            "classes:\n  User: [name]\ninstances:\n  alice: { class: User, fields: { age: 3 } }",
+        );
+        let path = file.path().to_str().unwrap().to_string();
+        load_fixtures(&mut oso, &path).unwrap_err();
+        fs::write(
+            &path,
+            "classes:\n  User: [name]\ninstances:\n  alice: { class: User, fields: { name: alice } }",
+        )
+        .unwrap();
+        load_fixtures(&mut oso, &path).unwrap();
+        assert!(oso.query("alice matches User").unwrap().next().is_some());
+
+        // Nor from a file with names that are registered already.
+        let file = fixture_file(
+            ".yaml",
+            "classes:\n  Doc: [owner]\n  User: [name]\ninstances:\n  doc: { class: Doc, fields: { owner: alice } }",
+        );
+        let path = file.path().to_str().unwrap();
+        let err = load_fixtures(&mut oso, path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("{}: User is already registered", path)
+        );
+        assert!(!oso.is_registered("Doc"));
+        assert!(!oso.is_registered("doc"));
+    }
+
+    fn args(args: &[&str]) -> impl Iterator<Item = String> {
+        args.iter()
+            .map(|arg| arg.to_string())
+            .collect::<Vec<_>>()
+            .into_iter()
+    }
+
+    #[test]
+    fn test_fmt() {
+        let unformatted = "allow(actor,_action,_resource)if actor=\"alice\";";
+        let formatted = format_policy(unformatted, None).unwrap();
+        assert_ne!(formatted, unformatted);
+        let messy = fixture_file(".polar", unformatted);
+        let tidy = fixture_file(".polar", &formatted);
+        let messy = messy.path().to_str().unwrap();
+        let tidy = tidy.path().to_str().unwrap();
+
+        // With `--check`, the unformatted files are listed and left as they
+        // are, and the command fails.
+        let mut out = vec![];
+        let err = fmt(&mut args(&["--check", messy, tidy]), &mut out).unwrap_err();
+        assert_eq!(err.to_string(), "1 file(s) are not formatted");
+        assert_eq!(String::from_utf8(out).unwrap(), format!("{}\n", messy));
+        assert_eq!(fs::read_to_string(messy).unwrap(), unformatted);
+
+        // Otherwise, they are formatted in place.
+        let mut out = vec![];
+        fmt(&mut args(&[messy, tidy]), &mut out).unwrap();
+        assert!(out.is_empty());
+        assert_eq!(fs::read_to_string(messy).unwrap(), formatted);
+        fmt(&mut args(&["--check", messy, tidy]), &mut out).unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn test_test() {
+        let policy = fixture_file(
+            ".polar",
+            r#"allow(actor, "read", _resource) if actor = "alice";"#,
+        );
+        let tests = fixture_file(
+            ".test.polar",
+            "test(\"alice can read\") if allow(\"alice\", \"read\", \"doc\");\n\
+             test(\"bob can read\") if allow(\"bob\", \"read\", \"doc\");\n",
+        );
+        let policy = policy.path().to_str().unwrap();
+        let tests = tests.path().to_str().unwrap();
+
+        let mut out = vec![];
+        let err = test(&mut args(&[tests, policy]), &mut out).unwrap_err();
+        assert_eq!(err.to_string(), "1 test(s) failed");
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            format!(
+                "PASS alice can read ({0}:1)\n\
+                 FAIL bob can read ({0}:2): expected the query to succeed, but it failed\n\
+                 1 passed, 1 failed\n",
+                tests
+            )
+        );
+
+        // Without failures, the command succeeds.
+        let passing = fixture_file(
+            ".test.polar",
+            "test(\"alice can read\") if allow(\"alice\", \"read\", \"doc\");\n",
+        );
+        let mut out = vec![];
+        test(
+            &mut args(&[passing.path().to_str().unwrap(), policy]),
+            &mut out,
+        )
+        .unwrap();
+        assert!(String::from_utf8(out)
+            .unwrap()
+            .ends_with("1 passed, 0 failed\n"));
+    }
+}
@@ -0,0 +1,277 @@
+//! Policy test files.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use polar_core::diagnostic::Position;
+use polar_core::formatting::to_polar::ToPolarString;
+use polar_core::parser::{parse_lines, Line};
+use polar_core::rules::Rule;
+use polar_core::sources::Source;
+use polar_core::terms::{Term, Value};
+
+use crate::errors::OsoError;
+use crate::ResultSet;
+
+/// Name of the rules that declare test cases.
+const TEST_RULE: &str = "test";
+
+/// The outcome a test case expects from its query.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expectation {
+    /// At least one result.
+    Allow,
+    /// No results.
+    Deny,
+    /// Exactly these results, in order. Each result lists the bindings it
+    /// must have; other bindings are ignored.
+    Results(Vec<BTreeMap<String, Term>>),
+}
+
+/// A test case of a test file.
+///
+/// A test file is a Polar file whose rules named `test` are test cases. The
+/// first parameter of a case is its name, the body is the query to run, and
+/// an optional second parameter is the expected outcome:
+///
+/// ```polar
+/// # The query must have a result (the default).
+/// test("alice can read", allow) if allow("alice", "read", "doc");
+/// # The query must not have a result.
+/// test("bob can't delete", deny) if allow("bob", "delete", "doc");
+/// # The query must have exactly these results, in order.
+/// test("alice's roles", [{role: "admin"}, {role: "member"}]) if role("alice", role);
+/// ```
+///
+/// The other rules of a test file are fixtures, and are loaded before the
+/// cases run.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestCase {
+    pub name: String,
+    /// Source of the query.
+    pub query: String,
+    pub expected: Expectation,
+    pub filename: String,
+    pub line: usize,
+}
+
+impl TestCase {
+    /// Check the results of the query, and describe how they differ from
+    /// the expected ones.
+    pub fn check(&self, results: &[ResultSet]) -> Result<(), String> {
+        match &self.expected {
+            Expectation::Allow if results.is_empty() => {
+                Err("expected the query to succeed, but it failed".to_string())
+            }
+            Expectation::Deny if !results.is_empty() => Err(format!(
+                "expected the query to fail, but it had {} result(s)",
+                results.len()
+            )),
+            Expectation::Results(expected) => {
+                let matches = expected.len() == results.len()
+                    && expected.iter().zip(results).all(|(expected, result)| {
+                        expected.iter().all(|(name, value)| {
+                            result
+                                .iter_bindings()
+                                .any(|(k, v)| k == name && v == value.value())
+                        })
+                    });
+                if matches {
+                    return Ok(());
+                }
+                let got: Vec<String> = results
+                    .iter()
+                    .map(|result| {
+                        let fields: Vec<String> = expected
+                            .iter()
+                            .flat_map(|bindings| bindings.keys())
+                            .collect::<std::collections::BTreeSet<_>>()
+                            .into_iter()
+                            .filter_map(|name| {
+                                result
+                                    .iter_bindings()
+                                    .find(|(k, _)| k == name)
+                                    .map(|(_, value)| format!("{}: {}", name, value.to_polar()))
+                            })
+                            .collect();
+                        format!("{{{}}}", fields.join(", "))
+                    })
+                    .collect();
+                let expected: Vec<String> = expected
+                    .iter()
+                    .map(|bindings| {
+                        let fields: Vec<String> = bindings
+                            .iter()
+                            .map(|(name, value)| format!("{}: {}", name, value.to_polar()))
+                            .collect();
+                        format!("{{{}}}", fields.join(", "))
+                    })
+                    .collect();
+                Err(format!(
+                    "expected results [{}], got [{}]",
+                    expected.join(", "),
+                    got.join(", ")
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The cases of a test file, and the source of its other rules.
+#[derive(Clone, Debug)]
+pub(crate) struct TestFile {
+    /// The file with the test cases blanked out, so that locations in the
+    /// fixtures are unchanged.
+    pub fixtures: String,
+    pub cases: Vec<TestCase>,
+}
+
+impl TestFile {
+    pub fn parse(src: &str, filename: &str) -> crate::Result<Self> {
+        let lines = parse_lines(0, src).map_err(|e| {
+            let source = Source {
+                filename: Some(filename.to_string()),
+                src: src.to_string(),
+            };
+            e.set_context(Some(&source), None)
+        })?;
+        let mut fixtures = src.to_string();
+        let mut cases = vec![];
+        for line in lines {
+            let rule = match line {
+                Line::Rule(rule) if rule.name.0 == TEST_RULE => rule,
+                _ => continue,
+            };
+            let (start, end, case) = test_case(&rule, src, filename)?;
+            let blank: String = src[start..end]
+                .chars()
+                .map(|c| if c == '\n' { c } else { ' ' })
+                .collect();
+            fixtures.replace_range(start..end, &blank);
+            cases.push(case);
+        }
+        Ok(Self { fixtures, cases })
+    }
+}
+
+/// The test case declared by `rule`, and the offsets of the rule in `src`.
+fn test_case(rule: &Rule, src: &str, filename: &str) -> crate::Result<(usize, usize, TestCase)> {
+    let (body_left, body_right) = rule.body.span().unwrap_or_default();
+    let head = rule
+        .params
+        .iter()
+        .filter_map(|param| param.parameter.span())
+        .map(|(left, _)| left)
+        .chain(std::iter::once(body_left))
+        .min()
+        .unwrap();
+    let start = src[..head].rfind(TEST_RULE).unwrap_or(head);
+    let end = src[body_right..]
+        .find(';')
+        .map_or(body_right, |i| body_right + i + 1);
+    let line = Position::from_offset(src, start).line;
+    let invalid = |message: &str| OsoError::InvalidTestCase {
+        location: format!("{}:{}", filename, line),
+        message: message.to_string(),
+    };
+
+    if rule.params.iter().any(|param| param.specializer.is_some()) {
+        return Err(invalid("test parameters can't have specializers"));
+    }
+    let name = match rule.params.first().map(|param| param.parameter.value()) {
+        Some(Value::String(name)) => name.clone(),
+        _ => return Err(invalid("the first parameter must be the name of the test")),
+    };
+    let expected = match rule.params.get(1).map(|param| param.parameter.value()) {
+        None => Expectation::Allow,
+        Some(Value::Variable(symbol)) if symbol.0 == "allow" => Expectation::Allow,
+        Some(Value::Variable(symbol)) if symbol.0 == "deny" => Expectation::Deny,
+        Some(Value::List(results)) => Expectation::Results(
+            results
+                .iter()
+                .map(|result| match result.value() {
+                    Value::Dictionary(bindings) => Ok(bindings
+                        .fields
+                        .iter()
+                        .map(|(name, value)| (name.0.clone(), value.clone()))
+                        .collect()),
+                    _ => Err(invalid("expected results must be dictionaries of bindings")),
+                })
+                .collect::<crate::Result<_>>()?,
+        ),
+        Some(_) => {
+            return Err(invalid(
+                "the expected outcome must be allow, deny, or a list of results",
+            ))
+        }
+    };
+    if rule.params.len() > 2 {
+        return Err(invalid("a test has a name and an expected outcome"));
+    }
+    let query = src[body_left..body_right].trim();
+    if query.is_empty() {
+        return Err(invalid("a test needs a query"));
+    }
+
+    let case = TestCase {
+        name,
+        query: query.to_string(),
+        expected,
+        filename: filename.to_string(),
+        line,
+    };
+    Ok((start, end, case))
+}
+
+#[derive(Clone, Debug)]
+pub struct TestResult {
+    pub case: TestCase,
+    /// Why the case failed, or `None` if it passed.
+    pub failure: Option<String>,
+}
+
+impl TestResult {
+    pub fn passed(&self) -> bool {
+        self.failure.is_none()
+    }
+}
+
+/// Results of the cases of a test file.
+#[derive(Clone, Debug, Default)]
+pub struct TestReport {
+    pub results: Vec<TestResult>,
+}
+
+impl TestReport {
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(TestResult::passed)
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &TestResult> {
+        self.results.iter().filter(|result| !result.passed())
+    }
+}
+
+impl fmt::Display for TestReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for result in &self.results {
+            let case = &result.case;
+            match &result.failure {
+                None => writeln!(f, "PASS {} ({}:{})", case.name, case.filename, case.line)?,
+                Some(failure) => writeln!(
+                    f,
+                    "FAIL {} ({}:{}): {}",
+                    case.name, case.filename, case.line, failure
+                )?,
+            }
+        }
+        let failed = self.failures().count();
+        write!(
+            f,
+            "{} passed, {} failed",
+            self.results.len() - failed,
+            failed
+        )
+    }
+}
@@ -502,7 +502,7 @@ fn test_class_does_not_exist() {
     assert!(query.next().unwrap().is_err());
 }
 
-/// Test that using keyword arguments for constructor raises error:
+/// Test that using keyword arguments for constructor without named parameters raises error:
 /// - Keyword args only
 /// - Mixed parameters
 #[test]
@@ -539,7 +539,7 @@ fn test_constructor_keyword_arguments_error() {
     assert!(query.next().unwrap().is_err());
 }
 
-/// Test that using keyword arguments for method raises error:
+/// Test that using keyword arguments for method without named parameters raises error:
 /// - Keyword args only
 /// - Mixed parameters
 #[test]
@@ -583,9 +583,9 @@ fn test_method_keyword_arguments_error() -> oso::Result<()> {
     Ok(())
 }
 
-/// Test operator raises not implemented error.
+/// Test comparison raises unsupported error for a class without a comparison check.
 #[test]
-fn test_operator_unimplemented() -> oso::Result<()> {
+fn test_operator_unsupported() -> oso::Result<()> {
     common::setup();
 
     let mut oso = OsoTest::new();
@@ -602,7 +602,17 @@ fn test_operator_unimplemented() -> oso::Result<()> {
 
     assert!(Foo(0) < Foo(1));
     let mut query = oso.oso.query_rule("lt", (Foo(0), Foo(1))).unwrap();
-    assert!(query.next().unwrap().is_err());
+    let error = query.next().unwrap().unwrap_err();
+    assert!(
+        matches!(
+            &error,
+            OsoError::UnsupportedOperation {
+                operation,
+                type_name
+            } if operation == "comparison" && type_name == "Foo"),
+        "{} doesn't match expected error",
+        error
+    );
 
     Ok(())
 }
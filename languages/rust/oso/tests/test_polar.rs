@@ -3,7 +3,7 @@ use std::collections::HashMap;
 use std::io::Write;
 use std::path::{Path, PathBuf};
 
-use oso::{Class, FromPolarValue, Oso, OsoError, PolarClass, PolarValue};
+use oso::{Class, DynamicInstance, FromPolarValue, Oso, OsoError, PolarClass, PolarValue};
 use polar_core::error as polar_error;
 
 use maplit::hashmap;
@@ -263,8 +263,14 @@ fn test_load_multiple_files() -> oso::Result<()> {
     let path = test_file_path();
     let path_gx = test_file_gx_path();
 
-    oso.oso.load_file(path)?;
-    oso.oso.load_file(path_gx)?;
+    oso.oso.load_file(&path)?;
+    oso.oso.load_file(&path_gx)?;
+
+    assert_eq!(oso.qvar::<i64>("f(x)", "x"), vec![1, 2, 3]);
+    assert_eq!(oso.qvar::<i64>("g(x)", "x"), vec![1, 2, 3]);
+
+    let mut oso = test_oso();
+    oso.oso.load_files(&[path, path_gx])?;
 
     assert_eq!(oso.qvar::<i64>("f(x)", "x"), vec![1, 2, 3]);
     assert_eq!(oso.qvar::<i64>("g(x)", "x"), vec![1, 2, 3]);
@@ -722,7 +728,19 @@ fn test_inline_queries() {
     oso.load_str("f(1); f(2); ?= f(1); ?= not f(3);");
 
     // Fails if inline fails.
-    oso.oso.load_str("g(1); ?= g(2);").unwrap_err();
+    let err = oso.oso.load_str("g(1); ?= g(2);").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Inline query failed: expected a result for g(2) at line 1, column 9"
+    );
+
+    // Expected results.
+    oso.load_str("h(1); h(2); ?= h(3), false; ?= h(x), 2; ?= h(x), [{x: 1}, {x: 2}];");
+    let err = oso.oso.load_str("?= h(x), [{x: 2}];").unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "Inline query failed: expected results [{x: 2}], got [{x: 1}, {x: 2}] at line 1, column 4"
+    );
 }
 
 // Skipped parse error tests.
@@ -839,3 +857,384 @@ fn test_nan_inf() -> oso::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_debugger() -> oso::Result<()> {
+    common::setup();
+
+    let messages = std::sync::Arc::new(std::sync::Mutex::new(vec![]));
+    let recorded = messages.clone();
+    let mut oso = test_oso();
+    oso.oso.set_debugger(move |message: &str| {
+        let mut messages = recorded.lock().unwrap();
+        messages.push(message.to_string());
+        match messages.len() {
+            1 => "var x".to_string(),
+            _ => "continue".to_string(),
+        }
+    });
+    oso.load_str("f(x) if debug() and x = 1;");
+
+    oso.qvar_one("f(x)", "x", 1);
+    let messages = messages.lock().unwrap();
+    assert_eq!(messages.len(), 2);
+    assert!(messages[0].contains("debug()"));
+    assert_eq!(messages[1], "x = _x_2");
+
+    // A debugger set on the query replaces the one set on `Oso`.
+    let commands = std::rc::Rc::new(std::cell::RefCell::new(0));
+    let count = commands.clone();
+    let mut query = oso.oso.query("f(x)")?;
+    query.set_debugger(move |_: &str| {
+        *count.borrow_mut() += 1;
+        "continue".to_string()
+    });
+    assert_eq!(query.count(), 1);
+    assert_eq!(*commands.borrow(), 1);
+    assert_eq!(messages.len(), 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_record_and_replay() -> oso::Result<()> {
+    common::setup();
+
+    #[derive(PolarClass, Clone)]
+    struct User {
+        #[polar(attribute)]
+        name: String,
+    }
+
+    impl User {
+        fn roles(&self) -> Vec<String> {
+            vec!["reader".to_string(), "writer".to_string()]
+        }
+    }
+
+    let policy = r#"allow(user: User, action, "doc") if
+                        role in user.roles() and
+                        can(role, action);
+                    can("reader", "read");
+                    can("writer", "write");"#;
+
+    let mut oso = Oso::new();
+    oso.register_class(
+        User::get_polar_class_builder()
+            .add_method("roles", User::roles)
+            .build(),
+    )?;
+    oso.load_str(policy)?;
+
+    let user = User {
+        name: "alice".to_string(),
+    };
+    let mut query = oso.query_rule(
+        "allow",
+        (user, PolarValue::Variable("x".to_string()), "doc"),
+    )?;
+    query.start_recording();
+    let results = query.by_ref().collect::<oso::Result<Vec<_>>>()?;
+    let recording = query.take_recording().unwrap();
+    let json = recording.to_json()?;
+
+    // Replay without the `User` class.
+    let mut replay_oso = Oso::new();
+    replay_oso.load_str(policy)?;
+    let replayed = replay_oso.replay(oso::Recording::from_json(&json)?)?;
+    assert_eq!(replayed.len(), results.len());
+    let actions = replayed
+        .iter()
+        .map(|result| result.get_typed::<String>("x"))
+        .collect::<oso::Result<Vec<_>>>()?;
+    assert_eq!(actions, vec!["read", "write"]);
+
+    // The recording's constants are gone after the replay, so `User` is a
+    // variable again.
+    assert!(replay_oso.query("User = 1")?.next().is_some());
+
+    // A changed policy makes the replay diverge.
+    let mut changed_oso = Oso::new();
+    changed_oso.load_str(r#"allow(_user, _action, "doc");"#)?;
+    let error = changed_oso
+        .replay(oso::Recording::from_json(&json)?)
+        .unwrap_err();
+    assert!(error.to_string().contains("replay diverged"));
+
+    Ok(())
+}
+
+#[test]
+fn test_profiling() -> oso::Result<()> {
+    common::setup();
+
+    #[derive(PolarClass, Clone)]
+    struct User;
+
+    impl User {
+        fn roles(&self) -> Vec<String> {
+            vec!["reader".to_string(), "writer".to_string()]
+        }
+    }
+
+    let mut oso = Oso::new();
+    oso.register_class(
+        User::get_polar_class_builder()
+            .add_method("roles", User::roles)
+            .build(),
+    )?;
+    oso.load_str(
+        r#"allow(user: User, action, _resource) if
+               role in user.roles() and
+               can(role, action);
+           can("writer", "write");"#,
+    )?;
+    oso.enable_profiling(true);
+
+    let mut query = oso.query_rule("allow", (User, "write", "doc"))?;
+    assert!(query.profile().is_some());
+    assert_eq!(query.by_ref().count(), 1);
+    let profile = query.profile().unwrap();
+    assert_eq!(profile.rules["allow"].calls, 1);
+    assert_eq!(profile.rules["allow"].successes, 1);
+    assert_eq!(profile.rules["can"].calls, 2);
+    assert_eq!(profile.rules["can"].failures, 1);
+    assert_eq!(profile.externals["roles"].calls, 1);
+    drop(query);
+
+    assert!(oso.is_allowed(User, "write", "doc")?);
+    let total = oso.profile().unwrap();
+    assert_eq!(total.rules["allow"].calls, 2);
+    assert_eq!(total.externals["roles"].calls, 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_coverage() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = Oso::new();
+    oso.load_str(
+        r#"allow(actor, "read", _resource) if actor = "alice";
+           allow(actor, "write", _resource) if actor = "admin";"#,
+    )?;
+    oso.enable_coverage(true);
+
+    assert!(oso.is_allowed("alice", "read", "doc")?);
+    assert!(!oso.is_allowed("bob", "read", "doc")?);
+
+    let report = oso.coverage_report().unwrap();
+    let rules = &report.files[0].rules;
+    assert_eq!(rules.len(), 2);
+    assert_eq!((rules[0].line, rules[0].hits.evaluated), (1, 2));
+    assert_eq!(rules[0].hits.succeeded, 1);
+    assert_eq!(rules[0].conditions[0].source, r#"actor = "alice""#);
+    assert_eq!(rules[1].hits.evaluated, 0);
+    Ok(())
+}
+
+#[test]
+fn test_trace_export() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = Oso::new();
+    oso.load_str(r#"allow(actor, "read", _resource) if actor = "alice";"#)?;
+
+    let mut query = oso.query_rule("allow", ("bob", "read", "doc"))?;
+    query.export_traces(true);
+    assert_eq!(query.by_ref().count(), 0);
+    let traces = query.take_traces();
+    assert_eq!(traces.len(), 1);
+    assert!(!traces[0].passed);
+    let rule = &traces[0].root.children[0];
+    assert_eq!(rule.kind, oso::NodeKind::Rule);
+    assert!(!rule.passed);
+
+    let mut query = oso.query_rule("allow", ("alice", "read", "doc"))?;
+    query.export_traces(false);
+    assert_eq!(query.by_ref().count(), 1);
+    let traces = query.take_traces();
+    assert_eq!(traces.len(), 1);
+    assert!(traces[0].passed);
+    assert!(traces[0]
+        .to_json()?
+        .contains(r#""source":"actor = \"alice\"""#));
+    Ok(())
+}
+
+#[test]
+fn test_run_tests() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = Oso::new();
+    oso.load_str(
+        r#"allow(actor, "read", _resource) if role(actor, "member");
+           role("alice", "admin");
+           role("alice", "member");"#,
+    )?;
+
+    let mut tempfile = tempfile::Builder::new()
+        .suffix(".polar")
+        .tempfile()
+        .unwrap();
+    let file = tempfile.as_file_mut();
+    write!(
+        file,
+        r#"role("bob", "member");
+test("alice can read") if allow("alice", "read", "doc");
+test("bob can read", allow) if allow("bob", "read", "doc");
+test("carol can't read", deny) if allow("carol", "read", "doc");
+test("alice's roles", [{{r: "admin"}}, {{r: "member"}}]) if role("alice", r);
+test("bob's roles", [{{r: "admin"}}]) if
+    role("bob", r);
+test("alice can't read", deny) if allow("alice", "read", "doc");
+"#
+    )
+    .unwrap();
+    file.sync_all().unwrap();
+
+    let report = oso.run_tests(tempfile.path())?;
+    let filename = tempfile.path().to_string_lossy().into_owned();
+    assert!(!report.passed());
+    assert_eq!(report.results.len(), 6);
+    assert_eq!(
+        report.to_string(),
+        format!(
+            r#"PASS alice can read ({0}:2)
+PASS bob can read ({0}:3)
+PASS carol can't read ({0}:4)
+PASS alice's roles ({0}:5)
+FAIL bob's roles ({0}:6): expected results [{{r: "admin"}}], got [{{r: "member"}}]
+FAIL alice can't read ({0}:8): expected the query to fail, but it had 1 result(s)
+4 passed, 2 failed"#,
+            filename
+        )
+    );
+
+    // The fixtures are only loaded for the test run, so the tests can run
+    // again.
+    assert!(oso.query(r#"role("bob", "member")"#)?.next().is_none());
+    assert_eq!(oso.run_tests(tempfile.path())?.results.len(), 6);
+
+    let mut tempfile = tempfile::Builder::new()
+        .suffix(".polar")
+        .tempfile()
+        .unwrap();
+    writeln!(tempfile.as_file_mut(), r#"test(name) if name = 1;"#).unwrap();
+    let err = Oso::new().run_tests(tempfile.path()).unwrap_err();
+    assert!(matches!(err, OsoError::InvalidTestCase { .. }));
+    assert!(err
+        .to_string()
+        .ends_with(":1: the first parameter must be the name of the test"));
+    Ok(())
+}
+
+#[test]
+fn test_tracing_rules_and_timeout() -> oso::Result<()> {
+    common::setup();
+
+    let mut oso = Oso::new();
+    oso.load_str(
+        r#"f(1);
+           f(x) if x = 2;
+           count(0);
+           count(n) if n > 0 and count(n - 1);"#,
+    )?;
+
+    assert_eq!(oso.rule_names(), vec!["count", "f"]);
+    assert_eq!(oso.rule_sources("f"), vec!["f(1);", "f(x) if x = 2;"]);
+    assert!(oso.rule_sources("g").is_empty());
+
+    let result = oso.query("f(2)")?.next().unwrap()?;
+    assert!(result.trace().is_none());
+    oso.enable_tracing(true);
+    let result = oso.query("f(2)")?.next().unwrap()?;
+    assert!(result.trace().unwrap().contains("f(x) if x = 2;"));
+
+    oso.set_query_timeout(std::time::Duration::from_secs(0));
+    let err = oso.query("count(100)")?.next().unwrap().unwrap_err();
+    assert!(err.to_string().starts_with("Query timeout"));
+    Ok(())
+}
+
+#[test]
+fn test_dynamic_classes() -> oso::Result<()> {
+    common::setup();
+
+    let mut test = OsoTest::new();
+    test.oso
+        .register_class(Class::dynamic("User", &["name", "role"]))?;
+    test.oso.register_class(Class::dynamic("Doc", &["owner"]))?;
+    let alice = DynamicInstance::new(
+        "User",
+        hashmap! {
+            "name".to_string() => PolarValue::String("alice".to_string()),
+            "role".to_string() => PolarValue::String("admin".to_string()),
+        },
+    );
+    test.oso.register_constant(alice, "alice")?;
+    test.load_str(r#"allow(user: User, "read", doc: Doc) if doc.owner = user.name;"#);
+
+    test.qvar_one("x = alice.role", "x", "admin".to_string());
+    test.qeval("alice matches User");
+    test.qnull("alice matches Doc");
+    test.qeval(r#"allow(alice, "read", new Doc("alice"))"#);
+    test.qnull(r#"allow(alice, "read", new Doc("bob"))"#);
+    test.qvar_one(
+        r#"x = new User("bob", "member").role"#,
+        "x",
+        "member".to_string(),
+    );
+
+    let err = test.query_err(r#"new Doc("alice", "bob")"#);
+    assert!(err.contains("Expected 1 arguments"), "{}", err);
+    let err = test.query_err("alice.age");
+    assert!(err.contains("Attribute age not found"), "{}", err);
+    Ok(())
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_dynamic_instances_from_json() -> oso::Result<()> {
+    common::setup();
+
+    let mut test = OsoTest::new();
+    test.oso
+        .register_class(Class::dynamic("Row", &["id", "owner", "tags", "meta"]))?;
+    let row = serde_json::json!({
+        "id": 1,
+        "owner": "alice",
+        "tags": ["a", "b"],
+        "meta": { "size": 2.5 },
+        "secret": "hidden",
+        "deleted_at": null,
+    });
+    test.oso
+        .register_constant(DynamicInstance::from_json("Row", &row)?, "row")?;
+    let mut same = row.clone();
+    same.as_object_mut().unwrap().remove("deleted_at");
+    test.oso
+        .register_constant(DynamicInstance::from_json("Row", &same)?, "same")?;
+    test.oso.register_constant(
+        DynamicInstance::from_json("Row", &serde_json::json!({ "id": 2 }))?,
+        "other",
+    )?;
+
+    test.qeval("row matches Row");
+    test.qvar_one("x = row.owner", "x", "alice".to_string());
+    test.qvar_one("x = row.meta.size", "x", 2.5);
+    test.qvar_one("x = row.tags", "x", vec!["a".to_string(), "b".to_string()]);
+    test.qeval("row = same");
+    test.qnull("row = other");
+
+    // Only declared fields are attributes, and null fields are left out.
+    let err = test.query_err("row.secret");
+    assert!(err.contains("Attribute secret not found"), "{}", err);
+    let err = test.query_err("row.deleted_at");
+    assert!(err.contains("Attribute deleted_at not found"), "{}", err);
+
+    let err = DynamicInstance::from_json("Row", &serde_json::json!([1])).unwrap_err();
+    assert!(matches!(err, OsoError::TypeError(_)));
+    Ok(())
+}
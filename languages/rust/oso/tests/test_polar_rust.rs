@@ -488,6 +488,387 @@ fn test_unify_externals() {
     assert!(result.is_none());
 }
 
+#[test]
+fn test_compare_externals() {
+    let mut test = OsoTest::new();
+
+    #[derive(PartialEq, PartialOrd, Clone, Debug)]
+    struct Date {
+        day: i64,
+    }
+
+    impl PolarClass for Date {}
+    impl Date {
+        fn new(day: i64) -> Self {
+            Self { day }
+        }
+    }
+
+    let date_class = ClassBuilder::with_constructor(Date::new)
+        .name("Date")
+        .with_equality_check()
+        .with_comparison_check()
+        .build();
+    test.oso.register_class(date_class).unwrap();
+
+    test.qeval("new Date(1) < new Date(2)");
+    test.qnull("new Date(2) < new Date(1)");
+    test.qeval("new Date(1) <= new Date(1)");
+    test.qeval("new Date(2) > new Date(1)");
+    test.qnull("new Date(1) >= new Date(2)");
+    test.qeval("new Date(1) == new Date(1)");
+    test.qeval("new Date(1) != new Date(2)");
+    test.qnull("new Date(1) != new Date(1)");
+
+    test.load_str("before(a, b) if a < b;");
+    let mut results = test
+        .oso
+        .query_rule("before", (Date::new(1), Date::new(2)))
+        .unwrap();
+    results.next().expect("At least one result").unwrap();
+
+    // A custom ordering, where `None` satisfies no comparison.
+    #[derive(Clone, Debug)]
+    struct Version(Option<i64>);
+
+    impl PolarClass for Version {}
+
+    let version_class = ClassBuilder::with_constructor(|v: i64| Version(Some(v)))
+        .name("Version")
+        .add_class_method("unknown", || Version(None))
+        .set_comparison_check(|a: &Version, b: &Version| match (a.0, b.0) {
+            (Some(a), Some(b)) => Some(a.cmp(&b)),
+            _ => None,
+        })
+        .build();
+    test.oso.register_class(version_class).unwrap();
+
+    test.qeval("new Version(1) < new Version(2)");
+    test.qnull("new Version(1) < Version.unknown()");
+    test.qnull("new Version(1) >= Version.unknown()");
+}
+
+#[test]
+fn test_class_inheritance() {
+    let mut test = OsoTest::new();
+
+    // A class with no instances of its own, like a trait.
+    #[derive(Clone)]
+    struct Animal;
+    impl PolarClass for Animal {}
+
+    #[derive(Clone)]
+    struct Dog;
+    impl PolarClass for Dog {}
+
+    #[derive(Clone)]
+    struct Puppy;
+    impl PolarClass for Puppy {}
+
+    #[derive(Clone)]
+    struct Cat;
+    impl PolarClass for Cat {}
+
+    test.oso.register_class(Animal::get_polar_class()).unwrap();
+    test.oso
+        .register_class(
+            ClassBuilder::with_constructor(|| Dog)
+                .add_superclass("Animal")
+                .build(),
+        )
+        .unwrap();
+    test.oso
+        .register_class(
+            ClassBuilder::with_constructor(|| Puppy)
+                .add_superclass("Dog")
+                .build(),
+        )
+        .unwrap();
+    test.oso
+        .register_class(
+            ClassBuilder::with_constructor(|| Cat)
+                .add_superclass("Animal")
+                .build(),
+        )
+        .unwrap();
+
+    test.qeval("new Puppy() matches Puppy");
+    test.qeval("new Puppy() matches Dog");
+    test.qeval("new Puppy() matches Animal");
+    test.qnull("new Dog() matches Puppy");
+    test.qnull("new Cat() matches Dog");
+
+    // The most specific rules come first, whatever the order they're defined in.
+    test.load_str(
+        r#"kind(_: Animal, "animal");
+           kind(_: Puppy, "puppy");
+           kind(_: Dog, "dog");"#,
+    );
+    assert_eq!(
+        test.qvar::<String>("kind(new Puppy(), x)", "x"),
+        vec!["puppy", "dog", "animal"]
+    );
+    assert_eq!(
+        test.qvar::<String>("kind(new Cat(), x)", "x"),
+        vec!["animal"]
+    );
+    test.oso.register_constant(Dog, "rex").unwrap();
+    assert_eq!(
+        test.qvar::<String>("kind(rex, x)", "x"),
+        vec!["dog", "animal"]
+    );
+}
+
+#[test]
+fn test_keyword_arguments() {
+    let mut test = OsoTest::new();
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Document {
+        owner: String,
+        public: bool,
+    }
+
+    impl PolarClass for Document {}
+    impl Document {
+        fn new(owner: String, public: bool) -> Self {
+            Self { owner, public }
+        }
+
+        fn describe(&self, prefix: String, suffix: String) -> String {
+            format!("{}{}{}", prefix, self.owner, suffix)
+        }
+
+        fn private(owner: String) -> Self {
+            Self::new(owner, false)
+        }
+    }
+
+    let document_class = ClassBuilder::with_constructor(Document::new)
+        .name("Document")
+        .set_constructor_params(&["owner", "public"])
+        .add_attribute_getter("owner", |this: &Document| this.owner.clone())
+        .add_attribute_getter("public", |this: &Document| this.public)
+        .add_method("describe", Document::describe)
+        .set_method_params("describe", &["prefix", "suffix"])
+        .add_class_method("private", Document::private)
+        .set_class_method_params("private", &["owner"])
+        .build();
+    test.oso.register_class(document_class).unwrap();
+
+    test.qvar_one(
+        r#"x = new Document(owner: "alice", public: true).owner"#,
+        "x",
+        "alice".to_string(),
+    );
+    test.qvar_one(
+        r#"x = new Document(public: true, owner: "alice").public"#,
+        "x",
+        true,
+    );
+    test.qvar_one(
+        r#"x = new Document("alice", public: false).public"#,
+        "x",
+        false,
+    );
+    test.qvar_one(
+        r#"x = new Document("alice", true).describe("<", suffix: ">")"#,
+        "x",
+        "<alice>".to_string(),
+    );
+    test.qvar_one(r#"x = Document.private(owner: "alice").public"#, "x", false);
+
+    let err = test.query_err(r#"new Document(owner: "alice")"#);
+    assert!(
+        err.contains("missing argument public of Document constructor"),
+        "{}",
+        err
+    );
+    let err = test.query_err(r#"new Document("alice", owner: "bob", public: true)"#);
+    assert!(
+        err.contains("argument owner of Document constructor is given twice"),
+        "{}",
+        err
+    );
+    let err = test.query_err(r#"new Document("alice", true).describe("<", ">", end: ".")"#);
+    assert!(
+        err.contains("Document.describe has no parameter named end"),
+        "{}",
+        err
+    );
+    let err = test.query_err(r#"Document.private("alice", public: true)"#);
+    assert!(
+        err.contains("Document.private has no parameter named public"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn test_derive_extensions() {
+    let mut test = OsoTest::new();
+
+    #[derive(Clone, PolarClass)]
+    #[polar(class_name = "Person", methods)]
+    struct User {
+        #[polar(attribute, rename = "username")]
+        name: String,
+        #[polar(attribute)]
+        age: i64,
+    }
+
+    #[oso::polar_methods]
+    impl User {
+        #[polar(constructor)]
+        fn new(name: String, age: i64) -> Self {
+            Self { name, age }
+        }
+
+        #[polar(method, rename = "greeting")]
+        fn greet(&self, greeting: String) -> String {
+            format!("{}, {}", greeting, self.name)
+        }
+
+        #[polar(class_method)]
+        fn adult_age() -> i64 {
+            18
+        }
+
+        #[polar(iterator_method)]
+        fn years(&self) -> std::ops::Range<i64> {
+            0..self.age
+        }
+
+        #[allow(dead_code)]
+        fn unregistered(&self) -> bool {
+            true
+        }
+    }
+
+    #[derive(Clone, Debug, PartialEq, PolarClass)]
+    enum Color {
+        Red,
+        #[polar(rename = "GREEN")]
+        Green,
+    }
+
+    test.oso.register_class(User::get_polar_class()).unwrap();
+    test.oso.register_class(Color::get_polar_class()).unwrap();
+
+    test.qvar_one(
+        r#"x = new Person("alice", 30).username"#,
+        "x",
+        "alice".to_string(),
+    );
+    test.qvar_one(
+        r#"x = new Person("alice", 30).greeting("hi")"#,
+        "x",
+        "hi, alice".to_string(),
+    );
+    test.qvar_one(r#"x = Person.adult_age()"#, "x", 18);
+    let years: Vec<i64> = test.qvar(r#"new Person("alice", 3).years() = x"#, "x");
+    assert_eq!(years, vec![0, 1, 2]);
+    let err = test.query_err(r#"new Person("alice", 30).name = _"#);
+    assert!(err.contains("Attribute name not found"), "{}", err);
+    let err = test.query_err(r#"new Person("alice", 30).unregistered()"#);
+    assert!(err.contains("Method unregistered not found"), "{}", err);
+
+    test.qvar_one(r#"x = Color.Red"#, "x", Color::Red);
+    test.qvar_one(r#"x = Color.GREEN"#, "x", Color::Green);
+    test.query(r#"Color.Red = Color.Red"#);
+    test.qnull(r#"Color.Red = Color.GREEN"#);
+    test.query(r#"Color.Red matches Color"#);
+}
+
+#[test]
+fn test_typed_results() {
+    use oso::{FromResultSet, OsoError};
+
+    #[derive(Debug, PartialEq, FromResultSet)]
+    struct Grant {
+        user: String,
+        #[polar(rename = "action")]
+        permission: String,
+        level: i64,
+    }
+
+    let mut test = OsoTest::new();
+    test.load_str(
+        r#"grant("alice", "read", 1);
+           grant("bob", "write", 2);"#,
+    );
+
+    let grants: Vec<Grant> = test
+        .oso
+        .query("grant(user, action, level)")
+        .unwrap()
+        .typed::<Grant>()
+        .collect::<oso::Result<_>>()
+        .unwrap();
+    assert_eq!(
+        grants,
+        vec![
+            Grant {
+                user: "alice".to_string(),
+                permission: "read".to_string(),
+                level: 1,
+            },
+            Grant {
+                user: "bob".to_string(),
+                permission: "write".to_string(),
+                level: 2,
+            },
+        ]
+    );
+
+    let mut missing = test
+        .oso
+        .query("grant(user, action, _)")
+        .unwrap()
+        .typed::<Grant>();
+    match missing.next() {
+        Some(Err(OsoError::TypeError(e))) => {
+            assert_eq!(e.expected, "a binding for variable level");
+            assert_eq!(e.got.as_deref(), Some("no binding"));
+        }
+        _ => panic!("expected a type error"),
+    }
+
+    #[derive(Debug, PartialEq, FromResultSet)]
+    struct MaybeGrant {
+        user: String,
+        level: Option<i64>,
+    }
+
+    let grants: Vec<MaybeGrant> = test
+        .oso
+        .query(r#"grant(user, "read", _)"#)
+        .unwrap()
+        .typed::<MaybeGrant>()
+        .collect::<oso::Result<_>>()
+        .unwrap();
+    assert_eq!(
+        grants,
+        vec![MaybeGrant {
+            user: "alice".to_string(),
+            level: None,
+        }]
+    );
+
+    let mut wrong_type = test
+        .oso
+        .query("grant(user, level, _) and action = 1")
+        .unwrap()
+        .typed::<Grant>();
+    match wrong_type.next() {
+        Some(Err(OsoError::TypeError(e))) => {
+            assert_eq!(e.expected, "String for variable action");
+            assert_eq!(e.got.as_deref(), Some("Integer"));
+        }
+        _ => panic!("expected a type error"),
+    }
+}
+
 #[test]
 fn test_values() {
     let _ = tracing_subscriber::fmt::try_init();
@@ -524,6 +905,108 @@ fn test_values() {
     println!("{:?}", result);
 }
 
+#[test]
+fn test_conversions() {
+    use oso::{OsoError, PolarValue};
+    use std::collections::{BTreeMap, HashSet};
+    use std::sync::Arc;
+
+    let mut test = OsoTest::new();
+    test.oso
+        .register_constant((1, "two".to_string()), "pair")
+        .unwrap();
+    let groups: HashSet<String> = vec!["hr".to_string()].into_iter().collect();
+    test.oso.register_constant(groups, "groups").unwrap();
+    let mut roles = BTreeMap::new();
+    roles.insert("doc".to_string(), "owner".to_string());
+    test.oso.register_constant(roles.clone(), "roles").unwrap();
+    test.oso.register_constant(u64::MAX, "big").unwrap();
+    test.oso.register_constant(7usize, "small").unwrap();
+    test.oso
+        .register_constant(Arc::new("shared".to_string()), "shared")
+        .unwrap();
+
+    test.qvar_one("x = pair", "x", (1, "two".to_string()));
+    test.query(r#"pair = [1, "two"]"#);
+    test.query(r#""hr" in groups"#);
+    test.qvar_one("x = roles", "x", roles);
+    test.qvar_one("x = big", "x", u64::MAX);
+    test.qvar_one("x = small", "x", 7usize);
+    test.query("small = 7");
+    test.qvar_one("x = shared", "x", Arc::new("shared".to_string()));
+
+    let groups: Vec<HashSet<String>> = test.qvar(r#"x = ["hr", "hr"]"#, "x");
+    assert_eq!(groups[0].len(), 1);
+    let unbound: Vec<Option<i64>> = test.qvar("x = y", "x");
+    assert_eq!(unbound, vec![None]);
+    test.qvar_one("x = 1", "x", Some(1));
+
+    let result = test.oso.query("x = -1").unwrap().next().unwrap().unwrap();
+    assert!(matches!(
+        result.get_typed::<u64>("x"),
+        Err(OsoError::TypeError(e)) if e.got.as_deref() == Some("-1, which is out of range")
+    ));
+    assert!(matches!(
+        result.get_typed::<(i64, i64)>("x"),
+        Err(OsoError::TypeError(e)) if e.got.as_deref() == Some("Integer")
+    ));
+    let result = test.oso.query("x = [1]").unwrap().next().unwrap().unwrap();
+    assert!(matches!(
+        result.get_typed::<(i64, i64)>("x"),
+        Err(OsoError::TypeError(e)) if e.expected == "List of 2 elements"
+    ));
+
+    let value = PolarValue::String("alice".to_string());
+    let name: &str = std::convert::TryFrom::try_from(&value).unwrap();
+    assert_eq!(name, "alice");
+    let result: Result<&str, _> = std::convert::TryFrom::try_from(&PolarValue::Integer(1));
+    assert!(result.is_err());
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn test_json_conversions() {
+    let mut test = OsoTest::new();
+    let json = serde_json::json!({"id": 1, "tags": ["a", null], "parent": null});
+    test.oso.register_constant(json, "json").unwrap();
+
+    // Null fields are left out, and other nulls convert back unchanged.
+    test.qvar_one(
+        "x = json",
+        "x",
+        serde_json::json!({"id": 1, "tags": ["a", null]}),
+    );
+    test.query(r#"json.tags = ["a", _] and json.id = 1"#);
+
+    // Nulls and integers too large for Polar can't be compared or added.
+    test.oso
+        .register_constant(serde_json::json!({"hits": u64::MAX}), "counter")
+        .unwrap();
+    test.oso
+        .register_constant(serde_json::Value::Null, "null")
+        .unwrap();
+    let err = test.query_err("counter.hits > 5");
+    assert!(
+        err.contains("Type error: > expects comparable arguments, got: u64, 5"),
+        "{}",
+        err
+    );
+    let err = test.query_err("x = counter.hits + 1");
+    assert!(err.contains("Type error: + expects numbers"), "{}", err);
+    let err = test.query_err("counter.hits > counter.hits");
+    assert!(
+        err.contains("Type error: Expected an instance of a registered class got u64"),
+        "{}",
+        err
+    );
+    let err = test.query_err("null = counter.hits");
+    assert!(
+        err.contains("Type error: Expected an instance of a registered class"),
+        "{}",
+        err
+    );
+}
+
 #[test]
 fn test_arg_number() {
     let _ = tracing_subscriber::fmt::try_init();
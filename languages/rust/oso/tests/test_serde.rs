@@ -0,0 +1,110 @@
+/// Tests of converting `Serialize` and `Deserialize` types, with the `serde`
+/// feature.
+use serde::{Deserialize, Serialize};
+
+use oso::{Deserialized, OsoError, PolarValue};
+
+mod common;
+
+use common::OsoTest;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Request {
+    user: String,
+    groups: Vec<String>,
+    amount: f64,
+    approver: Option<String>,
+    limits: Limits,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Limits {
+    max_amount: i64,
+}
+
+#[test]
+fn test_serialize_query_args() {
+    let mut test = OsoTest::new();
+    test.load_str(
+        r#"allow(request) if
+               "finance" in request.groups and
+               request.amount < request.limits.max_amount and
+               not request.approver = _;"#,
+    );
+
+    let mut request = Request {
+        user: "alice".to_string(),
+        groups: vec!["finance".to_string()],
+        amount: 99.5,
+        approver: None,
+        limits: Limits { max_amount: 100 },
+    };
+    let args = (PolarValue::serialize(&request).unwrap(),);
+    assert!(test.oso.query_rule("allow", args).unwrap().next().is_some());
+
+    request.approver = Some("bob".to_string());
+    let args = (PolarValue::serialize(&request).unwrap(),);
+    assert!(test.oso.query_rule("allow", args).unwrap().next().is_none());
+
+    let json = serde_json::json!({"groups": ["finance"], "amount": 1, "limits": {"max_amount": 2}});
+    let args = (PolarValue::serialize(&json).unwrap(),);
+    assert!(test.oso.query_rule("allow", args).unwrap().next().is_some());
+}
+
+#[test]
+fn test_deserialize_results() {
+    let mut test = OsoTest::new();
+    test.load_str(
+        r#"request(r) if r = {
+               user: "alice",
+               groups: ["finance", "hr"],
+               amount: 10.0,
+               limits: {max_amount: 100}
+           };
+           bad_request(r) if r = {user: "alice"};"#,
+    );
+
+    let result = test.query("request(r)").pop().unwrap();
+    let Deserialized(request) = result.get_typed::<Deserialized<Request>>("r").unwrap();
+    assert_eq!(
+        request,
+        Request {
+            user: "alice".to_string(),
+            groups: vec!["finance".to_string(), "hr".to_string()],
+            amount: 10.0,
+            approver: None,
+            limits: Limits { max_amount: 100 },
+        }
+    );
+
+    let request: Request = PolarValue::serialize(&request)
+        .unwrap()
+        .deserialize()
+        .unwrap();
+    assert_eq!(request.limits.max_amount, 100);
+
+    let result = test.query("bad_request(r)").pop().unwrap();
+    match result.get_typed::<Deserialized<Request>>("r") {
+        Err(OsoError::TypeError(e)) => {
+            assert!(e.got.unwrap().contains("missing field"));
+        }
+        Err(e) => panic!("unexpected error {}", e),
+        Ok(_) => panic!("expected a type error"),
+    }
+}
+
+#[test]
+fn test_serialize_null_and_large_integers() -> oso::Result<()> {
+    // They aren't Polar values, so they're passed as instances that
+    // deserialize unchanged.
+    let too_large = PolarValue::serialize(&u64::MAX)?;
+    assert!(matches!(too_large, PolarValue::Instance(_)));
+    assert_eq!(too_large.deserialize::<u64>()?, u64::MAX);
+
+    let nulls = PolarValue::serialize(&vec![None, Some(1)])?;
+    assert_eq!(
+        nulls.deserialize::<Vec<Option<i64>>>()?,
+        vec![None, Some(1)]
+    );
+    Ok(())
+}
@@ -102,6 +102,15 @@ pub extern "C" fn polar_load(
     })
 }
 
+#[no_mangle]
+pub extern "C" fn polar_check(polar_ptr: *mut Polar) -> i32 {
+    ffi_try!({
+        let polar = unsafe { ffi_ref!(polar_ptr) };
+        polar.check();
+        POLAR_SUCCESS
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn polar_clear_rules(polar_ptr: *mut Polar) -> i32 {
     ffi_try!({
@@ -0,0 +1,311 @@
+//! Policy coverage.
+//!
+//! Coverage is collected at the same points of evaluation that build the
+//! query [`Trace`](super::traces::Trace): when a rule is entered
+//! (`Node::Rule`), and when a term is queried (`Node::Term`) and succeeds.
+//! Hits are keyed by the source span of the parsed terms, so a report can
+//! list every rule and body condition of the loaded policy files, including
+//! the ones that were never evaluated.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+use serde::Serialize;
+
+use super::error::{self, PolarResult};
+use super::kb::KnowledgeBase;
+use super::lexer::byte_loc_to_pos;
+use super::rules::Rule;
+use super::terms::*;
+
+/// Source id, left and right offsets of a parsed term.
+type Span = (u64, usize, usize);
+
+fn span(term: &Term) -> Option<Span> {
+    let (left, right) = term.span()?;
+    Some((term.get_source_id()?, left, right))
+}
+
+fn is_and(term: &Term) -> bool {
+    matches!(
+        term.value(),
+        Value::Expression(Operation {
+            operator: Operator::And,
+            ..
+        })
+    )
+}
+
+/// How often a rule or condition was evaluated, and how often it succeeded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct Hits {
+    pub evaluated: u64,
+    pub succeeded: u64,
+}
+
+impl Hits {
+    fn add(&mut self, other: &Hits) {
+        self.evaluated += other.evaluated;
+        self.succeeded += other.succeeded;
+    }
+}
+
+/// Coverage collected while running queries.
+#[derive(Clone, Debug, Default)]
+pub struct Coverage {
+    /// Rules, keyed by the span of their body.
+    rules: HashMap<Span, Hits>,
+    /// Queried terms other than rule bodies.
+    terms: HashMap<Span, Hits>,
+}
+
+impl Coverage {
+    pub(crate) fn rule_evaluated(&mut self, rule: &Rule) {
+        if let Some(span) = span(&rule.body) {
+            self.rules.entry(span).or_default().evaluated += 1;
+        }
+    }
+
+    pub(crate) fn term_evaluated(&mut self, term: &Term) {
+        // Rule bodies are counted as part of their rule.
+        if is_and(term) {
+            return;
+        }
+        if let Some(span) = span(term) {
+            self.terms.entry(span).or_default().evaluated += 1;
+        }
+    }
+
+    pub(crate) fn term_succeeded(&mut self, term: &Term) {
+        if let Some(span) = span(term) {
+            let hits = if is_and(term) {
+                self.rules.get_mut(&span)
+            } else {
+                self.terms.get_mut(&span)
+            };
+            if let Some(hits) = hits {
+                hits.succeeded += 1;
+            }
+        }
+    }
+
+    /// Add the hits from `other` to this coverage.
+    pub fn merge(&mut self, other: &Coverage) {
+        for (span, hits) in &other.rules {
+            self.rules.entry(*span).or_default().add(hits);
+        }
+        for (span, hits) in &other.terms {
+            self.terms.entry(*span).or_default().add(hits);
+        }
+    }
+
+    /// Report coverage of the rules in `kb`.
+    pub fn report(&self, kb: &KnowledgeBase) -> CoverageReport {
+        let mut files: BTreeMap<Option<String>, FileCoverage> = BTreeMap::new();
+        for generic_rule in kb.rules.values() {
+            for rule in generic_rule.rules() {
+                let body = match span(&rule.body) {
+                    Some(body) => body,
+                    None => continue,
+                };
+                let source = match kb.sources.get_source(body.0) {
+                    Some(source) => source,
+                    None => continue,
+                };
+                let position = |offset: usize| {
+                    let (row, column) = byte_loc_to_pos(&source.src, offset);
+                    (row + 1, column + 1)
+                };
+
+                // The rule starts at its first parameter, or at the body for rules
+                // without parameters.
+                let start = rule
+                    .params
+                    .iter()
+                    .filter_map(|param| param.parameter.span())
+                    .map(|(left, _)| left)
+                    .chain(std::iter::once(body.1))
+                    .min()
+                    .unwrap();
+                let (line, column) = position(start);
+
+                let conditions = match rule.body.value() {
+                    Value::Expression(Operation { args, .. }) => args
+                        .iter()
+                        .filter_map(|condition| {
+                            let span = span(condition)?;
+                            let (line, column) = position(span.1);
+                            Some(ConditionCoverage {
+                                line,
+                                column,
+                                source: source.src[span.1..span.2].to_string(),
+                                hits: self.terms.get(&span).copied().unwrap_or_default(),
+                            })
+                        })
+                        .collect(),
+                    _ => vec![],
+                };
+
+                files
+                    .entry(source.filename.clone())
+                    .or_insert_with(|| FileCoverage {
+                        filename: source.filename.clone(),
+                        rules: vec![],
+                        lines: vec![],
+                    })
+                    .rules
+                    .push(RuleCoverage {
+                        name: generic_rule.name.0.clone(),
+                        line,
+                        column,
+                        hits: self.rules.get(&body).copied().unwrap_or_default(),
+                        conditions,
+                    });
+            }
+        }
+
+        let files = files
+            .into_values()
+            .map(|mut file| {
+                file.rules.sort_by_key(|rule| (rule.line, rule.column));
+                // A line is covered as often as the most evaluated rule or
+                // condition starting on it.
+                let mut lines: BTreeMap<usize, u64> = BTreeMap::new();
+                for rule in &file.rules {
+                    let hits = lines.entry(rule.line).or_default();
+                    *hits = (*hits).max(rule.hits.evaluated);
+                    for condition in &rule.conditions {
+                        let hits = lines.entry(condition.line).or_default();
+                        *hits = (*hits).max(condition.hits.evaluated);
+                    }
+                }
+                file.lines = lines
+                    .into_iter()
+                    .map(|(line, hits)| LineCoverage { line, hits })
+                    .collect();
+                file
+            })
+            .collect();
+        CoverageReport { files }
+    }
+}
+
+/// Coverage of a condition in a rule body.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ConditionCoverage {
+    pub line: usize,
+    pub column: usize,
+    /// Source text of the condition.
+    pub source: String,
+    pub hits: Hits,
+}
+
+/// Coverage of a rule and the conditions in its body.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RuleCoverage {
+    pub name: String,
+    pub line: usize,
+    pub column: usize,
+    /// Evaluated when the rule was applied to a query, succeeded when its
+    /// whole body succeeded.
+    pub hits: Hits,
+    pub conditions: Vec<ConditionCoverage>,
+}
+
+/// Number of evaluations of the rules and conditions starting on a line.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LineCoverage {
+    pub line: usize,
+    pub hits: u64,
+}
+
+/// Coverage of the rules loaded from one file, or from strings if
+/// `filename` is `None`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct FileCoverage {
+    pub filename: Option<String>,
+    pub rules: Vec<RuleCoverage>,
+    pub lines: Vec<LineCoverage>,
+}
+
+/// Coverage report of the rules in a knowledge base, by file and line.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct CoverageReport {
+    pub files: Vec<FileCoverage>,
+}
+
+impl CoverageReport {
+    pub fn to_json(&self) -> PolarResult<String> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| error::RuntimeError::Serialization { msg: e.to_string() }.into())
+    }
+
+    /// Format the report as an LCOV tracefile.
+    ///
+    /// Rules are reported as functions, named after the rule and its line
+    /// since rules can have several definitions, and each body condition as
+    /// a branch that is taken when the condition succeeds. Rules loaded from
+    /// strings have no file and are left out.
+    pub fn to_lcov(&self) -> String {
+        let mut lcov = String::new();
+        for file in &self.files {
+            let filename = match &file.filename {
+                Some(filename) => filename,
+                None => continue,
+            };
+            let _ = writeln!(lcov, "TN:");
+            let _ = writeln!(lcov, "SF:{}", filename);
+            for rule in &file.rules {
+                let _ = writeln!(lcov, "FN:{},{}:{}", rule.line, rule.name, rule.line);
+            }
+            for rule in &file.rules {
+                let _ = writeln!(
+                    lcov,
+                    "FNDA:{},{}:{}",
+                    rule.hits.evaluated, rule.name, rule.line
+                );
+            }
+            let _ = writeln!(lcov, "FNF:{}", file.rules.len());
+            let _ = writeln!(
+                lcov,
+                "FNH:{}",
+                file.rules.iter().filter(|r| r.hits.evaluated > 0).count()
+            );
+
+            let mut branches = 0;
+            let mut branches_hit = 0;
+            for (block, rule) in file.rules.iter().enumerate() {
+                for (branch, condition) in rule.conditions.iter().enumerate() {
+                    let taken = if condition.hits.evaluated == 0 {
+                        "-".to_string()
+                    } else {
+                        condition.hits.succeeded.to_string()
+                    };
+                    let _ = writeln!(
+                        lcov,
+                        "BRDA:{},{},{},{}",
+                        condition.line, block, branch, taken
+                    );
+                    branches += 1;
+                    if condition.hits.succeeded > 0 {
+                        branches_hit += 1;
+                    }
+                }
+            }
+            let _ = writeln!(lcov, "BRF:{}", branches);
+            let _ = writeln!(lcov, "BRH:{}", branches_hit);
+
+            for line in &file.lines {
+                let _ = writeln!(lcov, "DA:{},{}", line.line, line.hits);
+            }
+            let _ = writeln!(lcov, "LF:{}", file.lines.len());
+            let _ = writeln!(
+                lcov,
+                "LH:{}",
+                file.lines.iter().filter(|l| l.hits > 0).count()
+            );
+            let _ = writeln!(lcov, "end_of_record");
+        }
+        lcov
+    }
+}
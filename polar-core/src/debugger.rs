@@ -1,8 +1,15 @@
-use std::fmt::Write;
+use std::fmt::{self, Write};
+use std::path::Path;
 use std::rc::Rc;
 
+use super::counter::Counter;
 use super::error::PolarResult;
+use super::events::QueryEvent;
 use super::formatting::{source_lines, ToPolarString};
+use super::lexer::byte_loc_to_pos;
+use super::parser;
+use super::rewrites::rewrite_term;
+use super::runnable::Runnable;
 use super::sources::*;
 use super::terms::*;
 use super::traces::*;
@@ -35,7 +42,10 @@ impl PolarVirtualMachine {
     /// If the inner [`Debugger`](struct.Debugger.html) returns a [`Goal`](../vm/enum.Goal.html),
     /// push it onto the goal stack.
     pub fn maybe_break(&mut self, event: DebugEvent) -> PolarResult<()> {
-        let maybe_goal = self.debugger.maybe_break(event, self);
+        // Take the debugger so that it can update its watches while reading the VM.
+        let mut debugger = std::mem::take(&mut self.debugger);
+        let maybe_goal = debugger.maybe_break(event, self);
+        self.debugger = debugger;
         if let Some(goal) = maybe_goal {
             self.push_goal((*goal).clone())?;
         }
@@ -43,6 +53,63 @@ impl PolarVirtualMachine {
     }
 }
 
+/// Look up the value of a variable as it is named in the policy source.
+///
+/// Rule variables are renamed each time a rule is called (`x` becomes
+/// `_x_12`), so this finds the most recent binding of either the name itself
+/// or a renamed version of it.
+fn lookup_variable(name: &str, vm: &PolarVirtualMachine) -> Option<Term> {
+    let renamed = format!("_{}_", name.trim_start_matches('_'));
+    vm.bindings
+        .iter()
+        .rev()
+        .find(|Binding(var, _)| {
+            var.0 == name
+                || (var.0.starts_with(&renamed)
+                    && var.0[renamed.len()..].chars().all(|c| c.is_ascii_digit()))
+        })
+        .map(|Binding(_, value)| vm.deep_deref(value))
+}
+
+/// Like [`lookup_variable`](fn.lookup_variable.html), but only return a value
+/// if the variable is bound to something other than another variable.
+fn lookup_bound_variable(name: &str, vm: &PolarVirtualMachine) -> Option<Term> {
+    lookup_variable(name, vm).filter(|value| !matches!(value.value(), Value::Variable(_)))
+}
+
+/// Check a breakpoint condition by running it as a separate query, with its
+/// variables replaced by their values at the point of `query`.
+///
+/// Conditions that refer to unbound variables or need to call into the
+/// application are never satisfied.
+fn check_condition(condition: &Term, vm: &PolarVirtualMachine) -> bool {
+    let mut unbound = false;
+    let mut condition = condition.cloned_map_replace(&mut |term| match term.value() {
+        Value::Variable(var) => match lookup_variable(&var.0, vm) {
+            Some(value) if !matches!(value.value(), Value::Variable(_)) => value,
+            _ => {
+                unbound = true;
+                term.clone()
+            }
+        },
+        _ => term.clone(),
+    });
+    if unbound {
+        return false;
+    }
+    rewrite_term(&mut condition, &mut vm.kb.write().unwrap());
+    let mut condition_vm = PolarVirtualMachine::new(
+        vm.kb.clone(),
+        false,
+        vec![Goal::Query { term: condition }],
+        vm.messages.clone(),
+    );
+    matches!(
+        condition_vm.run(Counter::default()),
+        Ok(QueryEvent::Result { .. })
+    )
+}
+
 /// [`Debugger`](struct.Debugger.html) step granularity.
 #[derive(Clone, Debug)]
 enum Step {
@@ -72,6 +139,83 @@ pub enum DebugEvent {
     Pop,
 }
 
+/// Where a [`Breakpoint`](struct.Breakpoint.html) is set.
+#[derive(Clone, Debug)]
+enum BreakpointLocation {
+    /// Break on every query for a rule with this name.
+    Rule(Symbol),
+    /// Break on every query that starts on this (1-indexed) line of a file.
+    Line { file: String, line: usize },
+}
+
+impl fmt::Display for BreakpointLocation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Rule(name) => write!(f, "rule {}", name.0),
+            Self::Line { file, line } => write!(f, "{}:{}", file, line),
+        }
+    }
+}
+
+/// A user-defined breakpoint, set with the `break` debugger command.
+#[derive(Clone, Debug)]
+struct Breakpoint {
+    id: usize,
+    location: BreakpointLocation,
+    /// Only break if this query succeeds with the bindings of the current query.
+    condition: Option<Term>,
+}
+
+impl Breakpoint {
+    /// Check whether evaluation should pause before `query`.
+    fn applies_to(&self, query: &Term, vm: &PolarVirtualMachine) -> bool {
+        let at_location = match (&self.location, query.value()) {
+            (BreakpointLocation::Rule(name), Value::Call(call)) => &call.name == name,
+            (BreakpointLocation::Rule(_), _) => false,
+            (
+                BreakpointLocation::Line { .. },
+                Value::Expression(Operation {
+                    operator: Operator::And,
+                    ..
+                }),
+            ) => false,
+            (BreakpointLocation::Line { file, line }, _) => match vm.source(query) {
+                Some(Source {
+                    filename: Some(filename),
+                    src,
+                }) => {
+                    (filename == *file || Path::new(&filename).ends_with(file))
+                        && byte_loc_to_pos(&src, query.offset()).0 + 1 == *line
+                }
+                _ => false,
+            },
+        };
+        match &self.condition {
+            Some(condition) => at_location && check_condition(condition, vm),
+            None => at_location,
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.id, self.location)?;
+        if let Some(condition) = &self.condition {
+            write!(f, " if {}", condition.to_polar())?;
+        }
+        Ok(())
+    }
+}
+
+/// A variable watched with the `watch` debugger command.
+#[derive(Clone, Debug)]
+struct Watch {
+    /// Variable name as it appears in the policy.
+    name: String,
+    /// Whether the variable was bound to a value when last checked.
+    bound: bool,
+}
+
 /// Tracks internal debugger state.
 #[derive(Clone, Debug, Default)]
 pub struct Debugger {
@@ -81,6 +225,15 @@ pub struct Debugger {
     /// - `Some(step)`: View the stopping logic in
     ///   [`maybe_break`](struct.Debugger.html#method.maybe_break).
     step: Option<Step>,
+
+    /// Breakpoints checked before every query, regardless of `step`.
+    breakpoints: Vec<Breakpoint>,
+
+    /// ID of the most recently set breakpoint.
+    last_breakpoint_id: usize,
+
+    /// Variables to break on when they become bound, checked after every goal.
+    watches: Vec<Watch>,
 }
 
 impl Debugger {
@@ -110,8 +263,10 @@ impl Debugger {
     ///
     /// - `Some(Goal::Debug { message })` -> Pause evaluation.
     /// - `None` -> Continue evaluation.
-    fn maybe_break(&self, event: DebugEvent, vm: &PolarVirtualMachine) -> Option<Rc<Goal>> {
-        if let Some(step) = self.step.as_ref() {
+    fn maybe_break(&mut self, event: DebugEvent, vm: &PolarVirtualMachine) -> Option<Rc<Goal>> {
+        let is_query = matches!(event, DebugEvent::Query);
+        let is_goal = matches!(event, DebugEvent::Goal(_));
+        let step_goal = if let Some(step) = self.step.as_ref() {
             match (step, event) {
                 (Step::Goal, DebugEvent::Goal(goal)) => Some(Rc::new(Goal::Debug {
                     message: goal.to_string(),
@@ -129,7 +284,111 @@ impl Debugger {
             }
         } else {
             None
+        };
+        let watch_goal = if is_goal && !self.watches.is_empty() {
+            self.check_watches(vm)
+        } else {
+            None
+        };
+        if step_goal.is_some() {
+            step_goal
+        } else if watch_goal.is_some() {
+            watch_goal
+        } else if is_query && !self.breakpoints.is_empty() {
+            self.check_breakpoints(vm)
+        } else {
+            None
+        }
+    }
+
+    /// Break if any watched variable has become bound since the last goal.
+    fn check_watches(&mut self, vm: &PolarVirtualMachine) -> Option<Rc<Goal>> {
+        let mut newly_bound = vec![];
+        for watch in self.watches.iter_mut() {
+            match (watch.bound, lookup_bound_variable(&watch.name, vm)) {
+                (false, Some(value)) => {
+                    watch.bound = true;
+                    newly_bound.push(format!("{} = {}", watch.name, value.to_polar()));
+                }
+                (true, None) => watch.bound = false,
+                _ => (),
+            }
         }
+        if newly_bound.is_empty() {
+            return None;
+        }
+        let mut message = format!("Watch: {}", newly_bound.join(", "));
+        if let Some(query) = vm.queries.last() {
+            let source = self.query_source(query, &vm.kb.read().unwrap().sources, 3);
+            let _ = write!(message, "\n{}\n\n{}\n", vm.query_summary(query), source);
+        }
+        Some(Rc::new(Goal::Debug { message }))
+    }
+
+    /// Break if any [`Breakpoint`](struct.Breakpoint.html) applies to the current query.
+    fn check_breakpoints(&self, vm: &PolarVirtualMachine) -> Option<Rc<Goal>> {
+        let query = vm.trace.last().and_then(|trace| trace.term())?;
+        let breakpoint = self
+            .breakpoints
+            .iter()
+            .find(|breakpoint| breakpoint.applies_to(&query, vm))?;
+        let source = self.query_source(&query, &vm.kb.read().unwrap().sources, 3);
+        Some(Rc::new(Goal::Debug {
+            message: format!(
+                "Breakpoint {}\n{}\n\n{}\n",
+                breakpoint,
+                vm.query_summary(&query),
+                source
+            ),
+        }))
+    }
+
+    fn list_breakpoints(&self) -> String {
+        if self.breakpoints.is_empty() {
+            "No breakpoints set.".to_string()
+        } else {
+            self.breakpoints
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join("\n")
+        }
+    }
+
+    /// Parse the arguments of a `break` command, e.g. `allow`, `policy.polar:12`, or
+    /// `allow if actor = "alice"`.
+    fn add_breakpoint(&mut self, args: &str) -> String {
+        let (location, condition) = match args.find(" if ") {
+            Some(index) => (args[..index].trim(), Some(args[index + 4..].trim())),
+            None => (args.trim(), None),
+        };
+        if location.is_empty() {
+            return "Usage: break <rule> | <file>:<line> [if <condition>]".to_string();
+        }
+        let location = match location.rfind(':') {
+            Some(index) if index > 0 => match location[index + 1..].parse() {
+                Ok(line) if line > 0 => BreakpointLocation::Line {
+                    file: location[..index].to_string(),
+                    line,
+                },
+                _ => return format!("Invalid line number: {}", &location[index + 1..]),
+            },
+            _ => BreakpointLocation::Rule(Symbol::new(location)),
+        };
+        let condition = match condition.map(|condition| parser::parse_query(0, condition)) {
+            Some(Ok(condition)) => Some(condition),
+            Some(Err(e)) => return format!("Invalid breakpoint condition: {}", e),
+            None => None,
+        };
+        self.last_breakpoint_id += 1;
+        let breakpoint = Breakpoint {
+            id: self.last_breakpoint_id,
+            location,
+            condition,
+        };
+        let message = format!("Set breakpoint {}", breakpoint);
+        self.breakpoints.push(breakpoint);
+        message
     }
 
     /// Produce the `Goal::Debug` for breaking on a Query (as opposed to breaking on a Goal).
@@ -294,20 +553,101 @@ impl Debugger {
                     message: st
                 })
             }
+            "b" | "break" => {
+                let args = command.trim_start()[parts[0].len()..].trim();
+                let message = if args.is_empty() {
+                    self.list_breakpoints()
+                } else {
+                    self.add_breakpoint(args)
+                };
+                return Some(Goal::Debug { message });
+            }
+            "breakpoints" => {
+                return Some(Goal::Debug {
+                    message: self.list_breakpoints(),
+                })
+            }
+            "d" | "delete" => {
+                let message = match parts.get(1).map(|id| id.parse::<usize>()) {
+                    None => {
+                        self.breakpoints.clear();
+                        "Deleted all breakpoints.".to_string()
+                    }
+                    Some(Ok(id)) if self.breakpoints.iter().any(|b| b.id == id) => {
+                        self.breakpoints.retain(|b| b.id != id);
+                        format!("Deleted breakpoint {}.", id)
+                    }
+                    Some(_) => format!("No breakpoint {}.", parts[1]),
+                };
+                return Some(Goal::Debug { message });
+            }
             "goals" => return Some(show(&vm.goals)),
             "bindings" => {
-                return Some(show(&vm.bindings))
+                if parts.get(1) == Some(&"all") {
+                    return Some(show(&vm.bindings));
+                }
+                let mut bindings = vm
+                    .queries
+                    .last()
+                    .map(|query| vm.relevant_bindings(&[query]))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|(var, value)| Binding(var, value))
+                    .collect::<Vec<_>>();
+                if bindings.is_empty() {
+                    return Some(Goal::Debug {
+                        message: "No bindings for the current query.".to_string(),
+                    });
+                }
+                bindings.sort_by(|a, b| a.0.cmp(&b.0));
+                return Some(show(&bindings));
+            }
+            "w" | "watch" => {
+                if parts.len() == 1 {
+                    let message = if self.watches.is_empty() {
+                        "No variables watched.".to_string()
+                    } else {
+                        self.watches
+                            .iter()
+                            .map(|watch| watch.name.clone())
+                            .collect::<Vec<_>>()
+                            .join(", ")
+                    };
+                    return Some(Goal::Debug { message });
+                }
+                for name in &parts[1..] {
+                    if !self.watches.iter().any(|watch| &watch.name == name) {
+                        let bound = lookup_bound_variable(name, vm).is_some();
+                        self.watches.push(Watch {
+                            name: name.to_string(),
+                            bound,
+                        });
+                    }
+                }
+                return Some(Goal::Debug {
+                    message: format!("Watching {}.", parts[1..].join(", ")),
+                });
+            }
+            "unwatch" => {
+                if parts.len() == 1 {
+                    self.watches.clear();
+                } else {
+                    self.watches
+                        .retain(|watch| !parts[1..].contains(&watch.name.as_str()));
+                }
+                return Some(Goal::Debug {
+                    message: "Updated watched variables.".to_string(),
+                });
             }
             "var" => {
                 if parts.len() > 1 {
                     let vars: Vec<Binding> = parts[1..]
                         .iter()
                         .map(|var| {
-                            let var = Symbol::new(var);
-                            let value = vm.bindings(true).get(&var).cloned().unwrap_or_else(|| {
+                            let value = lookup_variable(var, vm).unwrap_or_else(|| {
                                 Term::new_temporary(Value::Variable(Symbol::new("<unbound>")))
                             });
-                            Binding(var, value)
+                            Binding(Symbol::new(var), value)
                         })
                         .collect();
                     return Some(show(&vars));
@@ -336,10 +676,21 @@ impl Debugger {
   l[ine] [<n>]            Print the current line and <n> lines of context.
   query [<i>]             Print the current query or the query at level <i> in the query stack.
   stack | trace           Print the current query stack.
+  b[reak] <rule> [if <condition>]
+                          Break on queries for rule <rule>. If a condition
+                          is provided, only break when it succeeds.
+  b[reak] <file>:<line> [if <condition>]
+                          Break on queries starting on line <line> of <file>.
+  breakpoints             Print all breakpoints.
+  d[elete] [<id>]         Delete breakpoint <id>, or all breakpoints.
   goals                   Print the current goal stack.
-  bindings                Print all bindings
+  bindings [all]          Print the bindings of variables in the current query,
+                          or all bindings.
   var [<name> ...]        Print available variables. If one or more arguments
                           are provided, print the value of those variables.
+  w[atch] [<name> ...]    Break when any of the variables becomes bound, or
+                          print the watched variables.
+  unwatch [<name> ...]    Stop watching the variables, or all variables.
   q[uit]                  Alias for 'continue'."
                         .to_string(),
                 })
@@ -0,0 +1,102 @@
+//! Structured diagnostics for errors and warnings.
+//!
+//! A [`Diagnostic`] has a stable code, a severity, a message, and the file
+//! and range of source it refers to, so tools like editors and CI annotators
+//! can place it precisely. Error codes start with `E` and are listed with
+//! the error kinds in [`error`](super::error), warning codes start with `W`
+//! and are listed below.
+
+use serde::{Deserialize, Serialize};
+
+use super::lexer::byte_loc_to_pos;
+use super::sources::Source;
+use super::terms::Term;
+
+/// Singleton variable.
+pub const SINGLETON_VARIABLE: &str = "W0001";
+/// Specializer that is not a registered class.
+pub const UNKNOWN_SPECIALIZER: &str = "W0002";
+/// Call to a rule that is not defined.
+pub const UNKNOWN_RULE: &str = "W0003";
+/// Call with a number of arguments no rule with that name takes.
+pub const WRONG_ARITY: &str = "W0004";
+/// Rule that is never reached because an earlier rule cuts it.
+pub const UNREACHABLE_RULE: &str = "W0005";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// A position in a source. Lines and columns start at 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Position {
+    pub fn from_offset(src: &str, offset: usize) -> Self {
+        let (row, column) = byte_loc_to_pos(src, offset);
+        Self {
+            line: row + 1,
+            column: column + 1,
+        }
+    }
+}
+
+/// The range of source a diagnostic refers to. The end is exclusive.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
+}
+
+impl Range {
+    pub fn from_offsets(src: &str, left: usize, right: usize) -> Self {
+        Self {
+            start: Position::from_offset(src, left),
+            end: Position::from_offset(src, right.max(left)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub code: String,
+    pub severity: Severity,
+    pub message: String,
+    /// File the diagnostic refers to, if the source was loaded from a file.
+    pub filename: Option<String>,
+    /// Range of source the diagnostic refers to, if known.
+    pub range: Option<Range>,
+}
+
+impl Diagnostic {
+    pub fn new(code: &str, severity: Severity, message: String) -> Self {
+        Self {
+            code: code.to_string(),
+            severity,
+            message,
+            filename: None,
+            range: None,
+        }
+    }
+
+    /// Locate the diagnostic at the offsets `left..right` of `source`.
+    pub fn with_offsets(mut self, source: &Source, left: usize, right: usize) -> Self {
+        self.filename = source.filename.clone();
+        self.range = Some(Range::from_offsets(&source.src, left, right));
+        self
+    }
+
+    /// Locate the diagnostic at `term`, if it was parsed from `source`.
+    pub fn with_term(self, source: &Source, term: &Term) -> Self {
+        match term.span() {
+            Some((left, right)) => self.with_offsets(source, left, right),
+            None => self,
+        }
+    }
+}
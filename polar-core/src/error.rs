@@ -2,6 +2,7 @@ use serde::{Deserialize, Serialize};
 
 use std::fmt;
 
+use crate::diagnostic::*;
 use crate::sources::*;
 use crate::terms::*;
 
@@ -9,19 +10,34 @@ use crate::terms::*;
 #[serde(into = "FormattedPolarError")]
 pub struct PolarError {
     pub kind: ErrorKind,
-    pub context: Option<ErrorContext>,
+    /// Boxed to keep `PolarResult` small now that errors carry additional
+    /// errors and the end of their source.
+    pub context: Option<Box<ErrorContext>>,
+    /// Further errors found in the same source, e.g. the other syntax errors
+    /// of a policy file.
+    #[serde(default)]
+    pub additional: Vec<PolarError>,
 }
 
 #[derive(Clone, Deserialize, Serialize)]
 pub struct FormattedPolarError {
     pub kind: ErrorKind,
     pub formatted: String,
+    pub diagnostic: Diagnostic,
+    /// Diagnostics of the additional errors.
+    pub additional: Vec<Diagnostic>,
 }
 
 impl From<PolarError> for FormattedPolarError {
     fn from(other: PolarError) -> Self {
         Self {
             formatted: other.to_string(),
+            diagnostic: other.diagnostic(),
+            additional: other
+                .additional
+                .iter()
+                .map(PolarError::diagnostic)
+                .collect(),
             kind: other.kind,
         }
     }
@@ -40,39 +56,92 @@ pub struct ErrorContext {
     pub source: Source,
     pub row: usize,
     pub column: usize,
+    /// Offset just past the end of the source of the error.
+    pub end: usize,
+}
+
+impl ErrorContext {
+    fn new(source: &Source, left: usize, right: usize) -> Self {
+        let (row, column) = crate::lexer::byte_loc_to_pos(&source.src, left);
+        Self {
+            source: source.clone(),
+            row,
+            column,
+            end: right.max(left),
+        }
+    }
 }
 
 impl PolarError {
     pub fn set_context(mut self, source: Option<&Source>, term: Option<&Term>) -> Self {
         match (&self.kind, source, term) {
             (ErrorKind::Parse(e), Some(source), _) => match e {
-                ParseError::IntegerOverflow { loc, .. }
-                | ParseError::InvalidTokenCharacter { loc, .. }
-                | ParseError::InvalidToken { loc, .. }
-                | ParseError::UnrecognizedEOF { loc }
-                | ParseError::UnrecognizedToken { loc, .. }
-                | ParseError::ExtraToken { loc, .. } => {
-                    let (row, column) = crate::lexer::loc_to_pos(&source.src, *loc);
-                    self.context.replace(ErrorContext {
-                        source: source.clone(),
-                        row,
-                        column,
-                    });
+                ParseError::InvalidToken { loc } | ParseError::UnrecognizedEOF { loc } => {
+                    self.context
+                        .replace(Box::new(ErrorContext::new(source, *loc, *loc)));
+                }
+                ParseError::IntegerOverflow { token, loc }
+                | ParseError::InvalidTokenCharacter { token, loc, .. }
+                | ParseError::UnrecognizedToken { token, loc }
+                | ParseError::ExtraToken { token, loc }
+                | ParseError::ReservedWord { token, loc }
+                | ParseError::InvalidFloat { token, loc }
+                | ParseError::InvalidExpectation { token, loc } => {
+                    let end = (*loc + token.len()).min(source.src.len());
+                    self.context
+                        .replace(Box::new(ErrorContext::new(source, *loc, end)));
                 }
-                _ => {}
             },
             (_, Some(source), Some(term)) => {
-                let (row, column) = crate::lexer::loc_to_pos(&source.src, term.offset());
-                self.context.replace(ErrorContext {
-                    source: source.clone(),
-                    row,
-                    column,
-                });
+                let (left, right) = term.span().unwrap_or((term.offset(), term.offset()));
+                self.context
+                    .replace(Box::new(ErrorContext::new(source, left, right)));
             }
             _ => {}
         }
+        self.additional = self
+            .additional
+            .into_iter()
+            .map(|e| e.set_context(source, term))
+            .collect();
         self
     }
+
+    /// All the errors, this one first.
+    pub fn errors(&self) -> impl Iterator<Item = &PolarError> {
+        std::iter::once(self).chain(self.additional.iter())
+    }
+
+    /// The stable code of this kind of error.
+    pub fn code(&self) -> &'static str {
+        match &self.kind {
+            ErrorKind::Parse(e) => e.code(),
+            ErrorKind::Runtime(e) => e.code(),
+            ErrorKind::Operational(e) => e.code(),
+            ErrorKind::Parameter(_) => PARAMETER_ERROR,
+        }
+    }
+
+    pub fn diagnostic(&self) -> Diagnostic {
+        let message = match &self.kind {
+            ErrorKind::Parse(e) => e.to_string(),
+            ErrorKind::Runtime(e) => e.to_string(),
+            ErrorKind::Operational(e) => e.to_string(),
+            ErrorKind::Parameter(e) => e.to_string(),
+        };
+        let mut diagnostic = Diagnostic::new(self.code(), Severity::Error, message);
+        if let Some(context) = &self.context {
+            diagnostic.filename = context.source.filename.clone();
+            diagnostic.range = Some(Range {
+                start: Position {
+                    line: context.row + 1,
+                    column: context.column + 1,
+                },
+                end: Position::from_offset(&context.source.src, context.end),
+            });
+        }
+        diagnostic
+    }
 }
 
 impl From<ParseError> for PolarError {
@@ -80,6 +149,7 @@ impl From<ParseError> for PolarError {
         Self {
             kind: ErrorKind::Parse(err),
             context: None,
+            additional: vec![],
         }
     }
 }
@@ -89,6 +159,7 @@ impl From<RuntimeError> for PolarError {
         Self {
             kind: ErrorKind::Runtime(err),
             context: None,
+            additional: vec![],
         }
     }
 }
@@ -98,6 +169,7 @@ impl From<OperationalError> for PolarError {
         Self {
             kind: ErrorKind::Operational(err),
             context: None,
+            additional: vec![],
         }
     }
 }
@@ -107,6 +179,7 @@ impl From<ParameterError> for PolarError {
         Self {
             kind: ErrorKind::Parameter(err),
             context: None,
+            additional: vec![],
         }
     }
 }
@@ -126,6 +199,9 @@ impl fmt::Display for PolarError {
         if let Some(ref context) = self.context {
             write!(f, "{}", context)?;
         }
+        for error in &self.additional {
+            write!(f, "\n{}", error)?;
+        }
         Ok(())
     }
 }
@@ -140,6 +216,23 @@ pub enum ParseError {
     ExtraToken { token: String, loc: usize },
     ReservedWord { token: String, loc: usize },
     InvalidFloat { token: String, loc: usize },
+    InvalidExpectation { token: String, loc: usize },
+}
+
+impl ParseError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::IntegerOverflow { .. } => "E0001",
+            Self::InvalidTokenCharacter { .. } => "E0002",
+            Self::InvalidToken { .. } => "E0003",
+            Self::UnrecognizedEOF { .. } => "E0004",
+            Self::UnrecognizedToken { .. } => "E0005",
+            Self::ExtraToken { .. } => "E0006",
+            Self::ReservedWord { .. } => "E0007",
+            Self::InvalidFloat { .. } => "E0008",
+            Self::InvalidExpectation { .. } => "E0009",
+        }
+    }
 }
 
 impl fmt::Display for ErrorContext {
@@ -189,6 +282,11 @@ impl fmt::Display for ParseError {
                 "{} was parsed as a float, but is invalid",
                 token.escape_debug()
             ),
+            Self::InvalidExpectation { token, .. } => write!(
+                f,
+                "{} is not a valid expectation for an inline query. Expected true, false, a number of results, or a list of results",
+                token.escape_debug()
+            ),
         }
     }
 }
@@ -225,9 +323,27 @@ pub enum RuntimeError {
     FileLoading {
         msg: String,
     },
+    InlineQueryFailed {
+        msg: String,
+    },
 }
 
 impl RuntimeError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::ArithmeticError { .. } => "E0101",
+            Self::Serialization { .. } => "E0102",
+            Self::Unsupported { .. } => "E0103",
+            Self::TypeError { .. } => "E0104",
+            Self::UnboundVariable { .. } => "E0105",
+            Self::StackOverflow { .. } => "E0106",
+            Self::QueryTimeout { .. } => "E0107",
+            Self::Application { .. } => "E0108",
+            Self::FileLoading { .. } => "E0109",
+            Self::InlineQueryFailed { .. } => "E0110",
+        }
+    }
+
     pub fn add_stack_trace(&mut self, vm: &crate::vm::PolarVirtualMachine) {
         match self {
             Self::Application { stack_trace, .. } | Self::TypeError { stack_trace, .. } => {
@@ -260,6 +376,7 @@ impl fmt::Display for RuntimeError {
                 write!(f, "Application error: {}", msg)
             }
             Self::FileLoading { msg } => write!(f, "Problem loading file: {}", msg),
+            Self::InlineQueryFailed { msg } => write!(f, "Inline query failed: {}", msg),
         }
     }
 }
@@ -273,6 +390,16 @@ pub enum OperationalError {
     InvalidState(String),
 }
 
+impl OperationalError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Unimplemented(_) => "E0201",
+            Self::Unknown => "E0202",
+            Self::InvalidState(_) => "E0203",
+        }
+    }
+}
+
 impl fmt::Display for OperationalError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -287,6 +414,9 @@ impl fmt::Display for OperationalError {
 /// Parameter passed to FFI lib function is invalid.
 pub struct ParameterError(pub String);
 
+/// Code of parameter errors.
+pub const PARAMETER_ERROR: &str = "E0301";
+
 impl fmt::Display for ParameterError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "Invalid parameter used in FFI function: {}", self.0)
@@ -0,0 +1,449 @@
+//! Formatting of policy source.
+//!
+//! The formatter parses a policy into a concrete syntax tree: the tokens of
+//! each rule and inline query, grouped by brackets, with the comments and
+//! blank lines around them. Printing the tree only changes the whitespace
+//! between tokens, and the formatted policy is parsed again and checked to
+//! have the same rules and queries as the original.
+//!
+//! A rule that fits on a line is printed on one line. Otherwise its body
+//! starts on the next line, with one condition per line:
+//!
+//! ```polar
+//! allow(actor: User, "read", resource: Document) if
+//!     resource.owner = actor.id and
+//!     not resource.archived;
+//! ```
+
+use super::error::{OperationalError, PolarResult};
+use super::lexer::{Lexer, Token};
+use super::parser::parse_lines;
+use super::sources::Source;
+
+/// Lines longer than this are broken up.
+const MAX_WIDTH: usize = 80;
+const INDENT: &str = "    ";
+
+#[derive(Clone, Debug)]
+struct Leaf {
+    token: Token,
+    /// Source text of the token.
+    text: String,
+}
+
+#[derive(Clone, Debug)]
+struct Comment {
+    text: String,
+    /// The comment follows a token on the same line.
+    trailing: bool,
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Leaf(Leaf),
+    Comment(Comment),
+    /// Nodes between brackets, including the brackets.
+    Group(Vec<Node>),
+}
+
+/// A rule or inline query.
+#[derive(Clone, Debug)]
+struct Statement {
+    nodes: Vec<Node>,
+    /// Comment on the same line after the `;`.
+    trailing: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+enum Item {
+    Statement(Statement),
+    Comment(String),
+}
+
+/// Concrete syntax tree of a policy: statements and comments between them,
+/// each with whether a blank line comes before it.
+#[derive(Clone, Debug, Default)]
+struct Document {
+    items: Vec<(bool, Item)>,
+}
+
+fn is_open(token: &Token) -> bool {
+    matches!(token, Token::LP | Token::LB | Token::LCB)
+}
+
+fn is_close(token: &Token) -> bool {
+    matches!(token, Token::RP | Token::RB | Token::RCB)
+}
+
+/// The token can end an operand, so a `-`, `+` or `*` after it is a binary
+/// operator rather than a sign or rest variable.
+fn ends_operand(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Integer(_)
+            | Token::Float(_)
+            | Token::String(_)
+            | Token::Boolean(_)
+            | Token::Symbol(_)
+            | Token::RP
+            | Token::RB
+            | Token::RCB
+    )
+}
+
+fn space_between(prev: &Token, prev_unary: bool, next: &Token) -> bool {
+    if prev_unary || matches!(prev, Token::LP | Token::LB | Token::LCB | Token::Dot) {
+        return false;
+    }
+    match next {
+        Token::Comma | Token::SemiColon | Token::Colon | Token::Dot => false,
+        Token::RP | Token::RB | Token::RCB => false,
+        // Calls and instance literals.
+        Token::LP => !matches!(
+            prev,
+            Token::Symbol(_) | Token::Print | Token::Debug | Token::ForAll
+        ),
+        Token::LCB => !matches!(prev, Token::Symbol(_)),
+        _ => true,
+    }
+}
+
+impl Document {
+    fn parse(src: &str) -> PolarResult<Self> {
+        let mut document = Document::default();
+        // The statement being parsed, and the groups open in it.
+        let mut stack: Vec<Vec<Node>> = vec![];
+        let mut statement_blank = false;
+        let mut prev_end = None;
+        let mut tokens = Lexer::new(src);
+        loop {
+            let next = tokens.next().transpose()?;
+            let gap_end = next.as_ref().map_or(src.len(), |(start, _, _)| *start);
+            let gap = &src[prev_end.unwrap_or(0)..gap_end];
+
+            // Only whitespace and comments are between tokens.
+            let mut last_line = 0;
+            let lines: Vec<&str> = gap.split('\n').collect();
+            for (i, line) in lines.iter().enumerate() {
+                let line = line.trim();
+                if !line.starts_with('#') {
+                    continue;
+                }
+                let comment = Comment {
+                    text: line.to_string(),
+                    trailing: i == 0 && prev_end.is_some(),
+                };
+                let blank = i - last_line > 1;
+                last_line = i;
+                if let Some(nodes) = stack.last_mut() {
+                    nodes.push(Node::Comment(comment));
+                    continue;
+                }
+                match document.items.last_mut() {
+                    Some((_, Item::Statement(statement)))
+                        if comment.trailing && statement.trailing.is_none() =>
+                    {
+                        statement.trailing = Some(comment.text)
+                    }
+                    _ => document.items.push((blank, Item::Comment(comment.text))),
+                }
+            }
+
+            let (_, token, end) = match next {
+                Some(next) => next,
+                None => break,
+            };
+            let start = gap_end;
+            prev_end = Some(end);
+            if stack.is_empty() {
+                stack.push(vec![]);
+                statement_blank = lines.len() - last_line > 2;
+            }
+            let leaf = Node::Leaf(Leaf {
+                text: src[start..end].to_string(),
+                token: token.clone(),
+            });
+            if is_open(&token) {
+                stack.push(vec![leaf]);
+            } else if is_close(&token) && stack.len() > 1 {
+                let mut group = stack.pop().unwrap();
+                group.push(leaf);
+                stack.last_mut().unwrap().push(Node::Group(group));
+            } else {
+                stack.last_mut().unwrap().push(leaf);
+            }
+            if matches!(token, Token::SemiColon) && stack.len() == 1 {
+                let statement = Statement {
+                    nodes: stack.pop().unwrap(),
+                    trailing: None,
+                };
+                document
+                    .items
+                    .push((statement_blank, Item::Statement(statement)));
+            }
+        }
+        Ok(document)
+    }
+
+    fn print(&self) -> String {
+        let mut out = String::new();
+        for (i, (blank, item)) in self.items.iter().enumerate() {
+            if i > 0 && *blank {
+                out.push('\n');
+            }
+            match item {
+                Item::Statement(statement) => out.push_str(&statement.print()),
+                Item::Comment(text) => out.push_str(text),
+            }
+            out.push('\n');
+        }
+        out
+    }
+}
+
+impl Statement {
+    fn print(&self) -> String {
+        let mut printer = Printer::default();
+        printer.nodes(&self.nodes);
+        let fits = printer.out.chars().count() <= MAX_WIDTH;
+        if printer.comments > 0 || !fits {
+            printer = Printer::default();
+            match self.nodes.iter().position(|node| {
+                matches!(
+                    node,
+                    Node::Leaf(Leaf {
+                        token: Token::If,
+                        ..
+                    })
+                )
+            }) {
+                // Break after the head, and after each condition of the body.
+                Some(i) => {
+                    printer.nodes(&self.nodes[..=i]);
+                    printer.indent = 1;
+                    printer.pending_break = true;
+                    for node in &self.nodes[i + 1..] {
+                        printer.node(node);
+                        if let Node::Leaf(Leaf {
+                            token: Token::And, ..
+                        }) = node
+                        {
+                            printer.pending_break = true;
+                        }
+                    }
+                }
+                None => printer.nodes(&self.nodes),
+            }
+        }
+        let mut out = printer.out;
+        if let Some(comment) = &self.trailing {
+            if !out.ends_with('\n') {
+                out.push(' ');
+            }
+            out.push_str(comment);
+        }
+        out.trim_end_matches('\n').to_string()
+    }
+}
+
+#[derive(Default)]
+struct Printer {
+    out: String,
+    indent: usize,
+    line_start: bool,
+    /// Start a new line before the next token or comment on its own line.
+    pending_break: bool,
+    prev: Option<Token>,
+    prev_unary: bool,
+    comments: usize,
+}
+
+impl Printer {
+    fn newline(&mut self) {
+        self.out.push('\n');
+        self.line_start = true;
+        self.pending_break = false;
+    }
+
+    fn write_indent(&mut self) {
+        for _ in 0..self.indent {
+            self.out.push_str(INDENT);
+        }
+        self.line_start = false;
+    }
+
+    fn nodes(&mut self, nodes: &[Node]) {
+        for node in nodes {
+            self.node(node);
+        }
+    }
+
+    fn node(&mut self, node: &Node) {
+        match node {
+            Node::Leaf(leaf) => self.leaf(leaf),
+            Node::Comment(comment) => self.comment(comment),
+            Node::Group(nodes) => {
+                let (open, rest) = nodes.split_first().unwrap();
+                self.node(open);
+                self.indent += 1;
+                self.nodes(rest);
+                self.indent -= 1;
+            }
+        }
+    }
+
+    fn leaf(&mut self, leaf: &Leaf) {
+        if self.pending_break {
+            self.newline();
+        }
+        // The closing bracket of a group lines up with its opening line.
+        if is_close(&leaf.token) && self.line_start {
+            self.indent -= 1;
+            self.write_indent();
+            self.indent += 1;
+        } else if self.line_start {
+            self.write_indent();
+        } else if let Some(prev) = &self.prev {
+            if space_between(prev, self.prev_unary, &leaf.token) {
+                self.out.push(' ');
+            }
+        }
+        self.out.push_str(&leaf.text);
+        self.prev_unary = matches!(leaf.token, Token::Sub | Token::Add | Token::Mul)
+            && !matches!(&self.prev, Some(prev) if ends_operand(prev));
+        self.prev = Some(leaf.token.clone());
+    }
+
+    fn comment(&mut self, comment: &Comment) {
+        self.comments += 1;
+        if comment.trailing && !self.line_start {
+            self.out.push(' ');
+        } else {
+            if !self.line_start && !self.out.is_empty() {
+                self.newline();
+            }
+            self.write_indent();
+        }
+        self.out.push_str(&comment.text);
+        self.newline();
+    }
+}
+
+/// Format a policy. Comments are kept, and the formatted policy has the same
+/// rules and inline queries as `src`.
+pub fn format_policy(src: &str, filename: Option<String>) -> PolarResult<String> {
+    let source = Source {
+        filename,
+        src: src.to_owned(),
+    };
+    let lines = parse_lines(0, src).map_err(|e| e.set_context(Some(&source), None))?;
+    let formatted = Document::parse(src)?.print();
+    match parse_lines(0, &formatted) {
+        Ok(formatted_lines) if formatted_lines == lines => Ok(formatted),
+        _ => Err(OperationalError::InvalidState(
+            "formatting changed the meaning of the policy".to_string(),
+        )
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    #[track_caller]
+    fn format(src: &str) -> String {
+        let formatted = format_policy(src, None).unwrap();
+        assert_eq!(format_policy(&formatted, None).unwrap(), formatted);
+        formatted
+    }
+
+    #[test]
+    fn test_format_spacing() {
+        assert_eq!(
+            format("f( x,y )if x=y+ -1 and y in[1,*rest]and z.foo( 1 )={a:1};"),
+            "f(x, y) if x = y + -1 and y in [1, *rest] and z.foo(1) = {a: 1};\n"
+        );
+        assert_eq!(
+            format("g(x:Foo,{a:b})if not(x matches Bar{}or x=new Baz(1,c:2)) and cut;"),
+            "g(x: Foo, {a: b}) if not (x matches Bar{} or x = new Baz(1, c: 2)) and cut;\n"
+        );
+        assert_eq!(
+            format("?=  print( \"a\\\"b\" ,1.50)  ;h();"),
+            "?= print(\"a\\\"b\", 1.50);\nh();\n"
+        );
+        assert_eq!(format(""), "");
+    }
+
+    #[test]
+    fn test_format_line_breaks() {
+        let src = indoc! {r#"
+            allow(actor: User, "read", resource: Document) if resource.owner = actor.id and not resource.archived;
+            allow(actor, action, resource) if (actor.admin or resource.public) and action = "read";
+            allow(actor, action, resource) if actor.admin and action = "read";
+        "#};
+        assert_eq!(
+            format(src),
+            indoc! {r#"
+                allow(actor: User, "read", resource: Document) if
+                    resource.owner = actor.id and
+                    not resource.archived;
+                allow(actor, action, resource) if
+                    (actor.admin or resource.public) and
+                    action = "read";
+                allow(actor, action, resource) if actor.admin and action = "read";
+            "#}
+        );
+    }
+
+    #[test]
+    fn test_format_comments() {
+        let src = indoc! {r#"
+
+
+            # Rules for documents.
+            allow(actor, "read", doc) if   # readers
+                # owners can always read
+                doc.owner = actor and
+                doc.tags = [
+                    "a", # first
+                    "b"
+                ];
+
+
+            # Rules for folders.
+
+            allow(actor, "read", folder) if folder.public;  # public folders
+            f(1);
+            # the end
+        "#};
+        assert_eq!(
+            format(src),
+            indoc! {r#"
+                # Rules for documents.
+                allow(actor, "read", doc) if # readers
+                    # owners can always read
+                    doc.owner = actor and
+                    doc.tags = ["a", # first
+                        "b"];
+
+                # Rules for folders.
+
+                allow(actor, "read", folder) if folder.public; # public folders
+                f(1);
+                # the end
+            "#}
+        );
+    }
+
+    #[test]
+    fn test_format_parse_error() {
+        let e = format_policy("f(x) if x = ;", Some("bad.polar".to_string())).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "did not expect to find the token ';' at line 1, column 13 in file bad.polar"
+        );
+    }
+}
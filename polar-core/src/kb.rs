@@ -1,6 +1,9 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
 use super::counter::Counter;
+use super::error::ParseError;
+use super::formatting::to_polar::ToPolarString;
+use super::numerics::Numeric;
 use super::rules::*;
 use super::sources::*;
 use super::terms::*;
@@ -9,7 +12,106 @@ use super::terms::*;
 /// but can translate to and from this type.
 pub type Bindings = HashMap<Symbol, Term>;
 
-#[derive(Default)]
+/// The results an inline query is expected to have.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExpectedResults {
+    /// At least one result: `?= query;` or `?= query, true;`.
+    Success,
+    /// No results: `?= query, false;`.
+    Failure,
+    /// Exactly `n` results: `?= query, n;`.
+    Count(usize),
+    /// Exactly these results, in order: `?= query, [{x: 1}, {x: 2}];`. Each
+    /// result lists the bindings it must have; other bindings are ignored.
+    Results(Vec<BTreeMap<Symbol, Term>>),
+}
+
+impl ExpectedResults {
+    /// Parse the expectation after the query of an inline query.
+    pub fn from_term(term: &Term) -> Result<Self, ParseError> {
+        let results = |list: &[Term]| {
+            list.iter()
+                .map(|result| match result.value() {
+                    Value::Dictionary(dict) => Some(dict.fields.clone()),
+                    _ => None,
+                })
+                .collect::<Option<Vec<_>>>()
+        };
+        let expected = match term.value() {
+            Value::Boolean(true) => Some(Self::Success),
+            Value::Boolean(false) => Some(Self::Failure),
+            Value::Number(Numeric::Integer(n)) if *n >= 0 => Some(Self::Count(*n as usize)),
+            Value::List(list) => results(list).map(Self::Results),
+            _ => None,
+        };
+        expected.ok_or_else(|| ParseError::InvalidExpectation {
+            token: term.to_polar(),
+            loc: term.offset(),
+        })
+    }
+
+    /// Check the results of a query, and describe how they differ from the
+    /// expected ones.
+    pub fn check(&self, results: &[Bindings]) -> Result<(), String> {
+        match self {
+            Self::Success if results.is_empty() => Err("expected a result, got none".to_string()),
+            Self::Failure if !results.is_empty() => {
+                Err(format!("expected no results, got {}", results.len()))
+            }
+            Self::Count(n) if *n != results.len() => {
+                Err(format!("expected {} result(s), got {}", n, results.len()))
+            }
+            Self::Results(expected) => {
+                let matches = expected.len() == results.len()
+                    && expected.iter().zip(results).all(|(expected, result)| {
+                        expected.iter().all(|(name, value)| {
+                            result.get(name).map(Term::value) == Some(value.value())
+                        })
+                    });
+                if matches {
+                    return Ok(());
+                }
+                // Only show the bindings that are expected in some result.
+                let names: BTreeSet<&Symbol> = expected.iter().flat_map(|e| e.keys()).collect();
+                let got: Vec<String> = results
+                    .iter()
+                    .map(|result| {
+                        let fields: BTreeMap<Symbol, Term> = names
+                            .iter()
+                            .filter_map(|&name| Some((name.clone(), result.get(name)?.clone())))
+                            .collect();
+                        Dictionary { fields }.to_polar()
+                    })
+                    .collect();
+                let expected: Vec<String> = expected
+                    .iter()
+                    .map(|fields| {
+                        Dictionary {
+                            fields: fields.clone(),
+                        }
+                        .to_polar()
+                    })
+                    .collect();
+                Err(format!(
+                    "expected results [{}], got [{}]",
+                    expected.join(", "),
+                    got.join(", ")
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A query in a policy, `?= query;`, that must have the expected results
+/// when the policy is loaded.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InlineQuery {
+    pub term: Term,
+    pub expected: ExpectedResults,
+}
+
+#[derive(Clone, Default)]
 pub struct KnowledgeBase {
     pub constants: Bindings,
     pub rules: HashMap<Symbol, GenericRule>,
@@ -18,7 +120,7 @@ pub struct KnowledgeBase {
     gensym_counter: Counter,
     /// For call IDs, instance IDs, symbols, etc.
     id_counter: Counter,
-    pub inline_queries: Vec<Term>,
+    pub inline_queries: Vec<InlineQuery>,
 }
 
 impl KnowledgeBase {
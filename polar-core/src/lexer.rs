@@ -24,6 +24,13 @@ pub fn loc_to_pos(src: &str, loc: usize) -> SrcPos {
     (row, col)
 }
 
+// Take a byte offset in a string, like the offsets of the lexer, and return
+// the row and column.
+pub fn byte_loc_to_pos(src: &str, loc: usize) -> SrcPos {
+    let chars = src.char_indices().take_while(|(i, _)| *i < loc).count();
+    loc_to_pos(src, chars)
+}
+
 pub struct Lexer<'input> {
     c: Option<(usize, char)>,
     chars: Peekable<CharIndices<'input>>,
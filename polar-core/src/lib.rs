@@ -3,12 +3,15 @@
 extern crate maplit;
 
 mod debugger;
+pub mod diagnostic;
 pub mod error;
+pub mod formatter;
 pub mod formatting;
 mod lexer;
 #[macro_use]
 pub mod macros;
 mod counter;
+pub mod coverage;
 pub mod events;
 pub mod kb;
 pub mod messages;
@@ -16,11 +19,13 @@ mod numerics;
 pub mod parser;
 mod partial;
 pub mod polar;
+pub mod profiler;
+pub mod recording;
 mod rewrites;
 pub mod rules;
 mod runnable;
-mod sources;
+pub mod sources;
 pub mod terms;
 pub mod traces;
 mod vm;
-mod warnings;
+pub mod warnings;
@@ -2,6 +2,8 @@ use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 use std::sync::{Arc, Mutex};
 
+use super::diagnostic::Diagnostic;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum MessageKind {
     Print,
@@ -12,6 +14,19 @@ pub enum MessageKind {
 pub struct Message {
     pub kind: MessageKind,
     pub msg: String,
+    /// Structured form of a warning.
+    #[serde(default)]
+    pub diagnostic: Option<Diagnostic>,
+}
+
+impl Message {
+    pub fn warning(msg: String, diagnostic: Diagnostic) -> Self {
+        Self {
+            kind: MessageKind::Warning,
+            msg,
+            diagnostic: Some(diagnostic),
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -36,7 +51,11 @@ impl MessageQueue {
 
     pub fn push(&self, kind: MessageKind, msg: String) {
         let mut messages = self.messages.lock().unwrap();
-        messages.push_back(Message { kind, msg });
+        messages.push_back(Message {
+            kind,
+            msg,
+            diagnostic: None,
+        });
     }
 
     pub fn extend<T: IntoIterator<Item = Message>>(&self, iter: T) {
@@ -1,5 +1,5 @@
 use crate::lexer::Token;
-use lalrpop_util::{lalrpop_mod, ParseError};
+use lalrpop_util::{lalrpop_mod, ErrorRecovery, ParseError};
 
 lalrpop_mod!(
     #[allow(clippy::all, dead_code, unused_imports, unused_mut)]
@@ -7,6 +7,7 @@ lalrpop_mod!(
 );
 
 use super::error::{self, PolarResult};
+use super::kb::InlineQuery;
 use super::lexer::{self, Lexer};
 use super::rules::*;
 use super::terms::*;
@@ -14,7 +15,7 @@ use super::terms::*;
 #[derive(Clone, Debug, PartialEq)]
 pub enum Line {
     Rule(Rule),
-    Query(Term),
+    Query(InlineQuery),
 }
 
 lazy_static::lazy_static! {
@@ -50,35 +51,57 @@ fn to_parse_error(e: ParseError<usize, lexer::Token, error::ParseError>) -> erro
     }
 }
 
+/// Fail with the first error if the parser had to recover from any.
+fn check_recovered<T>(
+    result: Result<T, ParseError<usize, lexer::Token, error::ParseError>>,
+    errors: Vec<ErrorRecovery<usize, lexer::Token, error::ParseError>>,
+) -> PolarResult<T> {
+    let mut errors: Vec<error::PolarError> = errors
+        .into_iter()
+        .map(|recovery| to_parse_error(recovery.error).into())
+        .collect();
+    match result {
+        Ok(parsed) if errors.is_empty() => return Ok(parsed),
+        Ok(_) => {}
+        Err(e) => errors.push(to_parse_error(e).into()),
+    }
+    let mut first = errors.remove(0);
+    first.additional = errors;
+    Err(first)
+}
+
 pub fn parse_term(src: &str) -> PolarResult<Term> {
-    TERM_PARSER
-        .parse(0, Lexer::new(src))
-        .map_err(|e| to_parse_error(e).into())
+    let mut errors = vec![];
+    let result = TERM_PARSER.parse(0, &mut errors, Lexer::new(src));
+    check_recovered(result, errors)
 }
 
+/// Parse the lines of a policy. Syntax errors are recovered from at the end
+/// of the line they occur on, so the error returned lists all of them.
 pub fn parse_lines(src_id: u64, src: &str) -> PolarResult<Vec<Line>> {
-    LINES_PARSER
-        .parse(src_id, Lexer::new(src))
-        .map_err(|e| to_parse_error(e).into())
+    let mut errors = vec![];
+    let result = LINES_PARSER.parse(src_id, &mut errors, Lexer::new(src));
+    check_recovered(result, errors)
 }
 
 pub fn parse_query(src_id: u64, src: &str) -> PolarResult<Term> {
-    QUERY_PARSER
-        .parse(src_id, Lexer::new(src))
-        .map_err(|e| to_parse_error(e).into())
+    let mut errors = vec![];
+    let result = QUERY_PARSER.parse(src_id, &mut errors, Lexer::new(src));
+    check_recovered(result, errors)
 }
 
 #[cfg(test)]
 pub fn parse_rules(src_id: u64, src: &str) -> PolarResult<Vec<Rule>> {
-    RULES_PARSER
-        .parse(src_id, Lexer::new(src))
-        .map_err(|e| to_parse_error(e).into())
+    let mut errors = vec![];
+    let result = RULES_PARSER.parse(src_id, &mut errors, Lexer::new(src));
+    check_recovered(result, errors)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::formatting::ToPolarString;
+    use crate::kb::ExpectedResults;
     use pretty_assertions::assert_eq;
 
     #[track_caller]
@@ -228,7 +251,36 @@ mod tests {
         let f = r#"?= f(1);"#;
         let line = parse_lines(&f);
 
-        assert_eq!(line[0], Line::Query(term!(call!("f", [1]))));
+        assert_eq!(
+            line[0],
+            Line::Query(InlineQuery {
+                term: term!(call!("f", [1])),
+                expected: ExpectedResults::Success
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_inline_query_expectations() {
+        let line = parse_lines(r#"?= f(1), false;"#);
+        assert!(matches!(&line[0], Line::Query(q) if q.expected == ExpectedResults::Failure));
+        let line = parse_lines(r#"?= f(x), 2;"#);
+        assert!(matches!(&line[0], Line::Query(q) if q.expected == ExpectedResults::Count(2)));
+        let line = parse_lines(r#"?= f(x), [{x: 1}, {x: 2}];"#);
+        assert!(matches!(
+            &line[0],
+            Line::Query(q) if q.expected == ExpectedResults::Results(vec![
+                maplit::btreemap! {sym!("x") => term!(1)},
+                maplit::btreemap! {sym!("x") => term!(2)},
+            ])
+        ));
+        assert!(matches!(
+            super::parse_lines(0, r#"?= f(x), "one";"#),
+            Err(error::PolarError {
+                kind: error::ErrorKind::Parse(error::ParseError::InvalidExpectation { .. }),
+                ..
+            })
+        ));
     }
 
     #[test]
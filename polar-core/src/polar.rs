@@ -1,24 +1,36 @@
-use super::error::PolarResult;
+use super::coverage::*;
+use super::error::{PolarError, PolarResult, RuntimeError};
 use super::events::*;
 use super::kb::*;
 use super::messages::*;
 use super::parser;
+use super::profiler::*;
+use super::recording::*;
 use super::rewrites::*;
 use super::rules::*;
 use super::runnable::Runnable;
 use super::sources::*;
 use super::terms::*;
+use super::traces::*;
 use super::vm::*;
-use super::warnings::check_singletons;
+use super::warnings::{check_calls, check_singletons, check_unreachable};
 
 use std::collections::{HashMap, HashSet};
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Mutex, RwLock};
 
 pub struct Query {
     runnable_stack: Vec<(Box<dyn Runnable>, u64)>, // Tuple of Runnable + call_id.
     vm: PolarVirtualMachine,
     term: Term,
     done: bool,
+    recording: Option<Recording>,
+    /// Aggregate profile to add this query's profile to when it is dropped.
+    profile_total: Option<Arc<Mutex<Option<Profile>>>>,
+    /// Aggregate coverage to add this query's coverage to when it is dropped.
+    coverage_total: Option<Arc<Mutex<Option<Coverage>>>>,
+    /// For inline queries with expected results, the expected results and
+    /// the results so far.
+    expected: Option<(ExpectedResults, Vec<Bindings>)>,
 }
 
 impl Query {
@@ -28,6 +40,10 @@ impl Query {
             vm,
             term,
             done: false,
+            recording: None,
+            profile_total: None,
+            coverage_total: None,
+            expected: None,
         }
     }
 
@@ -39,17 +55,34 @@ impl Query {
     /// 4. When Runnable B emits a Done event, pop Runnable B off the stack and return its result as
     ///    an answer to Runnable A.
     pub fn next_event(&mut self) -> PolarResult<QueryEvent> {
+        let event = if self.expected.is_some() {
+            self.next_checked_event()
+        } else {
+            self.run_next_event()
+        };
+        if let Some(recording) = self.recording.as_mut() {
+            recording.steps.push(match &event {
+                Ok(event) => Step::event(event),
+                Err(e) => Step::Error {
+                    message: e.to_string(),
+                },
+            });
+        }
+        event
+    }
+
+    fn run_next_event(&mut self) -> PolarResult<QueryEvent> {
         let counter = self.vm.id_counter();
         match self.top_runnable().run(counter)? {
             QueryEvent::Run { runnable, call_id } => {
                 self.push_runnable(runnable, call_id)?;
-                self.next_event()
+                self.run_next_event()
             }
             QueryEvent::Done { result } => {
                 if let Some((_, result_call_id)) = self.pop_runnable() {
                     self.top_runnable()
                         .external_question_result(result_call_id, result)?;
-                    self.next_event()
+                    self.run_next_event()
                 } else {
                     // VM is done.
                     assert!(self.runnable_stack.is_empty());
@@ -60,6 +93,36 @@ impl Query {
         }
     }
 
+    /// Collect the results of an inline query until it is done, then check
+    /// them. The query has a single result with no bindings if they are as
+    /// expected, and fails with an error located at the query if they aren't.
+    fn next_checked_event(&mut self) -> PolarResult<QueryEvent> {
+        loop {
+            match self.run_next_event()? {
+                QueryEvent::Result { bindings, .. } => {
+                    if let Some((_, results)) = self.expected.as_mut() {
+                        results.push(bindings);
+                    }
+                }
+                QueryEvent::Done { .. } => {
+                    let (expected, results) = self.expected.take().unwrap();
+                    return match expected.check(&results) {
+                        Ok(()) => Ok(QueryEvent::Result {
+                            bindings: Bindings::new(),
+                            trace: None,
+                        }),
+                        Err(msg) => {
+                            let error = PolarError::from(RuntimeError::InlineQueryFailed { msg });
+                            let source = self.vm.source(&self.term);
+                            Err(error.set_context(source.as_ref(), Some(&self.term)))
+                        }
+                    };
+                }
+                event => return Ok(event),
+            }
+        }
+    }
+
     fn top_runnable(&mut self) -> &mut (dyn Runnable) {
         self.runnable_stack
             .last_mut()
@@ -76,23 +139,91 @@ impl Query {
         self.runnable_stack.pop()
     }
 
+    fn record(&mut self, step: Step) {
+        if let Some(recording) = self.recording.as_mut() {
+            recording.steps.push(step);
+        }
+    }
+
     pub fn call_result(&mut self, call_id: u64, value: Option<Term>) -> PolarResult<()> {
+        self.record(Step::CallResult {
+            call_id,
+            value: value.clone(),
+        });
         self.top_runnable().external_call_result(call_id, value)
     }
 
     pub fn question_result(&mut self, call_id: u64, result: bool) -> PolarResult<()> {
+        self.record(Step::QuestionResult { call_id, result });
         self.top_runnable()
             .external_question_result(call_id, result)
     }
 
     pub fn application_error(&mut self, message: String) -> PolarResult<()> {
+        self.record(Step::ApplicationError {
+            message: message.clone(),
+        });
         self.top_runnable().external_error(message)
     }
 
     pub fn debug_command(&mut self, command: &str) -> PolarResult<()> {
+        self.record(Step::DebugCommand {
+            command: command.to_string(),
+        });
         self.vm.debug_command(command)
     }
 
+    /// Collect a [`Profile`] while running the query.
+    pub fn enable_profiling(&mut self) {
+        self.vm.profiler.get_or_insert_with(Profiler::default);
+    }
+
+    /// The profile of the query so far, if profiling is enabled.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.vm.profiler.as_ref().map(|profiler| profiler.profile())
+    }
+
+    /// Collect [`Coverage`] while running the query.
+    pub fn enable_coverage(&mut self) {
+        self.vm.coverage.get_or_insert_with(Coverage::default);
+    }
+
+    /// The coverage of the query so far, if coverage is enabled.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.vm.coverage.as_ref()
+    }
+
+    /// Export a trace of each result in the [`ExportedTrace`] format, and of
+    /// each failing branch if `failing_branches` is true. Exported traces are
+    /// returned by [`Query::take_traces`].
+    pub fn export_traces(&mut self, failing_branches: bool) {
+        self.vm.trace_export = Some(TraceExport {
+            failing_branches,
+            traces: vec![],
+        });
+    }
+
+    /// Return the traces exported since the last call, in the order they
+    /// were produced.
+    pub fn take_traces(&mut self) -> Vec<ExportedTrace> {
+        self.vm
+            .trace_export
+            .as_mut()
+            .map(|export| std::mem::take(&mut export.traces))
+            .unwrap_or_default()
+    }
+
+    /// Start recording events and host answers, see [`Recording`].
+    pub fn start_recording(&mut self) {
+        let constants = self.vm.kb.read().unwrap().constants.clone();
+        self.recording = Some(Recording::new(self.term.clone(), constants));
+    }
+
+    /// Stop recording and return what was recorded so far.
+    pub fn take_recording(&mut self) -> Option<Recording> {
+        self.recording.take()
+    }
+
     pub fn next_message(&self) -> Option<Message> {
         self.vm.messages.next()
     }
@@ -102,6 +233,21 @@ impl Query {
     }
 }
 
+impl Drop for Query {
+    fn drop(&mut self) {
+        if let (Some(total), Some(profile)) = (&self.profile_total, self.profile()) {
+            if let Some(total) = total.lock().unwrap().as_mut() {
+                total.merge(profile);
+            }
+        }
+        if let (Some(total), Some(coverage)) = (&self.coverage_total, self.coverage()) {
+            if let Some(total) = total.lock().unwrap().as_mut() {
+                total.merge(coverage);
+            }
+        }
+    }
+}
+
 // Query as an iterator returns `None` after the first time `Done` is seen
 impl Iterator for Query {
     type Item = PolarResult<QueryEvent>;
@@ -125,6 +271,15 @@ pub struct Polar {
     loaded_files: Arc<RwLock<HashSet<String>>>,
     /// Map from source code loaded to the filename it was loaded as
     loaded_content: Arc<RwLock<HashMap<String, String>>>,
+    /// Profile of all queries, if profiling is enabled
+    profile: Arc<Mutex<Option<Profile>>>,
+    /// Coverage of all queries, if coverage is enabled
+    coverage: Arc<Mutex<Option<Coverage>>>,
+    /// Timeout of all queries, if not the default
+    query_timeout: Arc<Mutex<Option<std::time::Duration>>>,
+    /// Rules and inline queries loaded since the last check
+    unchecked_rules: Arc<Mutex<Vec<Arc<Rule>>>>,
+    unchecked_queries: Arc<Mutex<Vec<Term>>>,
 }
 
 impl Default for Polar {
@@ -140,6 +295,28 @@ impl Polar {
             messages: MessageQueue::new(),
             loaded_content: Arc::new(RwLock::new(HashMap::new())), // file content -> file name
             loaded_files: Arc::new(RwLock::new(HashSet::new())),   // set of file names
+            profile: Arc::new(Mutex::new(None)),
+            coverage: Arc::new(Mutex::new(None)),
+            query_timeout: Arc::new(Mutex::new(None)),
+            unchecked_rules: Arc::new(Mutex::new(vec![])),
+            unchecked_queries: Arc::new(Mutex::new(vec![])),
+        }
+    }
+
+    /// A copy of this instance with its own copy of the knowledge base, so
+    /// rules loaded into the copy don't affect this instance. Messages,
+    /// profiles and coverage are shared.
+    pub fn fork(&self) -> Self {
+        Self {
+            kb: Arc::new(RwLock::new(self.kb.read().unwrap().clone())),
+            messages: self.messages.clone(),
+            loaded_content: Arc::new(RwLock::new(self.loaded_content.read().unwrap().clone())),
+            loaded_files: Arc::new(RwLock::new(self.loaded_files.read().unwrap().clone())),
+            profile: self.profile.clone(),
+            coverage: self.coverage.clone(),
+            query_timeout: self.query_timeout.clone(),
+            unchecked_rules: Arc::new(Mutex::new(vec![])),
+            unchecked_queries: Arc::new(Mutex::new(vec![])),
         }
     }
 
@@ -201,6 +378,8 @@ impl Polar {
         lines.reverse();
         kb.sources.add_source(source, src_id);
         let mut warnings = vec![];
+        let mut rules = vec![];
+        let mut queries = vec![];
         while let Some(line) = lines.pop() {
             match line {
                 parser::Line::Rule(mut rule) => {
@@ -213,21 +392,46 @@ impl Polar {
                         .rules
                         .entry(name.clone())
                         .or_insert_with(|| GenericRule::new(name, vec![]));
-                    generic_rule.add_rule(Arc::new(rule));
+                    let rule = Arc::new(rule);
+                    generic_rule.add_rule(rule.clone());
+                    rules.push(rule);
                 }
-                parser::Line::Query(term) => {
-                    kb.inline_queries.push(term);
+                parser::Line::Query(query) => {
+                    queries.push(query.term.clone());
+                    kb.inline_queries.push(query);
                 }
             }
         }
-        self.messages.extend(warnings.iter().map(|m| Message {
-            kind: MessageKind::Warning,
-            msg: m.to_owned(),
-        }));
+        self.messages.extend(warnings);
+        // Calls are checked once all the rules they may refer to are loaded.
+        self.unchecked_rules.lock().unwrap().extend(rules);
+        self.unchecked_queries.lock().unwrap().extend(queries);
 
         Ok(())
     }
 
+    /// Warn about calls to undefined rules, calls with the wrong number of
+    /// arguments and unreachable rules, in the rules and inline queries loaded
+    /// since the last check.
+    ///
+    /// Hosts call this after loading all of their policies, so that rules can
+    /// call rules defined in any of them. Otherwise, the next query checks
+    /// them.
+    pub fn check(&self) {
+        let rules = std::mem::take(&mut *self.unchecked_rules.lock().unwrap());
+        let queries = std::mem::take(&mut *self.unchecked_queries.lock().unwrap());
+        let kb = self.kb.read().unwrap();
+        let mut warnings = vec![];
+        for rule in &rules {
+            warnings.append(&mut check_unreachable(rule, &kb));
+            warnings.append(&mut check_calls(&rule.body, &kb));
+        }
+        for query in &queries {
+            warnings.append(&mut check_calls(query, &kb));
+        }
+        self.messages.extend(warnings);
+    }
+
     // Used in integration tests
     pub fn load_str(&self, src: &str) -> PolarResult<()> {
         self.load(src, None)
@@ -239,16 +443,31 @@ impl Polar {
         kb.rules.clear();
         kb.sources = Sources::default();
         kb.inline_queries.clear();
+        self.unchecked_rules.lock().unwrap().clear();
+        self.unchecked_queries.lock().unwrap().clear();
         self.loaded_content.write().unwrap().clear();
         self.loaded_files.write().unwrap().clear();
     }
 
+    /// The next inline query to check. Its events are those of the query,
+    /// except for inline queries with expected results other than success:
+    /// those have one result if the expectation holds, and fail with an error
+    /// otherwise.
     pub fn next_inline_query(&self, trace: bool) -> Option<Query> {
-        let term = { self.kb.write().unwrap().inline_queries.pop() };
-        term.map(|t| self.new_query_from_term(t, trace))
+        let inline_query = { self.kb.write().unwrap().inline_queries.pop() };
+        inline_query.map(|InlineQuery { term, expected }| {
+            // Inline queries run as their policy is loaded, possibly before
+            // the policies they call into, so they don't check the rules.
+            let mut query = self.query_from_term(term, trace);
+            if expected != ExpectedResults::Success {
+                query.expected = Some((expected, vec![]));
+            }
+            query
+        })
     }
 
     pub fn new_query(&self, src: &str, trace: bool) -> PolarResult<Query> {
+        self.check();
         let source = Source {
             filename: None,
             src: src.to_owned(),
@@ -265,10 +484,16 @@ impl Polar {
         let query = Goal::Query { term: term.clone() };
         let vm =
             PolarVirtualMachine::new(self.kb.clone(), trace, vec![query], self.messages.clone());
-        Ok(Query::new(vm, term))
+        Ok(self.make_query(vm, term))
     }
 
-    pub fn new_query_from_term(&self, mut term: Term, trace: bool) -> Query {
+    pub fn new_query_from_term(&self, term: Term, trace: bool) -> Query {
+        self.check();
+        self.query_from_term(term, trace)
+    }
+
+    /// Make a query without checking the rules loaded since the last check.
+    fn query_from_term(&self, mut term: Term, trace: bool) -> Query {
         {
             let mut kb = self.kb.write().unwrap();
             rewrite_term(&mut term, &mut kb);
@@ -276,7 +501,111 @@ impl Polar {
         let query = Goal::Query { term: term.clone() };
         let vm =
             PolarVirtualMachine::new(self.kb.clone(), trace, vec![query], self.messages.clone());
-        Query::new(vm, term)
+        self.make_query(vm, term)
+    }
+
+    /// Replay a recorded query against the rules loaded into this instance.
+    ///
+    /// Constants from the recording that are not registered here are
+    /// registered first, so the query can refer to the application's classes.
+    /// They are registered on a copy of the knowledge base, so they don't
+    /// affect other queries.
+    pub fn replay(&self, recording: Recording) -> Replay {
+        let mut kb = self.kb.read().unwrap().clone();
+        for (name, value) in recording.constants.iter() {
+            if !kb.constants.contains_key(name) {
+                kb.constant(name.clone(), value.clone());
+            }
+        }
+        // The recorded query is already rewritten.
+        let query = Goal::Query {
+            term: recording.query.clone(),
+        };
+        let vm = PolarVirtualMachine::new(
+            Arc::new(RwLock::new(kb)),
+            false,
+            vec![query],
+            self.messages.clone(),
+        );
+        Replay::new(self.make_query(vm, recording.query.clone()), recording)
+    }
+
+    fn make_query(&self, mut vm: PolarVirtualMachine, term: Term) -> Query {
+        if let Some(timeout) = *self.query_timeout.lock().unwrap() {
+            vm.set_query_timeout(timeout);
+        }
+        let mut query = Query::new(vm, term);
+        if self.profile.lock().unwrap().is_some() {
+            query.enable_profiling();
+            query.profile_total = Some(self.profile.clone());
+        }
+        if self.coverage.lock().unwrap().is_some() {
+            query.enable_coverage();
+            query.coverage_total = Some(self.coverage.clone());
+        }
+        query
+    }
+
+    /// Set the time queries made from now on may run for before they fail
+    /// with a timeout error. Defaults to 30 seconds.
+    pub fn set_query_timeout(&self, timeout: std::time::Duration) {
+        *self.query_timeout.lock().unwrap() = Some(timeout);
+    }
+
+    /// The names of the loaded rules, sorted.
+    pub fn rule_names(&self) -> Vec<String> {
+        let kb = self.kb.read().unwrap();
+        let mut names: Vec<String> = kb.rules.keys().map(|name| name.0.clone()).collect();
+        names.sort();
+        names
+    }
+
+    /// The source of each definition of the rule `name`, in the order they
+    /// were loaded.
+    pub fn rule_sources(&self, name: &str) -> Vec<String> {
+        let rules: Vec<Arc<Rule>> =
+            match self.kb.read().unwrap().rules.get(&Symbol(name.to_string())) {
+                Some(generic_rule) => generic_rule.rules().cloned().collect(),
+                None => return vec![],
+            };
+        let vm = PolarVirtualMachine::new(self.kb.clone(), false, vec![], self.messages.clone());
+        rules.iter().map(|rule| vm.rule_source(rule)).collect()
+    }
+
+    /// Profile all queries made from now on, and add up their profiles.
+    /// Disabling profiling discards the aggregate profile.
+    pub fn enable_profiling(&self, enabled: bool) {
+        let mut profile = self.profile.lock().unwrap();
+        match (enabled, profile.is_some()) {
+            (true, false) => *profile = Some(Profile::default()),
+            (false, _) => *profile = None,
+            _ => {}
+        }
+    }
+
+    /// The aggregate profile of the queries dropped since profiling
+    /// was enabled.
+    pub fn profile(&self) -> Option<Profile> {
+        self.profile.lock().unwrap().clone()
+    }
+
+    /// Collect coverage for all queries made from now on.
+    /// Disabling coverage discards what was collected.
+    pub fn enable_coverage(&self, enabled: bool) {
+        let mut coverage = self.coverage.lock().unwrap();
+        match (enabled, coverage.is_some()) {
+            (true, false) => *coverage = Some(Coverage::default()),
+            (false, _) => *coverage = None,
+            _ => {}
+        }
+    }
+
+    /// Report the coverage of the loaded rules by the queries finished
+    /// since coverage was enabled.
+    pub fn coverage_report(&self) -> Option<CoverageReport> {
+        let coverage = self.coverage.lock().unwrap();
+        let kb = self.kb.read().unwrap();
+        coverage.as_ref().map(|coverage| coverage.report(&kb))
     }
 
     // @TODO: Direct load_rules endpoint.
@@ -289,6 +618,11 @@ impl Polar {
         self.kb.write().unwrap().constant(name, value)
     }
 
+    /// Whether a constant, such as a class, is registered under `name`.
+    pub fn is_constant(&self, name: &Symbol) -> bool {
+        self.kb.read().unwrap().is_constant(name)
+    }
+
     pub fn next_message(&self) -> Option<Message> {
         self.messages.next()
     }
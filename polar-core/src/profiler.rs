@@ -0,0 +1,195 @@
+//! Per-rule profiling of query execution.
+//!
+//! Rule statistics follow the "box model" of Prolog debuggers: every call
+//! of a rule either succeeds, possibly several times, or fails once
+//! backtracking abandons it without another solution. Time is "self time":
+//! the wall-clock time spent on goals while the rule is the innermost call
+//! being evaluated, so time spent in rules it calls is not counted twice.
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+
+use serde::Serialize;
+
+use super::terms::*;
+
+/// Statistics for the rules with one name.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct RuleStats {
+    /// Number of times the rule was queried.
+    pub calls: u64,
+    /// Number of solutions found for the rule.
+    pub successes: u64,
+    /// Number of times a call of the rule was abandoned by backtracking.
+    pub failures: u64,
+    /// Number of times the VM backtracked while evaluating the rule.
+    pub backtracks: u64,
+    /// Time spent evaluating the rule, excluding the rules it calls.
+    pub time: Duration,
+}
+
+/// Statistics for calls to external attributes and methods with one name.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct ExternalStats {
+    /// Number of calls made to the application.
+    pub calls: u64,
+    /// Number of results returned by the application.
+    pub results: u64,
+    /// Time spent waiting for the application.
+    pub time: Duration,
+}
+
+/// Profile of one or more queries.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Profile {
+    pub rules: BTreeMap<String, RuleStats>,
+    pub externals: BTreeMap<String, ExternalStats>,
+}
+
+impl Profile {
+    /// Add the statistics from `other` to this profile.
+    pub fn merge(&mut self, other: &Profile) {
+        for (name, stats) in &other.rules {
+            let total = self.rules.entry(name.clone()).or_default();
+            total.calls += stats.calls;
+            total.successes += stats.successes;
+            total.failures += stats.failures;
+            total.backtracks += stats.backtracks;
+            total.time += stats.time;
+        }
+        for (name, stats) in &other.externals {
+            let total = self.externals.entry(name.clone()).or_default();
+            total.calls += stats.calls;
+            total.results += stats.results;
+            total.time += stats.time;
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+type Timestamp = std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+type Timestamp = f64;
+
+#[cfg(not(target_arch = "wasm32"))]
+fn now() -> Timestamp {
+    std::time::Instant::now()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn now() -> Timestamp {
+    js_sys::Date::now()
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn elapsed(start: Timestamp) -> Duration {
+    start.elapsed()
+}
+
+#[cfg(target_arch = "wasm32")]
+fn elapsed(start: Timestamp) -> Duration {
+    Duration::from_secs_f64((js_sys::Date::now() - start).max(0.0) / 1_000.0)
+}
+
+/// Name of the innermost rule call in a query stack.
+fn innermost_call(queries: &[Term]) -> Option<&Symbol> {
+    queries.iter().rev().find_map(|query| match query.value() {
+        Value::Call(call) => Some(&call.name),
+        _ => None,
+    })
+}
+
+pub(crate) struct GoalTimer {
+    call: Option<Symbol>,
+    start: Timestamp,
+}
+
+/// Collects a [`Profile`] for a virtual machine.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Profiler {
+    profile: Profile,
+    /// Start of the pending external calls, by call id.
+    externals: HashMap<u64, (Symbol, Timestamp)>,
+}
+
+impl Profiler {
+    pub(crate) fn profile(&self) -> &Profile {
+        &self.profile
+    }
+
+    fn rule(&mut self, name: &Symbol) -> &mut RuleStats {
+        self.profile.rules.entry(name.0.clone()).or_default()
+    }
+
+    pub(crate) fn call(&mut self, name: &Symbol) {
+        self.rule(name).calls += 1;
+    }
+
+    pub(crate) fn success(&mut self, query: &Term) {
+        if let Value::Call(call) = query.value() {
+            self.rule(&call.name).successes += 1;
+        }
+    }
+
+    /// Record a backtrack from the query stack `before` to `after`.
+    ///
+    /// Calls in `before` that are not in `after` are abandoned, and fail.
+    pub(crate) fn backtrack(&mut self, before: &[Term], after: &[Term]) {
+        if let Some(name) = innermost_call(before) {
+            let name = name.clone();
+            self.rule(&name).backtracks += 1;
+        }
+        let common = before
+            .iter()
+            .zip(after.iter())
+            .take_while(|(left, right)| left == right)
+            .count();
+        for query in &before[common..] {
+            if let Value::Call(call) = query.value() {
+                self.rule(&call.name).failures += 1;
+            }
+        }
+    }
+
+    /// Start timing a goal, which is attributed to the innermost call in `queries`.
+    pub(crate) fn start_goal(&self, queries: &[Term]) -> GoalTimer {
+        GoalTimer {
+            call: innermost_call(queries).cloned(),
+            start: now(),
+        }
+    }
+
+    pub(crate) fn end_goal(&mut self, timer: GoalTimer) {
+        if let Some(name) = timer.call {
+            self.rule(&name).time += elapsed(timer.start);
+        }
+    }
+
+    /// Record an external call. The same call id is used to poll for
+    /// each result, and only counts as one call.
+    pub(crate) fn external_call(&mut self, call_id: u64, attribute: &Symbol) {
+        if !self.externals.contains_key(&call_id) {
+            self.profile
+                .externals
+                .entry(attribute.0.clone())
+                .or_default()
+                .calls += 1;
+        }
+        self.externals.insert(call_id, (attribute.clone(), now()));
+    }
+
+    pub(crate) fn external_result(&mut self, call_id: u64, has_result: bool) {
+        let pending = if has_result {
+            self.externals.get(&call_id).cloned()
+        } else {
+            self.externals.remove(&call_id)
+        };
+        if let Some((attribute, start)) = pending {
+            let stats = self.profile.externals.entry(attribute.0).or_default();
+            stats.time += elapsed(start);
+            if has_result {
+                stats.results += 1;
+            }
+        }
+    }
+}
@@ -0,0 +1,266 @@
+//! Record query executions and replay them without the host.
+//!
+//! A [`Recording`] captures every event a query emits and every answer the
+//! host sends back (`call_result`, `question_result`, `application_error`
+//! and `debug_command`), together with the query term and the registered
+//! constants. Replaying it against a `Polar` instance with the same policy
+//! loaded answers the host questions from the recording, so the application's
+//! classes are not needed to reproduce a decision.
+
+use std::collections::{HashMap, VecDeque};
+
+use serde::{Deserialize, Serialize};
+
+use super::error::{self, PolarResult};
+use super::events::QueryEvent;
+use super::kb::Bindings;
+use super::polar::Query;
+use super::terms::*;
+
+/// Version of the recording format, bumped on incompatible changes.
+pub const RECORDING_VERSION: u32 = 1;
+
+/// One step of a query execution.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum Step {
+    /// An event returned by the query.
+    Event(QueryEvent),
+    /// An error returned by the query instead of an event.
+    Error {
+        message: String,
+    },
+    CallResult {
+        call_id: u64,
+        value: Option<Term>,
+    },
+    QuestionResult {
+        call_id: u64,
+        result: bool,
+    },
+    ApplicationError {
+        message: String,
+    },
+    DebugCommand {
+        command: String,
+    },
+}
+
+impl Step {
+    /// Record a copy of `event`.
+    pub(crate) fn event(event: &QueryEvent) -> Self {
+        // `QueryEvent` isn't `Clone` because of `Run`, which is never
+        // returned from a query, so copy it through its serialized form.
+        let event = serde_json::to_value(event)
+            .and_then(serde_json::from_value)
+            .expect("query events can be serialized");
+        Step::Event(event)
+    }
+}
+
+/// A recorded query execution.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Recording {
+    pub version: u32,
+    /// The query, after rewriting.
+    pub query: Term,
+    /// Constants registered when the query was made, e.g. classes.
+    pub constants: Bindings,
+    pub steps: Vec<Step>,
+}
+
+impl Recording {
+    pub fn new(query: Term, constants: Bindings) -> Self {
+        Self {
+            version: RECORDING_VERSION,
+            query,
+            constants,
+            steps: vec![],
+        }
+    }
+
+    pub fn to_json(&self) -> PolarResult<String> {
+        serde_json::to_string(self)
+            .map_err(|e| error::RuntimeError::Serialization { msg: e.to_string() }.into())
+    }
+
+    pub fn from_json(json: &str) -> PolarResult<Self> {
+        let recording: Self = serde_json::from_str(json).map_err(|e| {
+            error::PolarError::from(error::RuntimeError::Serialization { msg: e.to_string() })
+        })?;
+        if recording.version != RECORDING_VERSION {
+            return Err(error::RuntimeError::Serialization {
+                msg: format!(
+                    "unsupported recording version {}, expected {}",
+                    recording.version, RECORDING_VERSION
+                ),
+            }
+            .into());
+        }
+        Ok(recording)
+    }
+}
+
+/// Summary of an event used to check that a replay follows the recording.
+///
+/// Call ids, instance ids and generated variable names may differ between
+/// runs, so only the kind of event and the names it refers to are compared.
+fn event_summary(event: &QueryEvent) -> String {
+    match event {
+        QueryEvent::ExternalCall { attribute, .. } => format!("ExternalCall({})", attribute.0),
+        QueryEvent::ExternalIsa { class_tag, .. } => format!("ExternalIsa({})", class_tag.0),
+        QueryEvent::ExternalIsSubSpecializer {
+            left_class_tag,
+            right_class_tag,
+            ..
+        } => format!(
+            "ExternalIsSubSpecializer({}, {})",
+            left_class_tag.0, right_class_tag.0
+        ),
+        QueryEvent::ExternalIsSubclass {
+            left_class_tag,
+            right_class_tag,
+            ..
+        } => format!(
+            "ExternalIsSubclass({}, {})",
+            left_class_tag.0, right_class_tag.0
+        ),
+        QueryEvent::ExternalOp { operator, .. } => format!("ExternalOp({:?})", operator),
+        QueryEvent::Done { result } => format!("Done({})", result),
+        QueryEvent::None => "None".to_string(),
+        QueryEvent::Run { .. } => "Run".to_string(),
+        QueryEvent::Debug { .. } => "Debug".to_string(),
+        QueryEvent::MakeExternal { .. } => "MakeExternal".to_string(),
+        QueryEvent::ExternalUnify { .. } => "ExternalUnify".to_string(),
+        QueryEvent::Result { .. } => "Result".to_string(),
+    }
+}
+
+fn event_call_id(event: &QueryEvent) -> Option<u64> {
+    match event {
+        QueryEvent::ExternalCall { call_id, .. }
+        | QueryEvent::ExternalIsa { call_id, .. }
+        | QueryEvent::ExternalIsSubSpecializer { call_id, .. }
+        | QueryEvent::ExternalIsSubclass { call_id, .. }
+        | QueryEvent::ExternalUnify { call_id, .. }
+        | QueryEvent::ExternalOp { call_id, .. } => Some(*call_id),
+        _ => None,
+    }
+}
+
+/// Replays a [`Recording`], answering host questions from it.
+///
+/// Iterating over a replay returns the same events as the recorded query,
+/// and an error if the execution diverges from the recording, e.g. because
+/// the policy changed.
+pub struct Replay {
+    query: Query,
+    steps: VecDeque<Step>,
+    /// Number of steps replayed so far, for error messages.
+    position: usize,
+    /// Map from recorded call ids to the call ids of the replay.
+    call_ids: HashMap<u64, u64>,
+    done: bool,
+}
+
+impl Replay {
+    pub fn new(query: Query, recording: Recording) -> Self {
+        Self {
+            query,
+            steps: recording.steps.into(),
+            position: 0,
+            call_ids: HashMap::new(),
+            done: false,
+        }
+    }
+
+    fn next_step(&mut self) -> Option<Step> {
+        self.position += 1;
+        self.steps.pop_front()
+    }
+
+    fn diverged(&mut self, expected: &str, actual: &str) -> error::PolarError {
+        self.done = true;
+        error::OperationalError::InvalidState(format!(
+            "replay diverged from the recording at step {}: expected {}, got {}",
+            self.position, expected, actual
+        ))
+        .into()
+    }
+
+    fn call_id(&self, recorded: u64) -> u64 {
+        *self.call_ids.get(&recorded).unwrap_or(&recorded)
+    }
+
+    /// Send the recorded host answers up to the next event.
+    fn send_answers(&mut self) -> PolarResult<()> {
+        while let Some(Step::CallResult { .. })
+        | Some(Step::QuestionResult { .. })
+        | Some(Step::ApplicationError { .. })
+        | Some(Step::DebugCommand { .. }) = self.steps.front()
+        {
+            match self.next_step() {
+                Some(Step::CallResult { call_id, value }) => {
+                    let call_id = self.call_id(call_id);
+                    self.query.call_result(call_id, value)?
+                }
+                Some(Step::QuestionResult { call_id, result }) => {
+                    let call_id = self.call_id(call_id);
+                    self.query.question_result(call_id, result)?
+                }
+                Some(Step::ApplicationError { message }) => {
+                    self.query.application_error(message)?
+                }
+                Some(Step::DebugCommand { command }) => self.query.debug_command(&command)?,
+                _ => unreachable!(),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn next_event(&mut self) -> PolarResult<QueryEvent> {
+        self.send_answers()?;
+        let actual = self.query.next_event();
+        let actual_summary = match &actual {
+            Ok(event) => event_summary(event),
+            Err(e) => format!("error: {}", e),
+        };
+        match (self.next_step(), &actual) {
+            (Some(Step::Event(expected)), Ok(event)) => {
+                let expected_summary = event_summary(&expected);
+                if expected_summary != actual_summary {
+                    return Err(self.diverged(&expected_summary, &actual_summary));
+                }
+                if let (Some(recorded), Some(call_id)) =
+                    (event_call_id(&expected), event_call_id(event))
+                {
+                    self.call_ids.insert(recorded, call_id);
+                }
+            }
+            (Some(Step::Error { .. }), Err(_)) => {}
+            (Some(Step::Event(expected)), Err(_)) => {
+                return Err(self.diverged(&event_summary(&expected), &actual_summary))
+            }
+            (Some(Step::Error { message }), Ok(_)) => {
+                return Err(self.diverged(&format!("error: {}", message), &actual_summary))
+            }
+            (Some(_), _) => unreachable!("host answers are sent before the query runs"),
+            (None, _) => return Err(self.diverged("the end of the recording", &actual_summary)),
+        }
+        actual
+    }
+}
+
+impl Iterator for Replay {
+    type Item = PolarResult<QueryEvent>;
+
+    fn next(&mut self) -> Option<PolarResult<QueryEvent>> {
+        if self.done {
+            return None;
+        }
+        let event = self.next_event();
+        if let Ok(QueryEvent::Done { .. }) = event {
+            self.done = true;
+        }
+        Some(event)
+    }
+}
@@ -144,6 +144,13 @@ impl GenericRule {
         self.index.index_rule(rule_id, &rule.params[..], 0);
     }
 
+    /// All rules with this name, in the order they were added.
+    pub fn rules(&self) -> impl Iterator<Item = &Arc<Rule>> {
+        let mut ids: Vec<&u64> = self.rules.keys().collect();
+        ids.sort();
+        ids.into_iter().map(move |id| &self.rules[id])
+    }
+
     #[allow(clippy::ptr_arg)]
     pub fn get_applicable_rules(&self, args: &TermList) -> Rules {
         self.index
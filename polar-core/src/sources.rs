@@ -35,6 +35,7 @@ pub struct Source {
     pub src: String,
 }
 
+#[derive(Clone)]
 pub struct Sources {
     /// Map from term ID to `Source`.
     sources: HashMap<u64, Source>,
@@ -1,6 +1,11 @@
+use super::error::{self, PolarResult};
+use super::formatting::ToPolarString;
+use super::lexer::byte_loc_to_pos;
 use super::rules::*;
 use super::terms::*;
+use super::vm::PolarVirtualMachine;
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
 use std::rc::Rc;
 use std::sync::Arc;
 
@@ -31,3 +36,154 @@ pub struct TraceResult {
     pub trace: Rc<Trace>,
     pub formatted: String,
 }
+
+/// Version of the exported trace format, bumped on incompatible changes.
+pub const TRACE_FORMAT_VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeKind {
+    Rule,
+    Term,
+}
+
+/// Where a rule or term was defined. Lines and columns start at 1.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SourceLocation {
+    pub filename: Option<String>,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// A node of an [`ExportedTrace`].
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExportedNode {
+    pub kind: NodeKind,
+    /// Source of the rule or term, as written in the policy if available.
+    pub source: String,
+    pub location: Option<SourceLocation>,
+    /// Values of the variables in a term, by variable name, in Polar syntax.
+    /// Variables of rules are renamed when the rule is applied, so names may
+    /// carry a suffix, e.g. `_x_12`. For a rule, the values of its parameters
+    /// by the names in the rule. Unbound variables are left out.
+    pub bindings: BTreeMap<String, String>,
+    /// False for the rules and terms that were being evaluated when a
+    /// branch failed.
+    pub passed: bool,
+    pub children: Vec<ExportedNode>,
+}
+
+/// A trace in a stable, serializable format, for use outside the library.
+///
+/// A trace is exported for each result of a query, and, if requested, for
+/// each branch of the query that failed, at the point it failed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ExportedTrace {
+    pub version: u32,
+    /// True for the trace of a result, false for a failing branch.
+    pub passed: bool,
+    pub root: ExportedNode,
+}
+
+impl ExportedTrace {
+    pub fn to_json(&self) -> PolarResult<String> {
+        serde_json::to_string(self)
+            .map_err(|e| error::RuntimeError::Serialization { msg: e.to_string() }.into())
+    }
+
+    pub fn from_json(json: &str) -> PolarResult<Self> {
+        serde_json::from_str(json)
+            .map_err(|e| error::RuntimeError::Serialization { msg: e.to_string() }.into())
+    }
+}
+
+/// Traces exported by a virtual machine.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct TraceExport {
+    pub failing_branches: bool,
+    pub traces: Vec<ExportedTrace>,
+}
+
+impl Trace {
+    /// Export this trace and its children, marking them all as passed.
+    pub(crate) fn export(&self, vm: &PolarVirtualMachine) -> ExportedNode {
+        self.export_under(None, vm)
+    }
+
+    /// Export this trace as a child of `parent`. A rule is a child of the
+    /// call it was applied to, which gives the values of its parameters.
+    pub(crate) fn export_under(
+        &self,
+        parent: Option<&Trace>,
+        vm: &PolarVirtualMachine,
+    ) -> ExportedNode {
+        let (kind, source, term, bindings) = match &self.node {
+            Node::Rule(rule) => {
+                // Rules start at their first parameter, or their body.
+                let start = rule
+                    .params
+                    .first()
+                    .map(|param| &param.parameter)
+                    .unwrap_or(&rule.body);
+                let call = parent.and_then(|parent| parent.term());
+                let args: &[Term] = match call.as_ref().map(|term| term.value()) {
+                    Some(Value::Call(call)) => &call.args,
+                    _ => &[],
+                };
+                let bindings = rule
+                    .params
+                    .iter()
+                    .zip(args)
+                    .filter_map(|(param, arg)| match param.parameter.value() {
+                        Value::Variable(var) if !var.0.starts_with('_') => {
+                            binding(vm, arg).map(|value| (var.0.clone(), value))
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                (NodeKind::Rule, vm.rule_source(rule), start, bindings)
+            }
+            Node::Term(term) => {
+                let mut variables = HashSet::new();
+                term.variables(&mut variables);
+                let bindings = variables
+                    .into_iter()
+                    .filter_map(|var| {
+                        let value = binding(vm, &Term::new_temporary(Value::Variable(var.clone())));
+                        value.map(|value| (var.0, value))
+                    })
+                    .collect();
+                (NodeKind::Term, vm.term_source(term, false), term, bindings)
+            }
+        };
+        let location = vm.source(term).map(|source| {
+            let (row, column) = byte_loc_to_pos(&source.src, term.offset());
+            SourceLocation {
+                filename: source.filename,
+                line: row + 1,
+                column: column + 1,
+            }
+        });
+        ExportedNode {
+            kind,
+            source,
+            location,
+            bindings,
+            passed: true,
+            children: self
+                .children
+                .iter()
+                .map(|c| c.export_under(Some(self), vm))
+                .collect(),
+        }
+    }
+}
+
+/// The value of `term` in Polar syntax, unless it is an unbound variable.
+fn binding(vm: &PolarVirtualMachine, term: &Term) -> Option<String> {
+    let value = vm.deep_deref(term);
+    match value.value() {
+        Value::Variable(_) | Value::RestVariable(_) => None,
+        _ => Some(value.to_polar()),
+    }
+}
@@ -5,6 +5,7 @@ use std::rc::Rc;
 use std::string::ToString;
 use std::sync::{Arc, RwLock};
 
+use super::coverage::Coverage;
 use super::debugger::{DebugEvent, Debugger};
 use super::error::{self, PolarResult};
 use super::events::*;
@@ -13,6 +14,7 @@ use super::kb::*;
 use super::lexer::loc_to_pos;
 use super::messages::*;
 use super::numerics::*;
+use super::profiler::Profiler;
 use super::rules::*;
 use super::sources::*;
 use super::terms::*;
@@ -137,6 +139,12 @@ pub type Choices = Vec<Choice>;
 pub type Goals = Vec<Goal>;
 pub type TraceStack = Vec<Rc<Vec<Rc<Trace>>>>;
 
+/// Whether two trace snapshots share the same nodes, without comparing the
+/// contents of the nodes.
+fn same_rcs<T>(a: &[Rc<T>], b: &[Rc<T>]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).all(|(a, b)| Rc::ptr_eq(a, b))
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct GoalStack(Vec<Rc<Goal>>);
 
@@ -194,6 +202,15 @@ pub struct PolarVirtualMachine {
     /// Interactive debugger.
     pub debugger: Debugger,
 
+    /// Profiler, if profiling is enabled.
+    pub(crate) profiler: Option<Profiler>,
+
+    /// Coverage, if coverage collection is enabled.
+    pub(crate) coverage: Option<Coverage>,
+
+    /// Exported traces, if trace export is enabled.
+    pub(crate) trace_export: Option<TraceExport>,
+
     /// Rules and types.
     pub kb: Arc<RwLock<KnowledgeBase>>,
 
@@ -250,6 +267,9 @@ impl PolarVirtualMachine {
             trace: vec![],
             external_error: None,
             debugger: Debugger::default(),
+            profiler: None,
+            coverage: None,
+            trace_export: None,
             kb,
             call_id_symbols: HashMap::new(),
             log: std::env::var("RUST_LOG").is_ok(),
@@ -270,9 +290,14 @@ impl PolarVirtualMachine {
         self.stack_limit = limit;
     }
 
-    #[cfg(test)]
-    fn set_query_timeout(&mut self, timeout_s: u64) {
-        self.query_timeout = std::time::Duration::from_secs(timeout_s);
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_query_timeout(&mut self, timeout: std::time::Duration) {
+        self.query_timeout = timeout;
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub fn set_query_timeout(&mut self, timeout: std::time::Duration) {
+        self.query_timeout = timeout.as_secs_f64() * 1_000.0;
     }
 
     pub fn new_id(&self) -> u64 {
@@ -305,7 +330,13 @@ impl PolarVirtualMachine {
         self.check_timeout()?;
 
         match goal.as_ref() {
-            Goal::Backtrack => self.backtrack()?,
+            Goal::Backtrack => {
+                if matches!(&self.trace_export, Some(export) if export.failing_branches) {
+                    self.backtrack_and_export()?
+                } else {
+                    self.backtrack()?
+                }
+            }
             Goal::Cut { choice_index } => self.cut(*choice_index),
             Goal::Debug { message } => return Ok(self.debug(&message)),
             Goal::Halt => return Ok(self.halt()),
@@ -376,6 +407,9 @@ impl PolarVirtualMachine {
                         },
                         &[],
                     );
+                    if let Some(coverage) = &mut self.coverage {
+                        coverage.rule_evaluated(rule);
+                    }
                 }
                 self.trace.push(trace.clone());
             }
@@ -807,10 +841,16 @@ impl PolarVirtualMachine {
             self.print("⇒ backtrack");
         }
         self.log("BACKTRACK", &[]);
+        let queries_before = self.profiler.as_ref().map(|_| self.queries.clone());
 
         loop {
             match self.choices.pop() {
-                None => return self.push_goal(Goal::Halt),
+                None => {
+                    if let (Some(profiler), Some(before)) = (&mut self.profiler, queries_before) {
+                        profiler.backtrack(&before, &[]);
+                    }
+                    return self.push_goal(Goal::Halt);
+                }
                 Some(Choice {
                     mut alternatives,
                     bsp,
@@ -846,9 +886,80 @@ impl PolarVirtualMachine {
                 }
             }
         }
+        if let (Some(profiler), Some(before)) = (&mut self.profiler, queries_before) {
+            profiler.backtrack(&before, &self.queries);
+        }
         Ok(())
     }
 
+    fn push_exported_trace(&mut self, passed: bool, root: ExportedNode) {
+        if let Some(export) = &mut self.trace_export {
+            export.traces.push(ExportedTrace {
+                version: TRACE_FORMAT_VERSION,
+                passed,
+                root,
+            });
+        }
+    }
+
+    /// Backtrack, and export the trace of the failing branch if backtracking
+    /// abandons part of the trace tree. Backtracking used for control flow,
+    /// e.g. when checking which rules apply, leaves the trace tree as it was.
+    fn backtrack_and_export(&mut self) -> PolarResult<()> {
+        // Backtracking resumes from the last choice with alternatives left.
+        let abandons_trace = match self
+            .choices
+            .iter()
+            .rev()
+            .find(|choice| !choice.alternatives.is_empty())
+        {
+            None => true,
+            Some(choice) => {
+                !same_rcs(&choice.trace, &self.trace)
+                    || !same_rcs(&choice.trace_stack, &self.trace_stack)
+            }
+        };
+        if abandons_trace {
+            if let Some(root) = self.failing_trace() {
+                self.push_exported_trace(false, root);
+            }
+        }
+        self.backtrack()
+    }
+
+    /// Export the trace tree of the branch that is failing. The rules and
+    /// terms still being evaluated are marked as failed.
+    fn failing_trace(&self) -> Option<ExportedNode> {
+        let levels: Vec<&[Rc<Trace>]> = self
+            .trace_stack
+            .iter()
+            .map(|traces| traces.as_slice())
+            .chain(std::iter::once(self.trace.as_slice()))
+            .collect();
+        let mut children = vec![];
+        for (depth, traces) in levels.iter().enumerate().rev() {
+            let parent = match depth {
+                0 => None,
+                _ => levels[depth - 1].last().map(|trace| trace.as_ref()),
+            };
+            let mut level: Vec<ExportedNode> =
+                traces.iter().map(|t| t.export_under(parent, self)).collect();
+            if depth + 1 == levels.len() {
+                // The innermost node failed unless it was already done.
+                if let (Some(node), Some(trace)) = (level.last_mut(), traces.last()) {
+                    if let Node::Term(term) = &trace.node {
+                        node.passed = self.queries.last() != Some(term);
+                    }
+                }
+            } else if let Some(node) = level.last_mut() {
+                node.passed = false;
+                node.children.append(&mut children);
+            }
+            children = level;
+        }
+        children.into_iter().next()
+    }
+
     /// Commit to the current choice.
     fn cut(&mut self, index: usize) {
         let _ = self.choices.truncate(index);
@@ -856,7 +967,13 @@ impl PolarVirtualMachine {
 
     /// Clean up the query stack after completing a query.
     fn pop_query(&mut self) {
-        self.queries.pop();
+        let query = self.queries.pop();
+        if let (Some(profiler), Some(query)) = (&mut self.profiler, &query) {
+            profiler.success(query);
+        }
+        if let (Some(coverage), Some(query)) = (&mut self.coverage, &query) {
+            coverage.term_succeeded(query);
+        }
     }
 
     /// Interact with the debugger.
@@ -1144,6 +1261,10 @@ impl PolarVirtualMachine {
             }
         };
 
+        if let Some(profiler) = &mut self.profiler {
+            profiler.external_call(call_id, &field_name);
+        }
+
         self.push_choice(vec![vec![Goal::LookupExternal {
             call_id,
             instance: instance.clone(),
@@ -1274,6 +1395,10 @@ impl PolarVirtualMachine {
             }
         };
 
+        if let Some(coverage) = &mut self.coverage {
+            coverage.term_evaluated(term);
+        }
+
         self.queries.push(term.clone());
         self.push_goal(Goal::PopQuery { term: term.clone() })?;
         self.trace.push(Rc::new(Trace {
@@ -1301,6 +1426,9 @@ impl PolarVirtualMachine {
     /// Create a choice over the applicable rules.
     fn query_for_predicate(&mut self, predicate: Call) -> PolarResult<()> {
         assert!(predicate.kwargs.is_none());
+        if let Some(profiler) = &mut self.profiler {
+            profiler.call(&predicate.name);
+        }
         let goals = match self.kb.read().unwrap().rules.get(&predicate.name) {
             None => vec![Goal::Backtrack],
             Some(generic_rule) => {
@@ -1683,12 +1811,15 @@ impl PolarVirtualMachine {
                     ));
                 }
             }
-            (_, _) => {
-                return Err(self.set_error_context(
+            (left, right) => {
+                return Err(self.type_error(
                     term,
-                    error::RuntimeError::Unsupported {
-                        msg: "numeric operation only supported on numbers".to_string(),
-                    },
+                    format!(
+                        "{} expects numbers, got: {}, {}",
+                        op.to_polar(),
+                        left.to_polar(),
+                        right.to_polar()
+                    ),
                 ))
             }
         }
@@ -2528,7 +2659,12 @@ impl Runnable for PolarVirtualMachine {
         }
 
         while let Some(goal) = self.goals.pop() {
-            match self.next(goal.clone())? {
+            let timer = self.profiler.as_ref().map(|p| p.start_goal(&self.queries));
+            let event = self.next(goal.clone());
+            if let (Some(profiler), Some(timer)) = (&mut self.profiler, timer) {
+                profiler.end_goal(timer);
+            }
+            match event? {
                 QueryEvent::None => (),
                 event => {
                     self.external_error = None;
@@ -2557,6 +2693,12 @@ impl Runnable for PolarVirtualMachine {
             None
         };
 
+        if self.trace_export.is_some() {
+            if let Some(root) = self.trace.first().map(|trace| trace.export(self)) {
+                self.push_exported_trace(true, root);
+            }
+        }
+
         let bindings = partial::simplify_bindings(self.bindings(true));
 
         Ok(QueryEvent::Result { bindings, trace })
@@ -2579,6 +2721,10 @@ impl Runnable for PolarVirtualMachine {
         // TODO: Open question if we need to pass errors back down to rust.
         // For example what happens if the call asked for a field that doesn't exist?
 
+        if let Some(profiler) = &mut self.profiler {
+            profiler.external_result(call_id, term.is_some());
+        }
+
         if let Some(value) = term {
             self.log_with(|| format!("=> {}", value.to_string()), &[]);
 
@@ -3429,7 +3575,7 @@ mod tests {
     #[test]
     fn test_timeout() {
         let mut vm = PolarVirtualMachine::default();
-        vm.set_query_timeout(1);
+        vm.set_query_timeout(std::time::Duration::from_secs(1));
         // Turn this off so we don't hit it.
         vm.set_stack_limit(std::usize::MAX);
 
@@ -1,9 +1,11 @@
+use super::diagnostic::*;
 use super::formatting::source_lines;
 use super::kb::*;
+use super::messages::Message;
 use super::rules::*;
 use super::terms::*;
 
-use std::collections::{hash_map::Entry, HashMap};
+use std::collections::{hash_map::Entry, HashMap, HashSet};
 
 fn common_misspellings(t: &str) -> Option<String> {
     let misspelled_type = match t {
@@ -40,9 +42,24 @@ fn common_misspellings(t: &str) -> Option<String> {
     Some(misspelled_type.to_owned())
 }
 
+/// Make a warning about `term`, showing the source line it is on.
+fn warning(code: &str, msg: String, term: &Term, kb: &KnowledgeBase) -> Message {
+    let diagnostic = Diagnostic::new(code, Severity::Warning, msg.clone());
+    match term
+        .get_source_id()
+        .and_then(|id| kb.sources.get_source(id))
+    {
+        Some(ref source) => Message::warning(
+            format!("{}\n{}", msg, source_lines(source, term.offset(), 0)),
+            diagnostic.with_term(source, term),
+        ),
+        None => Message::warning(msg, diagnostic),
+    }
+}
+
 /// Warn about singleton variables and unknown specializers in a rule,
 /// except those whose names start with `_`.
-pub fn check_singletons(rule: &Rule, kb: &KnowledgeBase) -> Vec<String> {
+pub fn check_singletons(rule: &Rule, kb: &KnowledgeBase) -> Vec<Message> {
     let mut warnings = vec![];
     let mut singletons = HashMap::<Symbol, Option<Term>>::new();
     let mut check_term = |term: &Term| {
@@ -78,23 +95,203 @@ pub fn check_singletons(rule: &Rule, kb: &KnowledgeBase) -> Vec<String> {
     singletons.sort_by_key(|(_sym, term)| term.as_ref().map_or(0, |term| term.offset()));
     for (sym, singleton) in singletons {
         if let Some(term) = singleton {
-            let mut msg = if let Value::Pattern(..) = term.value() {
+            let (code, msg) = if let Value::Pattern(..) = term.value() {
                 let mut msg = format!("Unknown specializer {}", sym);
                 if let Some(t) = common_misspellings(&sym.0) {
                     msg.push_str(&format!(", did you mean {}?", t));
                 }
-                msg
+                (UNKNOWN_SPECIALIZER, msg)
             } else {
-                format!("Singleton variable {} is unused or undefined, see <https://docs.osohq.com/using/polar-syntax.html#variables>", sym)
+                (SINGLETON_VARIABLE, format!("Singleton variable {} is unused or undefined, see <https://docs.osohq.com/using/polar-syntax.html#variables>", sym))
             };
-            if let Some(ref source) = term
-                .get_source_id()
-                .and_then(|id| kb.sources.get_source(id))
-            {
-                msg = format!("{}\n{}", msg, source_lines(source, term.offset(), 0));
+            warnings.push(warning(code, msg, &term, kb))
+        }
+    }
+    warnings
+}
+
+/// Number of single-character edits needed to turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut previous = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous + if ca == *cb { 0 } else { 1 };
+            previous = row[j + 1];
+            row[j + 1] = substitution.min(previous + 1).min(row[j] + 1);
+        }
+    }
+    row[b.len()]
+}
+
+/// The name of a defined rule that `name` is likely a misspelling of.
+fn similar_rule_name(name: &Symbol, kb: &KnowledgeBase) -> Option<Symbol> {
+    let length = name.0.chars().count();
+    // Any two short names are a letter or two apart.
+    if length < 3 {
+        return None;
+    }
+    let max_distance = (length / 3).max(1);
+    kb.rules
+        .keys()
+        .map(|other| (edit_distance(&name.0, &other.0), other))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min()
+        .map(|(_, other)| other.clone())
+}
+
+fn plural(n: usize, word: &str) -> String {
+    if n == 1 {
+        format!("{} {}", n, word)
+    } else {
+        format!("{} {}s", n, word)
+    }
+}
+
+/// Collect the rule calls made by a query, i.e. calls that are queried
+/// rather than used as values.
+fn predicate_calls<'a>(term: &'a Term, calls: &mut Vec<&'a Term>) {
+    match term.value() {
+        Value::Call(_) => calls.push(term),
+        Value::Expression(Operation {
+            operator: Operator::And,
+            args,
+        })
+        | Value::Expression(Operation {
+            operator: Operator::Or,
+            args,
+        })
+        | Value::Expression(Operation {
+            operator: Operator::Not,
+            args,
+        })
+        | Value::Expression(Operation {
+            operator: Operator::ForAll,
+            args,
+        }) => {
+            for arg in args {
+                predicate_calls(arg, calls);
             }
-            warnings.push(msg)
         }
+        _ => {}
+    }
+}
+
+/// Warn about calls in `query` to rules that are not defined, or not
+/// defined with the number of arguments they are called with.
+///
+/// Only rules that are already loaded count as defined, so this should be
+/// checked after all the policies have been loaded.
+pub fn check_calls(query: &Term, kb: &KnowledgeBase) -> Vec<Message> {
+    let mut calls = vec![];
+    predicate_calls(query, &mut calls);
+
+    let mut warnings = vec![];
+    for term in calls {
+        let call = match term.value() {
+            Value::Call(call) => call,
+            _ => continue,
+        };
+        let (code, msg) = match kb.rules.get(&call.name) {
+            None => {
+                let mut msg = format!("Unknown rule {}", call.name);
+                if let Some(name) = similar_rule_name(&call.name, kb) {
+                    msg.push_str(&format!(", did you mean {}?", name));
+                }
+                (UNKNOWN_RULE, msg)
+            }
+            Some(generic_rule) => {
+                let mut arities: Vec<usize> =
+                    generic_rule.rules().map(|rule| rule.params.len()).collect();
+                arities.sort_unstable();
+                arities.dedup();
+                if arities.contains(&call.args.len()) {
+                    continue;
+                }
+                let defined = match &arities[..] {
+                    [arity] => plural(*arity, "argument"),
+                    _ => {
+                        let arities: Vec<String> = arities.iter().map(|n| n.to_string()).collect();
+                        format!("{} arguments", arities.join(" or "))
+                    }
+                };
+                let msg = format!(
+                    "Rule {} is called with {}, but is only defined with {}",
+                    call.name,
+                    plural(call.args.len(), "argument"),
+                    defined
+                );
+                (WRONG_ARITY, msg)
+            }
+        };
+        warnings.push(warning(code, msg, term, kb));
     }
     warnings
 }
+
+/// True if `earlier` is tried before `later` and applies to any arguments
+/// `later` applies to.
+fn subsumes(earlier: &Rule, later: &Rule) -> bool {
+    if earlier.params.len() != later.params.len() {
+        return false;
+    }
+    let mut variables = HashSet::new();
+    earlier
+        .params
+        .iter()
+        .zip(later.params.iter())
+        .all(|(e, l)| {
+            // Rules are sorted by their specializers, and rules with the same
+            // specializers are tried in the order they were defined.
+            e.specializer == l.specializer
+                && match e.parameter.value() {
+                    Value::Variable(name) => variables.insert(name.clone()),
+                    value => value == l.parameter.value(),
+                }
+        })
+}
+
+fn starts_with_cut(rule: &Rule) -> bool {
+    let first = match rule.body.value() {
+        Value::Expression(Operation {
+            operator: Operator::And,
+            args,
+        }) => args.first(),
+        _ => Some(&rule.body),
+    };
+    matches!(
+        first.map(|term| term.value()),
+        Some(Value::Expression(Operation {
+            operator: Operator::Cut,
+            ..
+        }))
+    )
+}
+
+/// Warn if `rule` can never be applied, because an earlier rule with the
+/// same name is always tried first and cuts the remaining rules.
+pub fn check_unreachable(rule: &Rule, kb: &KnowledgeBase) -> Vec<Message> {
+    let generic_rule = match kb.rules.get(&rule.name) {
+        Some(generic_rule) => generic_rule,
+        None => return vec![],
+    };
+    let shadowed = generic_rule
+        .rules()
+        .take_while(|earlier| !std::ptr::eq(earlier.as_ref(), rule))
+        .any(|earlier| starts_with_cut(earlier) && subsumes(earlier, rule));
+    if !shadowed {
+        return vec![];
+    }
+    let msg = format!(
+        "Rule {} is unreachable, because an earlier rule {} always applies first and cuts the rules after it",
+        rule.name, rule.name
+    );
+    let start = rule
+        .params
+        .first()
+        .map(|param| &param.parameter)
+        .unwrap_or(&rule.body);
+    vec![warning(UNREACHABLE_RULE, msg, start, kb)]
+}
@@ -768,6 +768,43 @@ fn test_load_str_with_query() {
     }
 }
 
+#[test]
+fn test_inline_query_expectations() {
+    let polar = Polar::new();
+    let src = r#"f(1); f(2);
+                 ?= f(3), false;
+                 ?= f(x), 2;
+                 ?= f(x), [{x: 1}, {x: 2}];
+                 ?= f(_), true;"#;
+    polar.load_str(src).unwrap();
+    while let Some(query) = polar.next_inline_query(false) {
+        assert!(!query_results!(query).is_empty());
+    }
+
+    let failures = [
+        ("?= f(1), false;", "expected no results, got 1"),
+        ("?= f(x), 3;", "expected 3 result(s), got 2"),
+        (
+            "?= f(x), [{x: 2}, {x: 1}];",
+            "expected results [{x: 2}, {x: 1}], got [{x: 1}, {x: 2}]",
+        ),
+    ];
+    for (query, msg) in failures.iter() {
+        let polar = Polar::new();
+        polar.load_str(&format!("f(1); f(2);\n{}", query)).unwrap();
+        let mut query = polar.next_inline_query(false).unwrap();
+        let error = query.next_event().unwrap_err();
+        assert_eq!(error.code(), "E0110");
+        assert_eq!(
+            error.to_string(),
+            format!("Inline query failed: {} at line 2, column 4", msg)
+        );
+    }
+
+    let error = Polar::new().load_str("?= f(1), \"yes\";").unwrap_err();
+    assert_eq!(error.code(), "E0009");
+}
+
 /// Test using a constructor with positional + kwargs.
 #[test]
 fn test_make_external() {
@@ -1017,6 +1054,14 @@ fn test_arithmetic() {
     assert!(qnull(&mut polar, "1/0 < 0"));
     assert!(qeval(&mut polar, "1/0 > 0"));
     assert!(qeval(&mut polar, "1/0 > 1e100"));
+
+    let mut query = polar.new_query(r#"x = "a" + 1"#, false).unwrap();
+    let e = query.next_event().unwrap_err();
+    assert!(matches!(
+        e.kind,
+        ErrorKind::Runtime(RuntimeError::TypeError { msg, .. })
+            if msg == r#"+ expects numbers, got: "a", 1"#
+    ));
 }
 
 #[test]
@@ -1181,6 +1226,378 @@ fn test_debug() {
     let _results = query_results!(query, no_results, no_externals, debug_handler);
 }
 
+#[test]
+fn test_debug_breakpoints() {
+    let source = indoc!(
+        r#"
+        a(x) if b(x) and c(x);
+        b(x) if x > 0;
+        c(x) if x < 10;"#
+    );
+
+    let polar = Polar::new();
+    polar
+        .load(source, Some("policies/test.polar".to_string()))
+        .unwrap();
+
+    let mut call_num = 0;
+    let debug_handler = |s: &str| {
+        let rt = match call_num {
+            0 => {
+                assert_eq!(s, "Set breakpoint 1: rule c");
+                "break test.polar:2"
+            }
+            1 => {
+                assert_eq!(s, "Set breakpoint 2: test.polar:2");
+                "breakpoints"
+            }
+            2 => {
+                assert_eq!(s, "1: rule c\n2: test.polar:2");
+                "continue"
+            }
+            3 => {
+                assert_eq!(s.lines().next().unwrap(), "Breakpoint 2: test.polar:2");
+                assert_eq!(
+                    s.lines().nth(1).unwrap(),
+                    "QUERY: _x_4 > 0, BINDINGS: {_x_4 = 1}"
+                );
+                "delete 2"
+            }
+            4 => {
+                assert_eq!(s, "Deleted breakpoint 2.");
+                "continue"
+            }
+            5 => {
+                assert_eq!(s.lines().next().unwrap(), "Breakpoint 1: rule c");
+                assert_eq!(
+                    s.lines().nth(1).unwrap(),
+                    "QUERY: c(_x_2), BINDINGS: {_x_2 = 1}"
+                );
+                "continue"
+            }
+            _ => panic!("Too many calls: {}", s),
+        };
+        call_num += 1;
+        rt.to_string()
+    };
+
+    let mut query = polar.new_query("x = 1 and a(x)", false).unwrap();
+    query.debug_command("break c").unwrap();
+    let results = query_results!(query, no_results, no_externals, debug_handler);
+    assert_eq!(results.len(), 1);
+
+    // Conditional breakpoints only break when the condition succeeds.
+    let mut hits = vec![];
+    let debug_handler = |s: &str| {
+        hits.push(s.lines().next().unwrap().to_string());
+        "continue".to_string()
+    };
+    let mut query = polar.new_query("a(1) and a(2) and a(3)", false).unwrap();
+    query.debug_command("break b if x = 2").unwrap();
+    let _results = query_results!(query, no_results, no_externals, debug_handler);
+    assert_eq!(
+        hits,
+        vec![
+            "Set breakpoint 1: rule b if x = 2",
+            "Breakpoint 1: rule b if x = 2"
+        ]
+    );
+
+    // Lines are found from byte offsets, so earlier lines can have
+    // non-ASCII text.
+    let polar = Polar::new();
+    polar
+        .load(
+            "d(x) if x = \"ééééééééééééééééééééé\";\ne(x) if d(x);",
+            Some("test.polar".to_string()),
+        )
+        .unwrap();
+    let mut hits = vec![];
+    let debug_handler = |s: &str| {
+        hits.push(s.lines().next().unwrap().to_string());
+        "continue".to_string()
+    };
+    let mut query = polar.new_query("e(1)", false).unwrap();
+    query.debug_command("break test.polar:2").unwrap();
+    let _results = query_results!(query, no_results, no_externals, debug_handler);
+    assert_eq!(
+        hits,
+        vec![
+            "Set breakpoint 1: test.polar:2",
+            "Breakpoint 1: test.polar:2"
+        ]
+    );
+}
+
+#[test]
+fn test_debug_watch_and_bindings() {
+    let polar = Polar::new();
+    polar
+        .load_str("a(x) if debug() and y = x + 1 and b(y);\nb(z) if z = 2;")
+        .unwrap();
+
+    let mut call_num = 0;
+    let debug_handler = |s: &str| {
+        let rt = match call_num {
+            0 => {
+                assert_eq!(s.lines().next().unwrap(), "QUERY: debug(), BINDINGS: {}");
+                "var x y"
+            }
+            1 => {
+                assert_eq!(s, "x = 1\ny = <unbound>");
+                "watch y"
+            }
+            2 => {
+                assert_eq!(s, "Watching y.");
+                "continue"
+            }
+            3 => {
+                assert_eq!(s.lines().next().unwrap(), "Watch: y = 2");
+                "step"
+            }
+            4 => {
+                assert_eq!(
+                    s.lines().next().unwrap(),
+                    "QUERY: b(_y_7), BINDINGS: {_y_7 = 2}"
+                );
+                "bindings"
+            }
+            5 => {
+                assert_eq!(s, "_y_7 = 2");
+                "unwatch"
+            }
+            6 => {
+                assert_eq!(s, "Updated watched variables.");
+                "continue"
+            }
+            _ => panic!("Too many calls: {}", s),
+        };
+        call_num += 1;
+        rt.to_string()
+    };
+
+    let query = polar.new_query("a(1)", false).unwrap();
+    let results = query_results!(query, no_results, no_externals, debug_handler);
+    assert_eq!(results.len(), 1);
+}
+
+#[test]
+fn test_profiling() {
+    let polar = Polar::new();
+    polar
+        .load_str(indoc!(
+            r#"
+            f(x) if g(x) and h(x);
+            g(1);
+            g(2);
+            g(3);
+            h(x) if x > 1;
+            "#
+        ))
+        .unwrap();
+
+    let mut query = polar.new_query("f(x)", false).unwrap();
+    query.enable_profiling();
+    let mut results = 0;
+    loop {
+        match query.next_event().unwrap() {
+            QueryEvent::Done { .. } => break,
+            QueryEvent::Result { .. } => results += 1,
+            _ => {}
+        }
+    }
+    assert_eq!(results, 2);
+    let profile = query.profile().unwrap();
+    let counts = |name: &str| {
+        let stats = &profile.rules[name];
+        (stats.calls, stats.successes, stats.failures)
+    };
+    assert_eq!(counts("f"), (1, 2, 0));
+    assert_eq!(counts("g"), (1, 3, 0));
+    // h(1) fails.
+    assert_eq!(counts("h"), (3, 2, 1));
+    assert!(profile.rules["h"].backtracks > 0);
+
+    // Profiles of dropped queries are added up.
+    assert!(polar.profile().is_none());
+    polar.enable_profiling(true);
+    assert_eq!(query_results!(polar.new_query("f(x)", false).unwrap()).len(), 2);
+    assert_eq!(query_results!(polar.new_query("g(1)", false).unwrap()).len(), 1);
+    let total = polar.profile().unwrap();
+    assert_eq!(total.rules["f"].calls, 1);
+    assert_eq!(total.rules["g"].calls, 2);
+    assert_eq!(total.rules["g"].successes, 4);
+    polar.enable_profiling(false);
+    assert!(polar.profile().is_none());
+}
+
+#[test]
+fn test_coverage() {
+    let polar = Polar::new();
+    polar
+        .load(
+            indoc!(
+                r#"
+                f(x) if g(x) and h(x);
+                g(1);
+                g(2);
+                h(x) if x > 1;
+                unused(x) if x = 1;
+                "#
+            ),
+            Some("coverage.polar".to_string()),
+        )
+        .unwrap();
+
+    assert!(polar.coverage_report().is_none());
+    polar.enable_coverage(true);
+    assert_eq!(query_results!(polar.new_query("f(x)", false).unwrap()).len(), 1);
+
+    let report = polar.coverage_report().unwrap();
+    assert_eq!(report.files.len(), 1);
+    let file = &report.files[0];
+    assert_eq!(file.filename.as_deref(), Some("coverage.polar"));
+    let rules: Vec<_> = file
+        .rules
+        .iter()
+        .map(|rule| {
+            (
+                rule.name.as_str(),
+                rule.line,
+                rule.hits.evaluated,
+                rule.hits.succeeded,
+            )
+        })
+        .collect();
+    assert_eq!(
+        rules,
+        vec![
+            ("f", 1, 1, 1),
+            ("g", 2, 1, 1),
+            ("g", 3, 1, 1),
+            ("h", 4, 2, 1),
+            ("unused", 5, 0, 0),
+        ]
+    );
+    let conditions: Vec<_> = file.rules[0]
+        .conditions
+        .iter()
+        .map(|c| (c.source.as_str(), c.hits.evaluated, c.hits.succeeded))
+        .collect();
+    assert_eq!(conditions, vec![("g(x)", 1, 2), ("h(x)", 2, 1)]);
+    assert_eq!(file.rules[3].conditions[0].source, "x > 1");
+    assert_eq!(file.rules[3].conditions[0].hits.evaluated, 2);
+
+    let lcov = report.to_lcov();
+    assert!(lcov.starts_with("TN:\nSF:coverage.polar\n"));
+    assert!(lcov.contains("FNDA:2,h:4\n"));
+    assert!(lcov.contains("FNDA:0,unused:5\n"));
+    assert!(lcov.contains("FNF:5\nFNH:4\n"));
+    assert!(lcov.contains("DA:5,0\n"));
+    assert!(lcov.contains("LF:5\nLH:4\n"));
+    assert!(lcov.ends_with("end_of_record\n"));
+    assert!(report.to_json().unwrap().contains("\"filename\": \"coverage.polar\""));
+
+    polar.enable_coverage(false);
+    assert!(polar.coverage_report().is_none());
+
+    // Positions are found from byte offsets in sources with non-ASCII text.
+    let polar = Polar::new();
+    polar
+        .load(
+            "# ééééééééééééééééééééé\nk(x) if x = \"é\";",
+            Some("unicode.polar".to_string()),
+        )
+        .unwrap();
+    polar.enable_coverage(true);
+    assert_eq!(query_results!(polar.new_query("k(x)", false).unwrap()).len(), 1);
+    let report = polar.coverage_report().unwrap();
+    let rule = &report.files[0].rules[0];
+    assert_eq!((rule.line, rule.column), (2, 3));
+    let condition = &rule.conditions[0];
+    assert_eq!(condition.source, "x = \"é\"");
+    assert_eq!((condition.line, condition.column), (2, 9));
+}
+
+#[test]
+fn test_trace_export() {
+    let polar = Polar::new();
+    polar
+        .load(
+            indoc!(
+                r#"
+                f(x) if g(x) and x > 1;
+                g(1);
+                g(2);
+                "#
+            ),
+            Some("trace.polar".to_string()),
+        )
+        .unwrap();
+
+    let mut query = polar.new_query("f(x)", false).unwrap();
+    query.export_traces(true);
+    let mut results = 0;
+    loop {
+        match query.next_event().unwrap() {
+            QueryEvent::Done { .. } => break,
+            QueryEvent::Result { .. } => results += 1,
+            _ => {}
+        }
+    }
+    assert_eq!(results, 1);
+
+    let traces = query.take_traces();
+    assert!(query.take_traces().is_empty());
+    let passed: Vec<bool> = traces.iter().map(|t| t.passed).collect();
+    // x > 1 fails for g(1) before f(2) succeeds, then backtracking finds no
+    // other rule for g.
+    assert_eq!(passed, vec![false, true]);
+
+    let failed = &traces[0].root;
+    assert_eq!(failed.kind, NodeKind::Term);
+    assert_eq!(failed.source, "f(x)");
+    assert!(!failed.passed);
+    let rule = &failed.children[0];
+    assert_eq!(rule.kind, NodeKind::Rule);
+    assert_eq!(rule.source, "f(x) if g(x) and x > 1;");
+    let location = rule.location.as_ref().unwrap();
+    assert_eq!(location.filename.as_deref(), Some("trace.polar"));
+    assert_eq!((location.line, location.column), (1, 3));
+    assert!(!rule.passed);
+    assert_eq!(rule.bindings["x"], "1");
+    let body = &rule.children[0];
+    let (call, comparison) = (&body.children[0], &body.children[1]);
+    assert_eq!((call.source.as_str(), call.passed), ("g(x)", true));
+    assert_eq!(
+        (comparison.source.as_str(), comparison.passed),
+        ("x > 1", false)
+    );
+    assert_eq!(comparison.bindings.values().collect::<Vec<_>>(), vec!["1"]);
+
+    let result = &traces[1];
+    assert_eq!(result.version, TRACE_FORMAT_VERSION);
+    assert_eq!(result.root.bindings["x"], "2");
+    assert_eq!(result.root.children[0].bindings["x"], "2");
+    let json = result.to_json().unwrap();
+    assert_eq!(&ExportedTrace::from_json(&json).unwrap(), result);
+
+    // Locations are found from byte offsets in sources with non-ASCII text.
+    let polar = Polar::new();
+    polar
+        .load(
+            "# ééééééééééééééééééééé\nk(x) if x = \"é\";",
+            Some("unicode.polar".to_string()),
+        )
+        .unwrap();
+    let mut query = polar.new_query("k(x)", false).unwrap();
+    query.export_traces(true);
+    while !matches!(query.next_event().unwrap(), QueryEvent::Done { .. }) {}
+    let traces = query.take_traces();
+    let location = traces[0].root.children[0].location.as_ref().unwrap();
+    assert_eq!((location.line, location.column), (2, 3));
+}
+
 #[test]
 fn test_anonymous_vars() {
     let mut polar = Polar::new();
@@ -1239,6 +1656,185 @@ fn test_unknown_specializer_suggestions() {
     );
 }
 
+#[test]
+fn test_undefined_rule_warnings() {
+    let polar = Polar::new();
+    polar
+        .load_str(indoc!(
+            r#"
+            allowed(actor) if is_admin(actor);
+            is_admn(actor) if actor = "alice";
+            f(x) if g(x, 1) or not g(x);
+            g(x) if x = 1;
+            g(x, y, _z) if x = y;
+            h(x) if x.is_admin() and x = is_admin(1);"#
+        ))
+        .unwrap();
+    assert!(polar.next_message().is_none());
+    polar.check();
+    let mut warnings = vec![];
+    while let Some(msg) = polar.next_message() {
+        assert!(matches!(&msg.kind, MessageKind::Warning));
+        warnings.push(msg.msg);
+    }
+    assert_eq!(
+        warnings,
+        vec![
+            "Unknown rule is_admin, did you mean is_admn?\n001: allowed(actor) if is_admin(actor);\n                       ^",
+            "Rule g is called with 2 arguments, but is only defined with 1 or 3 arguments\n003: f(x) if g(x, 1) or not g(x);\n             ^",
+        ]
+    );
+
+    // Rules from policies loaded later define calls made earlier.
+    polar.load("a(x) if b(x);", Some("a.polar".to_string())).unwrap();
+    polar.load("b(_x);", Some("b.polar".to_string())).unwrap();
+    polar.check();
+    assert!(polar.next_message().is_none());
+    polar.load_str("?= undefined(1);").unwrap();
+    polar.check();
+    let msg = polar.next_message().unwrap();
+    assert!(msg.msg.starts_with("Unknown rule undefined\n"));
+
+    // The next query checks the rules loaded since the last check. Short
+    // names aren't suggested as misspellings of other short names.
+    polar.load_str("k(x) if j(x);").unwrap();
+    assert!(polar.next_message().is_none());
+    polar.new_query("k(1)", false).unwrap();
+    let msg = polar.next_message().unwrap();
+    assert!(msg.msg.starts_with("Unknown rule j\n"));
+    assert!(polar.next_message().is_none());
+}
+
+#[test]
+fn test_unreachable_rule_warnings() {
+    let polar = Polar::new();
+    polar
+        .load_str(indoc!(
+            r#"
+            f(x, 1) if cut and x = 1;
+            f(y, 1) if y = 2;
+            f(y, 2) if y = 2;
+            f(x: {a: 1}, 1) if x = 3;
+            g(x, x) if cut;
+            g(x, y) if x = y;
+            h(x) if x = 1 and cut;
+            h(x) if x = 2;"#
+        ))
+        .unwrap();
+    polar.check();
+    let msg = polar.next_message().unwrap();
+    assert!(matches!(&msg.kind, MessageKind::Warning));
+    assert_eq!(
+        msg.msg,
+        "Rule f is unreachable, because an earlier rule f always applies first and cuts the rules after it\n002: f(y, 1) if y = 2;\n       ^"
+    );
+    assert!(polar.next_message().is_none());
+}
+
+#[test]
+fn test_diagnostics() {
+    use polar_core::diagnostic::*;
+
+    let polar = Polar::new();
+    let e = polar
+        .load("f(x) if x = 1;\ng(x) if x = ;", Some("bad.polar".to_string()))
+        .unwrap_err();
+    let diagnostic = e.diagnostic();
+    assert_eq!(diagnostic.code, "E0005");
+    assert_eq!(diagnostic.severity, Severity::Error);
+    assert_eq!(diagnostic.message, "did not expect to find the token ';'");
+    assert_eq!(diagnostic.filename.as_deref(), Some("bad.polar"));
+    assert_eq!(
+        diagnostic.range,
+        Some(Range {
+            start: Position { line: 2, column: 13 },
+            end: Position { line: 2, column: 14 },
+        })
+    );
+    let json = serde_json::to_value(&e).unwrap();
+    assert_eq!(json["diagnostic"]["code"], "E0005");
+    assert_eq!(json["diagnostic"]["severity"], "error");
+    assert_eq!(json["diagnostic"]["range"]["start"]["line"], 2);
+
+    polar
+        .load("f(x) if y = 1;", Some("warn.polar".to_string()))
+        .unwrap();
+    let diagnostics: Vec<Diagnostic> = std::iter::from_fn(|| polar.next_message())
+        .map(|msg| msg.diagnostic.unwrap())
+        .collect();
+    let codes: Vec<&str> = diagnostics.iter().map(|d| d.code.as_str()).collect();
+    assert_eq!(codes, vec![SINGLETON_VARIABLE, SINGLETON_VARIABLE]);
+    assert!(diagnostics[1].message.starts_with("Singleton variable y"));
+    assert_eq!(diagnostics[1].severity, Severity::Warning);
+    assert_eq!(diagnostics[1].filename.as_deref(), Some("warn.polar"));
+    assert_eq!(
+        diagnostics[1].range,
+        Some(Range {
+            start: Position { line: 1, column: 9 },
+            end: Position { line: 1, column: 10 },
+        })
+    );
+
+    let diagnostic = polar.new_query("f(", false).err().unwrap().diagnostic();
+    assert_eq!(diagnostic.code, "E0004");
+    assert_eq!(diagnostic.filename, None);
+    assert_eq!(diagnostic.range.unwrap().start, Position { line: 1, column: 3 });
+    let mut query = polar.new_query("x = 1 + \"a\"", false).unwrap();
+    let e = query.next_event().unwrap_err();
+    let diagnostic = e.diagnostic();
+    assert_eq!(diagnostic.code, "E0104");
+    assert_eq!(
+        diagnostic.range,
+        Some(Range {
+            start: Position { line: 1, column: 5 },
+            end: Position { line: 1, column: 12 },
+        })
+    );
+
+    // Offsets are in bytes, positions in characters.
+    let e = polar
+        .load("# café\nh(x) if x = \"é\" and ;", None)
+        .unwrap_err();
+    assert_eq!(
+        e.diagnostic().range,
+        Some(Range {
+            start: Position { line: 2, column: 21 },
+            end: Position { line: 2, column: 22 },
+        })
+    );
+}
+
+#[test]
+fn test_parse_error_recovery() {
+    let mut polar = Polar::new();
+    let src = indoc!(
+        r#"
+        f(x) if x = ;
+        g(1);
+        h(x) if x = 1 +;
+        ?= g(1) 2;
+        i(x) if x"#
+    );
+    let e = polar.load(src, Some("typos.polar".to_string())).unwrap_err();
+    assert_eq!(e.errors().count(), 4);
+    assert_eq!(
+        e.to_string(),
+        [
+            "did not expect to find the token ';' at line 1, column 13 in file typos.polar",
+            "did not expect to find the token ';' at line 3, column 16 in file typos.polar",
+            "did not expect to find the token '2' at line 4, column 9 in file typos.polar",
+            "hit the end of the file unexpectedly. Did you forget a semi-colon at line 5, column 10 in file typos.polar",
+        ]
+        .join("\n")
+    );
+    let json = serde_json::to_value(&e).unwrap();
+    assert_eq!(json["additional"].as_array().unwrap().len(), 3);
+    assert_eq!(json["additional"][0]["range"]["start"]["line"], 3);
+
+    // Nothing from a file with errors is loaded.
+    assert!(qnull(&mut polar, "g(1)"));
+}
+
 #[test]
 fn test_rest_vars() {
     let mut polar = Polar::new();
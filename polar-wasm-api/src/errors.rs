@@ -32,9 +32,11 @@ fn kind(err: &PolarError) -> String {
         Parse(ExtraToken { .. }) => "ParseError::ExtraToken",
         Parse(ReservedWord { .. }) => "ParseError::ReservedWord",
         Parse(InvalidFloat { .. }) => "ParseError::InvalidFloat",
+        Parse(InvalidExpectation { .. }) => "ParseError::InvalidExpectation",
         Runtime(Application { .. }) => "RuntimeError::Application",
         Runtime(ArithmeticError { .. }) => "RuntimeError::ArithmeticError",
         Runtime(FileLoading { .. }) => "RuntimeError::FileLoading",
+        Runtime(InlineQueryFailed { .. }) => "RuntimeError::InlineQueryFailed",
         Runtime(QueryTimeout { .. }) => "RuntimeError::QueryTimeout",
         Runtime(Serialization { .. }) => "RuntimeError::Serialization",
         Runtime(StackOverflow { .. }) => "RuntimeError::StackOverflow",
@@ -63,6 +65,12 @@ impl From<Error> for js_sys::Error {
     fn from(err: Error) -> Self {
         let e = Self::new(&err.inner.formatted);
         e.set_name(&err.kind);
+        if let Ok(diagnostic) = serde_wasm_bindgen::to_value(&err.inner.diagnostic) {
+            let _ = js_sys::Reflect::set(&e, &JsValue::from_str("diagnostic"), &diagnostic);
+        }
+        if let Ok(additional) = serde_wasm_bindgen::to_value(&err.inner.additional) {
+            let _ = js_sys::Reflect::set(&e, &JsValue::from_str("additional"), &additional);
+        }
         e
     }
 }